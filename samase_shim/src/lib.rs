@@ -234,13 +234,14 @@ impl samase_plugin::save::File for BwFile {
 
 impl Drop for Context {
     fn drop(&mut self) {
-        let ctx = CONTEXT.take()
+        let mut ctx = CONTEXT.take()
             .expect("Missing context for samase shim???");
         if !ctx.unsupported_features.is_empty() {
         }
         let mut patcher = PATCHER.lock();
         let mut exe = patcher.patch_exe(0x00400000);
         unsafe {
+            add_command_chain_hooks(&mut ctx);
             for (hook, after) in ctx.step_objects {
                 exe.hook_closure(bw::StepObjects, move |orig| {
                     if after == 0 {
@@ -848,16 +849,19 @@ impl Drop for Context {
             }
             if ctx.save_extensions_used {
                 unsafe fn save_hook(file: *mut c_void) {
-                    // TODO ?
+                    // Errors have already been shown to the player, and the extension
+                    // data has been invalidated so that it won't be loaded.
                     let _ = samase_plugin::save::call_save_hooks(BwFile(file));
                 }
                 unsafe fn load_hook() {
                     if *bw::loaded_save != null_mut() {
                         let result =
                             samase_plugin::save::call_load_hooks(BwFile(*bw::loaded_save));
-                        if let Err(e) = result {
-                            // TODO not crashing
-                            panic!("{}", e);
+                        if result.is_err() {
+                            // The failure has been shown to the player; continue with
+                            // every plugin in its initial state instead of
+                            // a partially loaded one.
+                            samase_plugin::save::call_init_hooks();
                         }
                     }
                 }
@@ -2620,8 +2624,7 @@ unsafe extern "C" fn hook_ingame_command(
             commands::ingame_hook(data, len, replayed, &globals, orig);
         }
         hook_process_commands(ingame_hook);
-        let command_lengths = &(*bw::command_lengths)[..];
-        commands::set_default_command_lengths(command_lengths.into());
+        init_command_lengths();
     });
     commands::add_ingame_hook(cmd as u8, hook);
     if let Some(len) = len {
@@ -2630,10 +2633,64 @@ unsafe extern "C" fn hook_ingame_command(
     1
 }
 
+unsafe fn init_command_lengths() {
+    use samase_plugin::commands;
+
+    static COMMAND_LENGTHS: Once = Once::new();
+    COMMAND_LENGTHS.call_once(|| {
+        let command_lengths = &(*bw::command_lengths)[..];
+        commands::lengths::check(commands::lengths::Target::V1161, command_lengths);
+        commands::set_default_command_lengths(command_lengths.into());
+    });
+}
+
+/// Makes lobby and sent commands go through the `commands` chains.
+///
+/// The chain hooks are applied before the ones that plugins added with
+/// `hook_process_lobby_commands` / `hook_send_command`.
+/// They pass the buffers on unsplit while no `Lobby` / `Send` chain hooks are added.
+unsafe fn add_command_chain_hooks(ctx: &mut InternalContext) {
+    use samase_plugin::commands;
+
+    init_command_lengths();
+    commands::set_lobby_player(|| unsafe { (*bw::command_user, *bw::unique_command_user) });
+    // Storm id of the local player isn't known, commands sent by the local player
+    // use u32::MAX as the unique player.
+    commands::set_send_player(|| unsafe { (*bw::local_player_id, u32::MAX) });
+    ctx.process_lobby_commands.insert(0, commands::lobby_commands_hook);
+    ctx.send_command.insert(0, commands::send_command_hook);
+}
+
 unsafe extern "C" fn add_to_replay_data(data: *const u8, length: usize) {
-    // No code to support replay data for extended commands :l
+    use samase_plugin::commands::replay;
+
+    // Bw's replay code only knows commands below 0x5d, others are stored
+    // inside a select command.
     if *data.add(0) < 0x5d {
         bw::add_to_replay_data(*bw::replay_data, *bw::storm_command_user, data, length);
+    } else {
+        let cmd = slice::from_raw_parts(data, length);
+        match replay::wrap(cmd) {
+            Some(wrapped) => {
+                bw::add_to_replay_data(
+                    *bw::replay_data,
+                    *bw::storm_command_user,
+                    wrapped.as_ptr(),
+                    wrapped.len(),
+                );
+            }
+            None => {
+                // Commands longer than `replay::MAX_WRAPPED_LEN` can't be recorded,
+                // let the player know that the replay won't play back correctly.
+                let msg = format!(
+                    "Command {:x} of length {:x} is too long to be recorded to the replay",
+                    cmd[0], cmd.len(),
+                );
+                if let Ok(msg) = CString::new(msg) {
+                    bw::print_text(msg.as_ptr() as *const u8, 8, 0);
+                }
+            }
+        }
     }
 }
 
@@ -0,0 +1,391 @@
+//! Description of the plugin ABI: `PluginApi` field offsets, types and the version that
+//! added each field, and the discriminants of the enums that are passed to the host.
+//!
+//! `describe` renders all of it as text; `tests/abi.rs` compares that against
+//! `tests/abi_golden_32bit.txt` / `tests/abi_golden_64bit.txt`, depending on the target,
+//! so that any change other than appending fields / variants together with a `VERSION`
+//! bump fails the tests.
+
+use alloc::string::String;
+use core::fmt::Write;
+use core::mem::{offset_of, size_of};
+
+use core::ffi::c_void;
+
+use crate::{
+    CommandLength, ComplexLineParam, ComplexLineParamType, DebugUiDrawCb, DebugUiLog,
+    ExtendedArray, FfiStr, FuncId, IngameCommandHook, LoadHook, PluginApi, SaveHook, VarId,
+    MAX_FUNC_ID, MAX_VAR_ID, VERSION,
+};
+
+pub struct ApiField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    /// The field type as written in `PluginApi`, see `type_description`.
+    pub ty: &'static str,
+    /// First `PluginApi::version` which has this field.
+    ///
+    /// 0 for fields that were added before the versions were recorded here; they are
+    /// assumed to exist in every host.
+    pub since: u16,
+}
+
+macro_rules! since_or_zero {
+    () => { 0 };
+    ($since:expr) => { $since };
+}
+
+macro_rules! api_fields {
+    ($($(#[since($since:expr)])? $name:ident: $ty:ty,)*) => {
+        /// All `PluginApi` fields in declaration order.
+        ///
+        /// `PluginApi` is append-only, so when adding a field, add it here too, marked
+        /// with `#[since(VERSION)]` of the version that adds it. The types have to match
+        /// `PluginApi`, and every field has to be listed.
+        pub static API_FIELDS: &[ApiField] = &[
+            $(ApiField {
+                name: stringify!($name),
+                offset: offset_of!(PluginApi, $name),
+                size: size_of::<$ty>(),
+                ty: stringify!($ty),
+                since: since_or_zero!($($since)?),
+            },)*
+        ];
+
+        #[allow(dead_code)]
+        fn check_field_types(api: &PluginApi) {
+            let PluginApi { $($name,)* } = api;
+            $(let _: &$ty = $name;)*
+        }
+    };
+}
+
+api_fields! {
+    version: u16,
+    max_func_id: u16,
+    free_memory: unsafe extern "C" fn(*mut u8),
+    write_exe_memory: unsafe extern "C" fn(usize, *const u8, usize) -> u32,
+    warn_unsupported_feature: unsafe extern "C" fn(*const u8),
+    read_file: unsafe extern "C" fn() -> unsafe extern "C" fn(*const u8, *mut usize) -> *mut u8,
+    game: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    rng_seed: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> u32>,
+    hook_step_objects: unsafe extern "C" fn(unsafe extern "C" fn(), u32) -> u32,
+    hook_aiscript_opcode: unsafe extern "C" fn(u32, unsafe extern "C" fn(*mut c_void)) -> u32,
+    ai_regions: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    player_ai: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    get_region: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(u32, u32) -> u32>,
+    change_ai_region_state: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void, u32)>,
+    first_active_unit: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    first_hidden_unit: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    // self, order, x, y, target, fow_unit
+    issue_order: unsafe extern "C" fn() ->
+        Option<unsafe extern "C" fn(*mut c_void, u32, u32, u32, *mut c_void, u32)>,
+    print_text: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*const u8)>,
+    hook_on_first_file_access: unsafe extern "C" fn(unsafe extern "C" fn()),
+    hook_step_order:
+        unsafe extern "C" fn(unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void))) -> u32,
+    hook_step_order_hidden:
+        unsafe extern "C" fn(unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void))) -> u32,
+    dat: unsafe extern "C" fn(u32) -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    hook_process_commands: unsafe extern "C" fn(
+        unsafe extern "C" fn(*const c_void, u32, u32, unsafe extern "C" fn(*const c_void, u32, u32))
+    ) -> u32,
+    hook_process_lobby_commands: unsafe extern "C" fn(
+        unsafe extern "C" fn(*const c_void, u32, u32, unsafe extern "C" fn(*const c_void, u32, u32))
+    ) -> u32,
+    hook_send_command: unsafe extern "C" fn(
+        unsafe extern "C" fn(*mut c_void, u32, unsafe extern "C" fn(*mut c_void, u32))
+    ) -> u32,
+    hook_step_secondary_order:
+        unsafe extern "C" fn(unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void))) -> u32,
+    extend_save: unsafe extern "C" fn(*const u8, SaveHook, LoadHook, unsafe extern "C" fn()) -> u32,
+    hook_ingame_command:
+        unsafe extern "C" fn(u32, IngameCommandHook, Option<CommandLength>) -> u32,
+    units: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    selections: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    first_ai_script: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    hook_game_screen_rclick:
+        unsafe extern "C" fn(unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void))) -> u32,
+    client_selection: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    // type, id
+    dat_requirements: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(u32, u32) -> *const u16>,
+    first_guard_ai: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    pathing: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    set_first_ai_script: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void)>,
+    first_free_ai_script: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    set_first_free_ai_script: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void)>,
+    player_ai_towns: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    map_tile_flags: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut u32>,
+    players: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    hook_draw_image: unsafe extern "C" fn(
+        unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void))
+    ) -> u32,
+    hook_renderer: unsafe extern "C" fn(u32, unsafe extern "C" fn()) -> u32,
+    get_iscript_bin: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    set_iscript_bin: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void)>,
+    hook_iscript_opcode: unsafe extern "C" fn(
+        // Iscript pos, iscript ptr, image ptr, dry_run, speed_out, return new pos
+        u32, unsafe extern "C" fn(*mut c_void, *mut c_void, *mut c_void, u32, *mut u32),
+    ) -> u32,
+    sprite_hlines: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut *mut c_void>,
+    sprite_hlines_end: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut *mut c_void>,
+    hook_file_read:
+        unsafe extern "C" fn(*const u8, unsafe extern "C" fn(*const u8, *mut u32) -> *mut u8),
+    first_active_bullet: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    first_lone_sprite: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    // Parent image, image_id, x, y, above
+    add_overlay_iscript: unsafe extern "C" fn() ->
+        Option<unsafe extern "C" fn(*mut c_void, u32, i32, i32, u32) -> *mut c_void>,
+    set_campaigns: unsafe extern "C" fn(*const *mut c_void) -> u32,
+    hook_run_dialog: unsafe extern "C" fn(
+        unsafe extern "C" fn(
+            *mut c_void,
+            usize,
+            *mut c_void,
+            unsafe extern "C" fn(*mut c_void, usize, *mut c_void) -> u32,
+        ) -> u32
+    ) -> u32,
+    send_command: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*const c_void, u32)>,
+    ai_update_attack_target:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void, u32, u32, u32) -> u32>,
+    update_visibility_point: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void)>,
+    create_lone_sprite:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(u32, i32, i32, u32) -> *mut c_void>,
+    step_iscript:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void, *mut c_void, u32, *mut u32)>,
+    is_outside_game_screen: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(i32, i32) -> u32>,
+    screen_pos: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut i32, *mut i32)>,
+    ui_scale: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> f32>,
+    first_fow_sprite: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    is_replay: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> u32>,
+    local_player_id: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> u32>,
+    unit_array_len:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut *mut c_void, *mut usize)>,
+    draw_cursor_marker: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(u32)>,
+    hook_spawn_dialog: unsafe extern "C" fn(
+        unsafe extern "C" fn(
+            *mut c_void,
+            usize,
+            *mut c_void,
+            unsafe extern "C" fn(*mut c_void, usize, *mut c_void) -> u32,
+        ) -> u32
+    ) -> u32,
+    misc_ui_state: unsafe extern "C" fn(usize) -> Option<unsafe extern "C" fn(*mut u8)>,
+    // bullet_id, x, y, player, direction, parent
+    create_bullet: unsafe extern "C" fn() ->
+        Option<unsafe extern "C" fn(u32, i32, i32, u32, u32, *mut c_void) -> *mut c_void>,
+    hook_create_bullet: unsafe extern "C" fn(
+        unsafe extern "C" fn(
+            u32, i32, i32, u32, u32, *mut c_void,
+            unsafe extern "C" fn(u32, i32, i32, u32, u32, *mut c_void) -> *mut c_void,
+        ) -> *mut c_void,
+    ) -> u32,
+    // unit_id, x, y, player, skin
+    create_unit: unsafe extern "C" fn() ->
+        Option<unsafe extern "C" fn(u32, i32, i32, u32, *const u8) -> *mut c_void>,
+    hook_create_unit: unsafe extern "C" fn(
+        unsafe extern "C" fn(
+            u32, i32, i32, u32, *const u8,
+            unsafe extern "C" fn(u32, i32, i32, u32, *const u8) -> *mut c_void,
+        ) -> *mut c_void,
+    ) -> u32,
+    finish_unit_pre: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void)>,
+    finish_unit_post: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void)>,
+    get_sprite_position:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void, *mut u16)>,
+    set_sprite_position:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void, *const u16)>,
+    hook_init_units: unsafe extern "C" fn(unsafe extern "C" fn(unsafe extern "C" fn())) -> u32,
+    get_tooltip_draw_func:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void)>>,
+    set_tooltip_draw_func:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(Option<unsafe extern "C" fn(*mut c_void)>)>,
+    hook_layout_draw_text: unsafe extern "C" fn(
+        unsafe extern "C" fn(
+            u32, u32, *const u8, *mut u32, u32, *mut u32, u32, u32,
+            unsafe extern "C" fn(u32, u32, *const u8, *mut u32, u32, *mut u32, u32, u32) -> *const u8,
+        ) -> *const u8,
+    ) -> u32,
+    hook_draw_graphic_layers: unsafe extern "C" fn(
+        unsafe extern "C" fn(u32, unsafe extern "C" fn(u32)),
+    ) -> u32,
+    graphic_layers: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> *mut c_void>,
+    // Arg 1 shader type (0 = Vertex, 1 = Pixel)
+    // Arg 2 shader id
+    // Arg 3 pointer (Must be a pointer to the entire set with static lifetime)
+    // Arg 4 set size
+    set_prism_shaders: unsafe extern "C" fn(u32, u32, *const u8, u32) -> u32,
+    // Can be stored and called after plugin initialization function.
+    crash_with_message: unsafe extern "C" fn(*const u8) -> !,
+    ai_attack_prepare:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(u32, u32, u32, u32, u32) -> u32>,
+    hook_ai_step_region: unsafe extern "C" fn(
+        unsafe extern "C" fn(u32, u32, unsafe extern "C" fn(u32, u32))
+    ) -> u32,
+    extended_arrays: unsafe extern "C" fn(*mut *mut ExtendedArray) -> usize,
+    extended_dat:
+        unsafe extern "C" fn(u32) -> Option<unsafe extern "C" fn(*mut usize) -> *mut c_void>,
+    give_ai: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut c_void)>,
+    hook_play_sound: unsafe extern "C" fn(
+        unsafe extern "C" fn(
+            u32, f32, *mut c_void, *mut i32, *mut i32,
+            unsafe extern "C" fn(u32, f32, *mut c_void, *mut i32, *mut i32) -> u32,
+        ) -> u32
+    ) -> u32,
+    is_multiplayer: unsafe extern "C" fn() -> Option<unsafe extern "C" fn() -> u32>,
+    hook_game_loop_start: unsafe extern "C" fn(unsafe extern "C" fn()) -> u32,
+    active_iscript_objects:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut *mut c_void, *const *mut c_void)>,
+    hook_ai_focus_disabled: unsafe extern "C" fn(
+        unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void))
+    ) -> u32,
+    hook_ai_focus_air: unsafe extern "C" fn(
+        unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void))
+    ) -> u32,
+    // out: 2 pointer array where [0] = *air*, [1] = *ground*
+    unit_base_strength: unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*mut *mut u32)>,
+    read_map_file:
+        unsafe extern "C" fn() -> Option<unsafe extern "C" fn(*const u8, *mut usize) -> *mut u8>,
+    hook_func: unsafe extern "C" fn(
+        // Func ID (enum)
+        u16,
+        // Hook function: Takes arguments and then original function callback.
+        // Casted from `unsafe extern fn(args..., unsafe extern fn(args...) -> ret) -> ret`
+        usize,
+    ) -> u32,
+    // Func ID -> func, will have to cast for expected calling convention
+    get_func: unsafe extern "C" fn(u16) -> Option<unsafe extern "C" fn()>,
+    // List of VarId, list of results, length of lists
+    // Result enum: 0 = Var not found, 1 = Not supported (Old samase),
+    //      2 = Read only, 3 = Read / write
+    load_vars: unsafe extern "C" fn(*const u16, *mut u8, usize),
+    // List of VarId, list of results, length of lists
+    read_vars: unsafe extern "C" fn(*const u16, *mut usize, usize),
+    // List of VarId, list of values, length of lists
+    write_vars: unsafe extern "C" fn(*const u16, *const usize, usize),
+    // Main tab name, subtab name, draw fn, draw fn ctx
+    // Return 0 if debug ui is disabled.
+    debug_ui_add_tab:
+        unsafe extern "C" fn(*const FfiStr, *const FfiStr, DebugUiDrawCb, *mut c_void) -> usize,
+    // Return null if debug ui is disabled.
+    debug_ui_add_log: unsafe extern "C" fn() -> *mut DebugUiLog,
+    // Log, format, format params, format param count, extra (should be null)
+    // Expected to be stored and used outside samase_plugin_init.
+    // Does nothing if DebugUiLog is null, so caller doesn't have to null check if they
+    // don't want to.
+    // Format string must be valid until debug_log_clear call
+    debug_log_add_data: unsafe extern "C" fn(
+        *mut DebugUiLog, *const FfiStr, *const ComplexLineParam, usize, *mut c_void,
+    ),
+    // Expected to be stored and used outside samase_plugin_init.
+    debug_log_clear: unsafe extern "C" fn(*mut DebugUiLog),
+    // Allocates 4 bytes per unit of memory for later use between plugins.
+    // Takes in field name string, returns id that is stable for the process lifetime,
+    // but unstable between process launches. Repeated calls with same string return
+    // same id.
+    // Can be called outside plugin init function.
+    // Returns 0 if not supported.
+    create_extended_unit_field: unsafe extern "C" fn(*const FfiStr) -> u32,
+    // unit index, field id -> value
+    // Can be called outside plugin init function.
+    read_extended_unit_field: unsafe extern "C" fn(u32, u32) -> u32,
+    // unit index, field id, new value -> old value
+    // Can be called outside plugin init function.
+    write_extended_unit_field: unsafe extern "C" fn(u32, u32, u32) -> u32,
+    // Dat type, dat array index. Return bw::DatTable pointer (Same as fn extended_dat)
+    // and marks the array to be saved / loaded.
+    // Return null on error / unsupported input.
+    mutate_dat: unsafe extern "C" fn(u32, u32) -> *mut c_void,
+}
+
+impl ApiField {
+    /// Returns true if hosts with `PluginApi::version` of `version` have this field.
+    pub fn exists_in(&self, version: u16) -> bool {
+        version >= self.since
+    }
+}
+
+/// Returns `ApiField` for a field at `offset`.
+pub fn field_at_offset(offset: usize) -> Option<&'static ApiField> {
+    API_FIELDS.iter().find(|x| x.offset == offset)
+}
+
+pub fn field_by_name(name: &str) -> Option<&'static ApiField> {
+    API_FIELDS.iter().find(|x| x.name == name)
+}
+
+/// Returns `ty` with whitespace only between words, after commas and around `->`, so
+/// that it doesn't depend on how `stringify!` spaces the tokens.
+pub fn type_description(ty: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '"';
+    let mut out = String::with_capacity(ty.len());
+    let mut space = false;
+    for c in ty.chars() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        if space && out.chars().next_back().map(is_word).unwrap_or(false) && is_word(c) {
+            out.push(' ');
+        }
+        space = false;
+        out.push(c);
+    }
+    out.replace(',', ", ").replace("->", " -> ")
+}
+
+/// `ComplexLineParamType` variants, they have explicit values so there's no
+/// `_Last` to iterate up to.
+pub static COMPLEX_LINE_PARAM_TYPES: &[ComplexLineParamType] = &[
+    ComplexLineParamType::Unit,
+    ComplexLineParamType::UnitId,
+    ComplexLineParamType::Point,
+    ComplexLineParamType::AiRegion,
+    ComplexLineParamType::AiTown,
+    ComplexLineParamType::TechId,
+    ComplexLineParamType::UpgradeId,
+    ComplexLineParamType::I32,
+    ComplexLineParamType::PlayerId,
+];
+
+/// Returns text description of the ABI, one item per line:
+///
+/// ```text
+/// version 44
+/// size 888
+/// field <offset> <size> <name> <type> [since <version>]
+/// func <id> <name>
+/// var <id> <name>
+/// complex_line_param_type <value> <name>
+/// ```
+pub fn describe() -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail
+    let _ = writeln!(out, "version {}", VERSION);
+    let _ = writeln!(out, "size {}", size_of::<PluginApi>());
+    for field in API_FIELDS {
+        let _ = write!(
+            out,
+            "field {} {} {} {}",
+            field.offset,
+            field.size,
+            field.name,
+            type_description(field.ty),
+        );
+        if field.since != 0 {
+            let _ = write!(out, " since {}", field.since);
+        }
+        let _ = writeln!(out);
+    }
+    for id in (0..MAX_FUNC_ID).filter_map(FuncId::from_u16) {
+        let _ = writeln!(out, "func {} {:?}", id as u16, id);
+    }
+    for id in (0..MAX_VAR_ID).filter_map(VarId::from_u16) {
+        let _ = writeln!(out, "var {} {:?}", id as u16, id);
+    }
+    for &ty in COMPLEX_LINE_PARAM_TYPES {
+        let _ = writeln!(out, "complex_line_param_type {} {:?}", ty as u32, ty);
+    }
+    out
+}
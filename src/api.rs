@@ -0,0 +1,666 @@
+//! Safe(r) wrapper over `PluginApi`.
+//!
+//! `SamaseApi::new` resolves every `fn() -> Option<fn>` getter once, and copies the
+//! rest of the function pointers, skipping any field that the host's struct may not have.
+//! Calls to such fields then return `None` / `Err` instead of reading past the end of the
+//! host's struct.
+//!
+//! Fields are checked against the version that added them (`abi::ApiField::since`), so
+//! hosts with an older `PluginApi::version` are not read past the end of their struct.
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::ffi::c_void;
+use core::fmt;
+use core::mem::offset_of;
+use core::ops::Deref;
+use core::ptr::null_mut;
+
+use crate::abi::{field_at_offset, field_by_name};
+use crate::{
+    CommandLength, ComplexLineParam, DebugUiDrawCb, DebugUiLog, ExtendedArray, FfiStr, FuncId,
+    IngameCommandHook, LoadHook, PluginApi, SaveHook,
+};
+
+/// Returns true if the host struct is known to contain field at `offset`.
+///
+/// # Safety
+///
+/// `api` must point to a host `PluginApi`; only `version` is read from it.
+pub unsafe fn has_field(api: *const PluginApi, offset: usize) -> bool {
+    match field_at_offset(offset) {
+        Some(field) => field.exists_in((*api).version),
+        None => false,
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ApiError {
+    /// Host `PluginApi` is older than the version that added this field, or the host
+    /// didn't provide it.
+    MissingField(&'static str),
+    /// `FuncId` is not below host's `max_func_id`.
+    UnsupportedFunc(FuncId),
+    /// Host returned a failure value.
+    Failed(&'static str),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApiError::MissingField(name) => {
+                write!(f, "Samase is too old to support `{}`", name)
+            }
+            ApiError::UnsupportedFunc(id) => {
+                write!(f, "Samase is too old to support function {:?}", id)
+            }
+            ApiError::Failed(name) => write!(f, "`{}` failed", name),
+        }
+    }
+}
+
+type UnitHook = unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void));
+type CommandHook =
+    unsafe extern "C" fn(*const c_void, u32, u32, unsafe extern "C" fn(*const c_void, u32, u32));
+type SendCommandHook =
+    unsafe extern "C" fn(*mut c_void, u32, unsafe extern "C" fn(*mut c_void, u32));
+type DialogHook = unsafe extern "C" fn(
+    *mut c_void,
+    usize,
+    *mut c_void,
+    unsafe extern "C" fn(*mut c_void, usize, *mut c_void) -> u32,
+) -> u32;
+type IscriptOpcodeHook =
+    unsafe extern "C" fn(*mut c_void, *mut c_void, *mut c_void, u32, *mut u32);
+type CreateBulletHook = unsafe extern "C" fn(
+    u32, i32, i32, u32, u32, *mut c_void,
+    unsafe extern "C" fn(u32, i32, i32, u32, u32, *mut c_void) -> *mut c_void,
+) -> *mut c_void;
+type CreateUnitHook = unsafe extern "C" fn(
+    u32, i32, i32, u32, *const u8,
+    unsafe extern "C" fn(u32, i32, i32, u32, *const u8) -> *mut c_void,
+) -> *mut c_void;
+type LayoutDrawTextHook = unsafe extern "C" fn(
+    u32, u32, *const u8, *mut u32, u32, *mut u32, u32, u32,
+    unsafe extern "C" fn(u32, u32, *const u8, *mut u32, u32, *mut u32, u32, u32) -> *const u8,
+) -> *const u8;
+type PlaySoundHook = unsafe extern "C" fn(
+    u32, f32, *mut c_void, *mut i32, *mut i32,
+    unsafe extern "C" fn(u32, f32, *mut c_void, *mut i32, *mut i32) -> u32,
+) -> u32;
+type TooltipDrawFunc = Option<unsafe extern "C" fn(*mut c_void)>;
+type FileReadFn = unsafe extern "C" fn(*const u8, *mut usize) -> *mut u8;
+
+macro_rules! ret_or_unit {
+    () => { () };
+    ($ret:ty) => { $ret };
+}
+
+macro_rules! samase_api {
+    (
+        getters {
+            $($get:ident: fn($($get_arg:ident: $get_ty:ty),*) $(-> $get_ret:ty)?;)*
+        }
+        manual_getters {
+            $($mget:ident: fn($($mget_ty:ty),*) $(-> $mget_ret:ty)?;)*
+        }
+        nonnull_getters {
+            $($nget:ident: fn($($nget_ty:ty),*) $(-> $nget_ret:ty)?;)*
+        }
+        direct {
+            $($dir:ident: fn($($dir_arg:ident: $dir_ty:ty),*) $(-> $dir_ret:ty)?;)*
+        }
+        manual_direct {
+            $($mdir:ident: fn($($mdir_ty:ty),*) $(-> $mdir_ret:ty)?;)*
+        }
+    ) => {
+        /// Resolved `PluginApi`.
+        ///
+        /// Functions that are provided through a getter (`fn() -> Option<fn>`) return
+        /// `None` if the host doesn't have them, other functions return
+        /// `Err(ApiError::MissingField)`.
+        pub struct SamaseApi {
+            api: *const PluginApi,
+            $($get: Option<unsafe extern "C" fn($($get_ty),*) $(-> $get_ret)?>,)*
+            $($mget: Option<unsafe extern "C" fn($($mget_ty),*) $(-> $mget_ret)?>,)*
+            $($nget: Option<unsafe extern "C" fn($($nget_ty),*) $(-> $nget_ret)?>,)*
+            $($dir: Option<unsafe extern "C" fn($($dir_ty),*) $(-> $dir_ret)?>,)*
+            $($mdir: Option<unsafe extern "C" fn($($mdir_ty),*) $(-> $mdir_ret)?>,)*
+        }
+
+        impl SamaseApi {
+            /// Resolves all functions from `api`.
+            ///
+            /// # Safety
+            ///
+            /// `api` must be the pointer given to `samase_plugin_init`, and stay valid
+            /// for the lifetime of `SamaseApi`. (Hosts keep it valid for the entire process)
+            pub unsafe fn new(api: *const PluginApi) -> SamaseApi {
+                SamaseApi {
+                    api,
+                    $($get: if has_field(api, offset_of!(PluginApi, $get)) {
+                        ((*api).$get)()
+                    } else {
+                        None
+                    },)*
+                    $($mget: if has_field(api, offset_of!(PluginApi, $mget)) {
+                        ((*api).$mget)()
+                    } else {
+                        None
+                    },)*
+                    $($nget: if has_field(api, offset_of!(PluginApi, $nget)) {
+                        Some(((*api).$nget)())
+                    } else {
+                        None
+                    },)*
+                    $($dir: if has_field(api, offset_of!(PluginApi, $dir)) {
+                        Some((*api).$dir)
+                    } else {
+                        None
+                    },)*
+                    $($mdir: if has_field(api, offset_of!(PluginApi, $mdir)) {
+                        Some((*api).$mdir)
+                    } else {
+                        None
+                    },)*
+                }
+            }
+
+            /// Returns true if field `name` exists in the host struct, and in case of
+            /// getter fields, the host returned a function for it.
+            pub fn is_resolved(&self, name: &str) -> bool {
+                match name {
+                    $(stringify!($get) => self.$get.is_some(),)*
+                    $(stringify!($mget) => self.$mget.is_some(),)*
+                    $(stringify!($nget) => self.$nget.is_some(),)*
+                    $(stringify!($dir) => self.$dir.is_some(),)*
+                    $(stringify!($mdir) => self.$mdir.is_some(),)*
+                    _ => self.has_field(name),
+                }
+            }
+
+            $(
+                /// Calls the function that the host's getter returned, or returns `None`
+                /// if it didn't return one.
+                ///
+                /// # Safety
+                ///
+                /// Same as calling the host function: pointer arguments must be valid
+                /// for it, and the game has to be in a state where it can be called.
+                pub unsafe fn $get(&self, $($get_arg: $get_ty),*)
+                    -> Option<ret_or_unit!($($get_ret)?)>
+                {
+                    self.$get.map(|func| func($($get_arg),*))
+                }
+            )*
+
+            $(
+                /// Calls the host function, or returns `Err` if the host struct doesn't
+                /// have it.
+                ///
+                /// # Safety
+                ///
+                /// Same as calling the host function: pointer arguments and hooks must
+                /// be valid for it, and the game has to be in a state where it can be
+                /// called.
+                pub unsafe fn $dir(&self, $($dir_arg: $dir_ty),*)
+                    -> Result<ret_or_unit!($($dir_ret)?), ApiError>
+                {
+                    match self.$dir {
+                        Some(func) => Ok(func($($dir_arg),*)),
+                        None => Err(ApiError::MissingField(stringify!($dir))),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+samase_api! {
+    getters {
+        game: fn() -> *mut c_void;
+        rng_seed: fn() -> u32;
+        ai_regions: fn() -> *mut c_void;
+        player_ai: fn() -> *mut c_void;
+        get_region: fn(x: u32, y: u32) -> u32;
+        change_ai_region_state: fn(region: *mut c_void, state: u32);
+        first_active_unit: fn() -> *mut c_void;
+        first_hidden_unit: fn() -> *mut c_void;
+        issue_order: fn(
+            unit: *mut c_void,
+            order: u32,
+            x: u32,
+            y: u32,
+            target: *mut c_void,
+            fow_unit: u32
+        );
+        units: fn() -> *mut c_void;
+        selections: fn() -> *mut c_void;
+        first_ai_script: fn() -> *mut c_void;
+        client_selection: fn() -> *mut c_void;
+        dat_requirements: fn(ty: u32, id: u32) -> *const u16;
+        first_guard_ai: fn() -> *mut c_void;
+        pathing: fn() -> *mut c_void;
+        set_first_ai_script: fn(script: *mut c_void);
+        first_free_ai_script: fn() -> *mut c_void;
+        set_first_free_ai_script: fn(script: *mut c_void);
+        player_ai_towns: fn() -> *mut c_void;
+        map_tile_flags: fn() -> *mut u32;
+        players: fn() -> *mut c_void;
+        get_iscript_bin: fn() -> *mut c_void;
+        set_iscript_bin: fn(bin: *mut c_void);
+        sprite_hlines: fn() -> *mut *mut c_void;
+        sprite_hlines_end: fn() -> *mut *mut c_void;
+        first_active_bullet: fn() -> *mut c_void;
+        first_lone_sprite: fn() -> *mut c_void;
+        add_overlay_iscript: fn(
+            parent: *mut c_void,
+            image_id: u32,
+            x: i32,
+            y: i32,
+            above: u32
+        ) -> *mut c_void;
+        send_command: fn(data: *const c_void, len: u32);
+        ai_update_attack_target: fn(unit: *mut c_void, a1: u32, a2: u32, a3: u32) -> u32;
+        update_visibility_point: fn(lone_sprite: *mut c_void);
+        create_lone_sprite: fn(sprite_id: u32, x: i32, y: i32, player: u32) -> *mut c_void;
+        step_iscript: fn(
+            image: *mut c_void,
+            iscript: *mut c_void,
+            dry_run: u32,
+            speed_out: *mut u32
+        );
+        is_outside_game_screen: fn(x: i32, y: i32) -> u32;
+        screen_pos: fn(x: *mut i32, y: *mut i32);
+        ui_scale: fn() -> f32;
+        first_fow_sprite: fn() -> *mut c_void;
+        is_replay: fn() -> u32;
+        local_player_id: fn() -> u32;
+        unit_array_len: fn(out: *mut *mut c_void, len: *mut usize);
+        draw_cursor_marker: fn(value: u32);
+        create_bullet: fn(
+            bullet_id: u32,
+            x: i32,
+            y: i32,
+            player: u32,
+            direction: u32,
+            parent: *mut c_void
+        ) -> *mut c_void;
+        create_unit: fn(unit_id: u32, x: i32, y: i32, player: u32, skin: *const u8) -> *mut c_void;
+        finish_unit_pre: fn(unit: *mut c_void);
+        finish_unit_post: fn(unit: *mut c_void);
+        get_sprite_position: fn(sprite: *mut c_void, out: *mut u16);
+        set_sprite_position: fn(sprite: *mut c_void, pos: *const u16);
+        get_tooltip_draw_func: fn() -> TooltipDrawFunc;
+        set_tooltip_draw_func: fn(func: TooltipDrawFunc);
+        graphic_layers: fn() -> *mut c_void;
+        ai_attack_prepare: fn(
+            player: u32,
+            x: u32,
+            y: u32,
+            always_override: u32,
+            allow_air_fallback: u32
+        ) -> u32;
+        give_ai: fn(unit: *mut c_void);
+        is_multiplayer: fn() -> u32;
+        active_iscript_objects: fn(read: *mut *mut c_void, write: *const *mut c_void);
+        unit_base_strength: fn(out: *mut *mut u32);
+    }
+    manual_getters {
+        print_text: fn(*const u8);
+        read_map_file: fn(*const u8, *mut usize) -> *mut u8;
+    }
+    nonnull_getters {
+        read_file: fn(*const u8, *mut usize) -> *mut u8;
+    }
+    direct {
+        write_exe_memory: fn(addr: usize, data: *const u8, len: usize) -> u32;
+        hook_step_objects: fn(hook: unsafe extern "C" fn(), after: u32) -> u32;
+        hook_aiscript_opcode: fn(opcode: u32, hook: unsafe extern "C" fn(*mut c_void)) -> u32;
+        hook_on_first_file_access: fn(hook: unsafe extern "C" fn());
+        hook_step_order: fn(hook: UnitHook) -> u32;
+        hook_step_order_hidden: fn(hook: UnitHook) -> u32;
+        hook_process_commands: fn(hook: CommandHook) -> u32;
+        hook_process_lobby_commands: fn(hook: CommandHook) -> u32;
+        hook_send_command: fn(hook: SendCommandHook) -> u32;
+        hook_step_secondary_order: fn(hook: UnitHook) -> u32;
+        extend_save: fn(
+            tag: *const u8,
+            save: SaveHook,
+            load: LoadHook,
+            init: unsafe extern "C" fn()
+        ) -> u32;
+        hook_ingame_command: fn(
+            id: u32,
+            hook: IngameCommandHook,
+            length: Option<CommandLength>
+        ) -> u32;
+        hook_game_screen_rclick: fn(hook: UnitHook) -> u32;
+        hook_draw_image: fn(hook: UnitHook) -> u32;
+        hook_renderer: fn(ty: u32, hook: unsafe extern "C" fn()) -> u32;
+        hook_iscript_opcode: fn(opcode: u32, hook: IscriptOpcodeHook) -> u32;
+        hook_file_read: fn(
+            prefix: *const u8,
+            hook: unsafe extern "C" fn(*const u8, *mut u32) -> *mut u8
+        );
+        set_campaigns: fn(campaigns: *const *mut c_void) -> u32;
+        hook_run_dialog: fn(hook: DialogHook) -> u32;
+        hook_spawn_dialog: fn(hook: DialogHook) -> u32;
+        hook_create_bullet: fn(hook: CreateBulletHook) -> u32;
+        hook_create_unit: fn(hook: CreateUnitHook) -> u32;
+        hook_init_units: fn(hook: unsafe extern "C" fn(unsafe extern "C" fn())) -> u32;
+        hook_layout_draw_text: fn(hook: LayoutDrawTextHook) -> u32;
+        hook_draw_graphic_layers: fn(hook: unsafe extern "C" fn(u32, unsafe extern "C" fn(u32))) -> u32;
+        set_prism_shaders: fn(ty: u32, id: u32, data: *const u8, size: u32) -> u32;
+        hook_ai_step_region: fn(
+            hook: unsafe extern "C" fn(u32, u32, unsafe extern "C" fn(u32, u32))
+        ) -> u32;
+        extended_arrays: fn(out: *mut *mut ExtendedArray) -> usize;
+        hook_play_sound: fn(hook: PlaySoundHook) -> u32;
+        hook_game_loop_start: fn(hook: unsafe extern "C" fn()) -> u32;
+        hook_ai_focus_disabled: fn(hook: UnitHook) -> u32;
+        hook_ai_focus_air: fn(hook: UnitHook) -> u32;
+        debug_ui_add_tab: fn(
+            tab: *const FfiStr,
+            subtab: *const FfiStr,
+            draw: DebugUiDrawCb,
+            ctx: *mut c_void
+        ) -> usize;
+        debug_ui_add_log: fn() -> *mut DebugUiLog;
+        debug_log_add_data: fn(
+            log: *mut DebugUiLog,
+            format: *const FfiStr,
+            params: *const ComplexLineParam,
+            param_count: usize,
+            extra: *mut c_void
+        );
+        debug_log_clear: fn(log: *mut DebugUiLog);
+        create_extended_unit_field: fn(name: *const FfiStr) -> u32;
+        read_extended_unit_field: fn(unit_index: u32, field: u32) -> u32;
+        write_extended_unit_field: fn(unit_index: u32, field: u32, value: u32) -> u32;
+        mutate_dat: fn(dat: u32, array_index: u32) -> *mut c_void;
+    }
+    manual_direct {
+        free_memory: fn(*mut u8);
+        warn_unsupported_feature: fn(*const u8);
+        dat: fn(u32) -> Option<unsafe extern "C" fn() -> *mut c_void>;
+        misc_ui_state: fn(usize) -> Option<unsafe extern "C" fn(*mut u8)>;
+        crash_with_message: fn(*const u8) -> !;
+        extended_dat: fn(u32) -> Option<unsafe extern "C" fn(*mut usize) -> *mut c_void>;
+        hook_func: fn(u16, usize) -> u32;
+        get_func: fn(u16) -> Option<unsafe extern "C" fn()>;
+        load_vars: fn(*const u16, *mut u8, usize);
+        read_vars: fn(*const u16, *mut usize, usize);
+        write_vars: fn(*const u16, *const usize, usize);
+    }
+}
+
+unsafe impl Send for SamaseApi {}
+unsafe impl Sync for SamaseApi {}
+
+/// Null-terminated copy of `text` for functions taking C strings.
+pub(crate) fn c_string(text: &str) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(text.len() + 1);
+    buf.extend_from_slice(text.as_bytes());
+    buf.push(0);
+    buf
+}
+
+/// File contents allocated by the host, freed with `free_memory` on drop.
+pub struct HostBuffer<'a> {
+    api: &'a SamaseApi,
+    data: *mut u8,
+    len: usize,
+}
+
+impl<'a> Deref for HostBuffer<'a> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            &[]
+        } else {
+            unsafe { core::slice::from_raw_parts(self.data, self.len) }
+        }
+    }
+}
+
+impl<'a> Drop for HostBuffer<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.api.free_memory(self.data);
+        }
+    }
+}
+
+impl SamaseApi {
+    /// The raw `PluginApi` pointer this was created from.
+    pub fn raw(&self) -> *const PluginApi {
+        self.api
+    }
+
+    pub fn version(&self) -> u16 {
+        unsafe { (*self.api).version }
+    }
+
+    pub fn max_func_id(&self) -> u16 {
+        unsafe { (*self.api).max_func_id }
+    }
+
+    /// Returns true if the host struct is known to have `PluginApi` field `name`.
+    pub fn has_field(&self, name: &str) -> bool {
+        match field_by_name(name) {
+            Some(field) => field.exists_in(self.version()),
+            None => false,
+        }
+    }
+
+    pub fn has_func(&self, id: FuncId) -> bool {
+        self.get_func.is_some() && (id as u16) < self.max_func_id()
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by the host (e.g. returned by `read_file`), and
+    /// not be used afterwards.
+    pub unsafe fn free_memory(&self, ptr: *mut u8) -> Result<(), ApiError> {
+        match self.free_memory {
+            Some(func) => {
+                func(ptr);
+                Ok(())
+            }
+            None => Err(ApiError::MissingField("free_memory")),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Has to be called during plugin initialization.
+    pub unsafe fn warn_unsupported_feature(&self, feature: &str) -> Result<(), ApiError> {
+        match self.warn_unsupported_feature {
+            Some(func) => {
+                let feature = c_string(feature);
+                func(feature.as_ptr());
+                Ok(())
+            }
+            None => Err(ApiError::MissingField("warn_unsupported_feature")),
+        }
+    }
+
+    unsafe fn read_with(
+        &self,
+        func: Option<FileReadFn>,
+        path: &str,
+    ) -> Option<HostBuffer<'_>> {
+        let func = func?;
+        let path = c_string(path);
+        let mut len = 0usize;
+        let data = func(path.as_ptr(), &mut len);
+        if data.is_null() {
+            None
+        } else {
+            Some(HostBuffer {
+                api: self,
+                data,
+                len,
+            })
+        }
+    }
+
+    /// Reads a file from the game's mpq archives.
+    ///
+    /// # Safety
+    ///
+    /// The host's file system has to be initialized; see `hook_on_first_file_access`.
+    pub unsafe fn read_file(&self, path: &str) -> Option<HostBuffer<'_>> {
+        self.read_with(self.read_file, path)
+    }
+
+    /// Reads a file from the current map.
+    ///
+    /// # Safety
+    ///
+    /// A map has to be loaded.
+    pub unsafe fn read_map_file(&self, path: &str) -> Option<HostBuffer<'_>> {
+        self.read_with(self.read_map_file, path)
+    }
+
+    /// # Safety
+    ///
+    /// The game has to be in a state where text can be printed, e.g. ingame.
+    pub unsafe fn print_text(&self, text: &str) -> Option<()> {
+        let func = self.print_text?;
+        let text = c_string(text);
+        func(text.as_ptr());
+        Some(())
+    }
+
+    /// Crashes the game with `message`.
+    ///
+    /// Only returns if the host doesn't have `crash_with_message`.
+    ///
+    /// # Safety
+    ///
+    /// The process is terminated without unwinding, so nothing after this call runs.
+    pub unsafe fn crash_with_message(&self, message: &str) -> Result<Infallible, ApiError> {
+        match self.crash_with_message {
+            Some(func) => {
+                let message = c_string(message);
+                func(message.as_ptr())
+            }
+            None => Err(ApiError::MissingField("crash_with_message")),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `dat` has to be a dat id that the host accepts.
+    pub unsafe fn dat(&self, dat: u32) -> Option<*mut c_void> {
+        let getter = self.dat?;
+        getter(dat).map(|func| func())
+    }
+
+    /// Returns dat table pointer and length of the table array.
+    ///
+    /// # Safety
+    ///
+    /// Same as `dat`.
+    pub unsafe fn extended_dat(&self, dat: u32) -> Option<(*mut c_void, usize)> {
+        let getter = self.extended_dat?;
+        let func = getter(dat)?;
+        let mut len = 0usize;
+        let result = func(&mut len);
+        Some((result, len))
+    }
+
+    /// Fills `out` with misc ui state bytes (is_paused, is_targeting, is_placing_building).
+    ///
+    /// # Safety
+    ///
+    /// The game has to be running.
+    pub unsafe fn misc_ui_state(&self, out: &mut [u8]) -> Option<()> {
+        let getter = self.misc_ui_state?;
+        let func = getter(out.len())?;
+        func(out.as_mut_ptr());
+        Some(())
+    }
+
+    /// Hooks function `id`.
+    ///
+    /// # Safety
+    ///
+    /// `hook` has to be
+    /// `unsafe extern "C" fn(args..., orig: unsafe extern "C" fn(args...) -> ret) -> ret`
+    /// with the signature of function `id`.
+    pub unsafe fn hook_func(&self, id: FuncId, hook: usize) -> Result<(), ApiError> {
+        let func = self.hook_func.ok_or(ApiError::MissingField("hook_func"))?;
+        if id as u16 >= self.max_func_id() {
+            return Err(ApiError::UnsupportedFunc(id));
+        }
+        if func(id as u16, hook) == 0 {
+            Err(ApiError::Failed("hook_func"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns function `id`, which will have to be casted to the correct signature.
+    ///
+    /// # Safety
+    ///
+    /// The returned function has to be transmuted to the actual signature of `id`
+    /// before calling it.
+    pub unsafe fn get_func(&self, id: FuncId) -> Option<unsafe extern "C" fn()> {
+        let func = self.get_func?;
+        if id as u16 >= self.max_func_id() {
+            return None;
+        }
+        func(id as u16)
+    }
+
+    /// `out` receives 0 = Not found, 1 = Not supported, 2 = Read only, 3 = Read / write
+    /// for each var.
+    ///
+    /// # Safety
+    ///
+    /// Calls the host directly; the host has to support `load_vars` for every id of
+    /// `vars`, which all hosts with the field do.
+    pub unsafe fn load_vars(&self, vars: &[u16], out: &mut [u8]) -> Result<(), ApiError> {
+        assert_eq!(vars.len(), out.len());
+        let func = self.load_vars.ok_or(ApiError::MissingField("load_vars"))?;
+        func(vars.as_ptr(), out.as_mut_ptr(), vars.len());
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// The game has to be initialized far enough for the variables to exist; the host
+    /// reads them without checks.
+    pub unsafe fn read_vars(&self, vars: &[u16], out: &mut [usize]) -> Result<(), ApiError> {
+        assert_eq!(vars.len(), out.len());
+        let func = self.read_vars.ok_or(ApiError::MissingField("read_vars"))?;
+        func(vars.as_ptr(), out.as_mut_ptr(), vars.len());
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// `values` have to be valid for the variables; the host writes them as is.
+    /// Variables that aren't writable are ignored by the host.
+    pub unsafe fn write_vars(&self, vars: &[u16], values: &[usize]) -> Result<(), ApiError> {
+        assert_eq!(vars.len(), values.len());
+        let func = self.write_vars.ok_or(ApiError::MissingField("write_vars"))?;
+        func(vars.as_ptr(), values.as_ptr(), vars.len());
+        Ok(())
+    }
+
+    /// Returns `ExtendedArray` pointer and count.
+    ///
+    /// # Safety
+    ///
+    /// The returned arrays are owned by the host, and must not be freed.
+    pub unsafe fn extended_array_list(&self) -> Result<(*mut ExtendedArray, usize), ApiError> {
+        let mut out = null_mut();
+        let count = self.extended_arrays(&mut out)?;
+        Ok((out, count))
+    }
+}
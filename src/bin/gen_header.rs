@@ -0,0 +1,418 @@
+//! Generates a C header (`samase_plugin.h`) matching the `#[repr(C)]` definitions of
+//! this crate.
+//!
+//! `cargo run --bin gen_header > samase_plugin.h`, or give the output path as an argument.
+//!
+//! The definitions are parsed from `src/lib.rs` source so that the comments describing
+//! arguments end up in the header too. Enum values are taken from the compiled enums.
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+use samase_plugin::{FuncId, VarId, MAX_FUNC_ID, MAX_VAR_ID, VERSION};
+
+static SOURCE: &str = include_str!("../lib.rs");
+
+/// Type aliases that get their own typedef instead of being expanded.
+static ALIASES: &[&str] = &[
+    "IngameCommandHook",
+    "CommandLength",
+    "SaveHook",
+    "LoadHook",
+    "DebugUiDrawCb",
+];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Ident(String),
+    Str,
+    Comment(String),
+    Arrow,
+    Punct(char),
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => (),
+            '/' if text[pos..].starts_with("//") => {
+                let end = text[pos..].find('\n').map(|x| pos + x).unwrap_or(text.len());
+                out.push(Token::Comment(text[pos + 2..end].trim().into()));
+                while chars.peek().map(|x| x.0 < end).unwrap_or(false) {
+                    chars.next();
+                }
+            }
+            '"' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                }
+                out.push(Token::Str);
+            }
+            '-' if chars.peek().map(|x| x.1) == Some('>') => {
+                chars.next();
+                out.push(Token::Arrow);
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                let mut end = pos + c.len_utf8();
+                while let Some(&(p, c)) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        end = p + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                out.push(Token::Ident(text[pos..end].into()));
+            }
+            _ => out.push(Token::Punct(c)),
+        }
+    }
+    out
+}
+
+#[derive(Clone, Debug)]
+enum Ty {
+    Named(String),
+    Ptr(bool, Box<Ty>),
+    Fn(Vec<Ty>, Option<Box<Ty>>),
+    Option(Box<Ty>),
+    Never,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Comments that were inside a type.
+    comments: Vec<String>,
+}
+
+impl Parser {
+    fn new(text: &str) -> Parser {
+        Parser {
+            tokens: tokenize(text),
+            pos: 0,
+            comments: Vec::new(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        while let Some(Token::Comment(text)) = self.tokens.get(self.pos) {
+            self.comments.push(text.clone());
+            self.pos += 1;
+        }
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.peek()?;
+        self.pos += 1;
+        Some(self.tokens[self.pos - 1].clone())
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_ident(&mut self, name: &str) -> bool {
+        self.eat(&Token::Ident(name.into()))
+    }
+
+    fn expect(&mut self, token: Token) {
+        let next = self.next();
+        assert_eq!(next.as_ref(), Some(&token), "Unexpected token at {}", self.pos);
+    }
+
+    fn ident(&mut self) -> String {
+        match self.next() {
+            Some(Token::Ident(name)) => name,
+            x => panic!("Expected identifier, got {:?} at {}", x, self.pos),
+        }
+    }
+
+    fn take_comments(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.comments)
+    }
+
+    fn ty(&mut self) -> Ty {
+        if self.eat(&Token::Punct('!')) {
+            return Ty::Never;
+        }
+        if self.eat(&Token::Punct('*')) {
+            let mutable = match self.ident().as_str() {
+                "mut" => true,
+                "const" => false,
+                x => panic!("Unexpected pointer qualifier {}", x),
+            };
+            return Ty::Ptr(mutable, Box::new(self.ty()));
+        }
+        self.eat_ident("unsafe");
+        if self.eat_ident("extern") {
+            self.expect(Token::Str);
+        }
+        if self.eat_ident("fn") {
+            self.expect(Token::Punct('('));
+            let mut args = Vec::new();
+            while !self.eat(&Token::Punct(')')) {
+                args.push(self.ty());
+                self.eat(&Token::Punct(','));
+            }
+            let ret = match self.eat(&Token::Arrow) {
+                true => Some(Box::new(self.ty())),
+                false => None,
+            };
+            return Ty::Fn(args, ret);
+        }
+        let name = self.ident();
+        if name == "Option" {
+            self.expect(Token::Punct('<'));
+            let inner = self.ty();
+            self.expect(Token::Punct('>'));
+            return Ty::Option(Box::new(inner));
+        }
+        Ty::Named(name)
+    }
+}
+
+struct Field {
+    name: String,
+    ty: Ty,
+    comments: Vec<String>,
+}
+
+/// Returns text between the braces following `start`.
+fn item_body(start: &str) -> &'static str {
+    let pos = SOURCE.find(start).unwrap_or_else(|| panic!("`{}` not found", start));
+    let open = pos + SOURCE[pos..].find('{').unwrap();
+    let mut depth = 0;
+    for (i, c) in SOURCE[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &SOURCE[open + 1..open + i];
+                }
+            }
+            _ => (),
+        }
+    }
+    panic!("Unterminated `{}`", start);
+}
+
+fn struct_fields(name: &str) -> Vec<Field> {
+    let mut parser = Parser::new(item_body(&format!("pub struct {} {{", name)));
+    let mut fields = Vec::new();
+    while parser.peek().is_some() {
+        let mut comments = parser.take_comments();
+        parser.expect(Token::Ident("pub".into()));
+        let name = parser.ident();
+        parser.expect(Token::Punct(':'));
+        let ty = parser.ty();
+        parser.eat(&Token::Punct(','));
+        comments.extend(parser.take_comments());
+        fields.push(Field {
+            name,
+            ty,
+            comments,
+        });
+    }
+    fields
+}
+
+fn type_alias(name: &str) -> Ty {
+    let start = format!("pub type {} =", name);
+    let pos = SOURCE.find(&start).unwrap_or_else(|| panic!("`{}` not found", start));
+    let end = pos + SOURCE[pos..].find(';').unwrap();
+    Parser::new(&SOURCE[pos + start.len()..end]).ty()
+}
+
+/// Returns comments of each variant of an enum.
+fn enum_comments(name: &str) -> Vec<(String, Vec<String>)> {
+    let mut parser = Parser::new(item_body(&format!("pub enum {} {{", name)));
+    let mut result = Vec::new();
+    while parser.peek().is_some() {
+        let name = parser.ident();
+        let comments = parser.take_comments();
+        if parser.eat(&Token::Punct('=')) {
+            parser.next();
+        }
+        parser.eat(&Token::Punct(','));
+        result.push((name, comments));
+    }
+    result
+}
+
+fn c_name(name: &str) -> String {
+    format!("Samase{}", name)
+}
+
+struct Header {
+    out: String,
+}
+
+impl Header {
+    fn comments(&mut self, comments: &[String], indent: &str) {
+        for line in comments {
+            if line.is_empty() {
+                writeln!(self.out, "{}//", indent).unwrap();
+            } else {
+                writeln!(self.out, "{}// {}", indent, line).unwrap();
+            }
+        }
+    }
+
+    /// Returns C type for `ty`, adding typedefs for function pointers, named from `hint`.
+    fn c_type(&mut self, ty: &Ty, hint: &str) -> String {
+        match *ty {
+            Ty::Named(ref name) => match name.as_str() {
+                "u8" => "uint8_t".into(),
+                "u16" => "uint16_t".into(),
+                "u32" => "uint32_t".into(),
+                "i32" => "int32_t".into(),
+                "usize" => "uintptr_t".into(),
+                "f32" => "float".into(),
+                "c_void" => "void".into(),
+                _ => c_name(name),
+            },
+            Ty::Ptr(mutable, ref inner) => {
+                let inner = self.c_type(inner, hint);
+                match (mutable, inner.ends_with('*')) {
+                    (true, _) => format!("{}*", inner),
+                    (false, true) => format!("{} const*", inner),
+                    (false, false) => format!("const {}*", inner),
+                }
+            }
+            // Function pointers are nullable in C.
+            Ty::Option(ref inner) => self.c_type(inner, hint),
+            Ty::Never => "void".into(),
+            Ty::Fn(ref args, ref ret) => {
+                let ret = match *ret {
+                    Some(ref ret) => self.c_type(ret, &format!("{}_ret", hint)),
+                    None => "void".into(),
+                };
+                let args = match args.is_empty() {
+                    true => "void".into(),
+                    false => args.iter()
+                        .enumerate()
+                        .map(|(i, arg)| self.c_type(arg, &format!("{}_arg{}", hint, i)))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                writeln!(self.out, "typedef {} (*{})({});", ret, hint, args).unwrap();
+                hint.into()
+            }
+        }
+    }
+
+    fn c_struct(&mut self, name: &str) {
+        let fields = struct_fields(name);
+        let c_struct = c_name(name);
+        let typedefs_start = self.out.len();
+        let mut lines = Vec::new();
+        for field in &fields {
+            let hint = format!("{}_{}", c_struct, field.name);
+            let ty = self.c_type(&field.ty, &hint);
+            lines.push((field, ty));
+        }
+        if self.out.len() != typedefs_start {
+            self.out.push('\n');
+        }
+        writeln!(self.out, "struct {} {{", c_struct).unwrap();
+        for (field, ty) in lines {
+            self.comments(&field.comments, "    ");
+            writeln!(self.out, "    {} {};", ty, field.name).unwrap();
+        }
+        writeln!(self.out, "}};\n").unwrap();
+    }
+
+    fn c_enum<F: Fn(u16) -> Option<String>>(&mut self, name: &str, max: u16, variant: F) {
+        let comments = enum_comments(name);
+        let c_enum = c_name(name);
+        writeln!(self.out, "enum {} {{", c_enum).unwrap();
+        for value in 0..max {
+            let variant = variant(value).unwrap();
+            if let Some(comments) = comments.iter().find(|x| x.0 == variant) {
+                self.comments(&comments.1, "    ");
+            }
+            writeln!(self.out, "    {}_{} = {},", c_enum, variant, value).unwrap();
+        }
+        writeln!(self.out, "    {}_Last = {},", c_enum, max).unwrap();
+        writeln!(self.out, "}};\n").unwrap();
+    }
+}
+
+fn generate() -> String {
+    let mut header = Header {
+        out: String::new(),
+    };
+    let h = &mut header;
+    writeln!(h.out, "// Generated by samase_plugin gen_header, do not edit.").unwrap();
+    writeln!(h.out, "#ifndef SAMASE_PLUGIN_H").unwrap();
+    writeln!(h.out, "#define SAMASE_PLUGIN_H\n").unwrap();
+    writeln!(h.out, "#include <stdint.h>\n").unwrap();
+    writeln!(h.out, "#ifdef __cplusplus\nextern \"C\" {{\n#endif\n").unwrap();
+    writeln!(h.out, "#define SAMASE_PLUGIN_VERSION {}", VERSION).unwrap();
+    writeln!(h.out, "#define SAMASE_MAX_FUNC_ID {}", MAX_FUNC_ID).unwrap();
+    writeln!(h.out, "#define SAMASE_MAX_VAR_ID {}\n", MAX_VAR_ID).unwrap();
+
+    h.c_enum("FuncId", MAX_FUNC_ID, |x| FuncId::from_u16(x).map(|x| format!("{:?}", x)));
+    h.c_enum("VarId", MAX_VAR_ID, |x| VarId::from_u16(x).map(|x| format!("{:?}", x)));
+    // Values are explicit in source
+    writeln!(h.out, "enum SamaseComplexLineParamType {{").unwrap();
+    let mut parser = Parser::new(item_body("pub enum ComplexLineParamType {"));
+    while parser.peek().is_some() {
+        let name = parser.ident();
+        parser.expect(Token::Punct('='));
+        let value = parser.ident();
+        parser.eat(&Token::Punct(','));
+        writeln!(h.out, "    SamaseComplexLineParamType_{} = {},", name, value).unwrap();
+    }
+    writeln!(h.out, "}};\n").unwrap();
+
+    for name in ["ExtendedArray", "DebugUiLog", "FfiStr", "ComplexLineParam", "DebugUiDraw",
+        "PluginApi"]
+    {
+        writeln!(h.out, "typedef struct {0} {0};", c_name(name)).unwrap();
+    }
+    writeln!(h.out, "// Extern struct.").unwrap();
+    writeln!(h.out, "struct SamaseDebugUiLog {{\n    uint32_t unused;\n}};").unwrap();
+    writeln!(h.out, "typedef uint32_t SamaseDebugUiColor;\n").unwrap();
+    for &alias in ALIASES {
+        let ty = type_alias(alias);
+        let c_ty = h.c_type(&ty, &c_name(alias));
+        if c_ty != c_name(alias) {
+            writeln!(h.out, "typedef {} {};", c_ty, c_name(alias)).unwrap();
+        }
+    }
+    h.out.push('\n');
+    for name in ["ExtendedArray", "FfiStr", "ComplexLineParam", "DebugUiDraw", "PluginApi"] {
+        h.c_struct(name);
+    }
+    writeln!(h.out, "#ifdef __cplusplus\n}}\n#endif\n").unwrap();
+    writeln!(h.out, "#endif // SAMASE_PLUGIN_H").unwrap();
+    header.out
+}
+
+fn main() {
+    let header = generate();
+    match std::env::args_os().nth(1) {
+        Some(path) => {
+            if let Err(e) = std::fs::write(&path, header) {
+                eprintln!("Couldn't write {}: {}", path.to_string_lossy(), e);
+                std::process::exit(1);
+            }
+        }
+        None => {
+            std::io::stdout().write_all(header.as_bytes()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Report of what the host `PluginApi` supports.
+//!
+//! `Capabilities::probe` walks every `PluginApi` field that the host is known to have, calls
+//! `get_func` for every `FuncId` and `load_vars` for every `VarId`. Plugins can then
+//! declare what they need with `Requirements` and fail at startup with a single message
+//! listing everything that is missing:
+//!
+//! ```ignore
+//! let caps = Capabilities::probe(&api);
+//! let req = Requirements::new()
+//!     .field("issue_order")
+//!     .func(FuncId::DamageUnit)
+//!     .var_writable(VarId::RngSeed);
+//! if let Err(e) = caps.check(&req) {
+//!     let _ = api.crash_with_message(&format!("My plugin: {}", e));
+//! }
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::abi::{field_by_name, API_FIELDS};
+use crate::api::SamaseApi;
+use crate::vars::VarAccess;
+use crate::{FuncId, VarId, MAX_FUNC_ID, MAX_VAR_ID};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FieldStatus {
+    /// Host version is older than the version that added this field.
+    Missing,
+    /// Field exists, but it is a getter which returned `None`.
+    NotProvided,
+    Available,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FieldReport {
+    pub name: &'static str,
+    pub status: FieldStatus,
+}
+
+pub struct Capabilities {
+    pub version: u16,
+    pub max_func_id: u16,
+    /// All `PluginApi` fields known to this crate, in declaration order.
+    pub fields: Vec<FieldReport>,
+    /// Indexed by `FuncId`, true if `get_func` returned a function.
+    pub funcs: Vec<bool>,
+    /// Indexed by `VarId`.
+    pub vars: Vec<VarAccess>,
+}
+
+impl Capabilities {
+    /// Checks which fields, functions and variables `api` has.
+    ///
+    /// # Safety
+    ///
+    /// Same as `SamaseApi::load_vars`; functions are only looked up, not called.
+    pub unsafe fn probe(api: &SamaseApi) -> Capabilities {
+        let fields = API_FIELDS.iter()
+            .map(|field| {
+                let status = if !field.exists_in(api.version()) {
+                    FieldStatus::Missing
+                } else if api.is_resolved(field.name) {
+                    FieldStatus::Available
+                } else {
+                    FieldStatus::NotProvided
+                };
+                FieldReport {
+                    name: field.name,
+                    status,
+                }
+            })
+            .collect();
+        let funcs = (0..MAX_FUNC_ID)
+            .map(|id| match FuncId::from_u16(id) {
+                Some(id) => api.get_func(id).is_some(),
+                None => false,
+            })
+            .collect();
+        let var_ids: Vec<u16> = (0..MAX_VAR_ID).collect();
+        let mut raw_vars = alloc::vec![0u8; var_ids.len()];
+        if api.load_vars(&var_ids, &mut raw_vars).is_err() {
+            // Old host without vars at all; report everything as not supported.
+            for var in raw_vars.iter_mut() {
+                *var = 1;
+            }
+        }
+        let vars = raw_vars.iter().map(|&x| VarAccess::from_raw(x)).collect();
+        Capabilities {
+            version: api.version(),
+            max_func_id: api.max_func_id(),
+            fields,
+            funcs,
+            vars,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldReport> {
+        self.fields.iter().find(|x| x.name == name)
+    }
+
+    pub fn has_field(&self, name: &str) -> bool {
+        self.field(name).map(|x| x.status == FieldStatus::Available).unwrap_or(false)
+    }
+
+    pub fn has_func(&self, id: FuncId) -> bool {
+        self.funcs.get(id as usize).copied().unwrap_or(false)
+    }
+
+    pub fn var_access(&self, id: VarId) -> VarAccess {
+        self.vars.get(id as usize).copied().unwrap_or(VarAccess::NotFound)
+    }
+
+    pub fn can_read_var(&self, id: VarId) -> bool {
+        self.var_access(id).can_read()
+    }
+
+    pub fn can_write_var(&self, id: VarId) -> bool {
+        self.var_access(id).can_write()
+    }
+
+    /// Returns everything in `req` that isn't available.
+    pub fn missing(&self, req: &Requirements) -> Vec<Missing> {
+        let mut result = Vec::new();
+        for &name in &req.fields {
+            match self.field(name) {
+                Some(field) => match field.status {
+                    FieldStatus::Available => (),
+                    FieldStatus::Missing => result.push(Missing::Field {
+                        name,
+                        old_host: true,
+                    }),
+                    FieldStatus::NotProvided => result.push(Missing::Field {
+                        name,
+                        old_host: false,
+                    }),
+                },
+                None => result.push(Missing::Field {
+                    name,
+                    old_host: false,
+                }),
+            }
+        }
+        for &id in &req.funcs {
+            if !self.has_func(id) {
+                result.push(Missing::Func(id));
+            }
+        }
+        for &(id, write) in &req.vars {
+            let ok = match write {
+                true => self.can_write_var(id),
+                false => self.can_read_var(id),
+            };
+            if !ok {
+                result.push(Missing::Var(id, write));
+            }
+        }
+        result
+    }
+
+    /// Returns `Err` listing everything missing if any of `req` isn't available.
+    pub fn check(&self, req: &Requirements) -> Result<(), MissingCapabilities> {
+        let missing = self.missing(req);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingCapabilities {
+                version: self.version,
+                missing,
+            })
+        }
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Samase API version {}, max func id {}", self.version, self.max_func_id)?;
+        writeln!(f, "Fields:")?;
+        for field in &self.fields {
+            let status = match field.status {
+                FieldStatus::Available => "ok",
+                FieldStatus::NotProvided => "not provided",
+                FieldStatus::Missing => "missing",
+            };
+            writeln!(f, "    {}: {}", field.name, status)?;
+        }
+        writeln!(f, "Functions:")?;
+        for (i, &ok) in self.funcs.iter().enumerate() {
+            if let Some(id) = FuncId::from_u16(i as u16) {
+                writeln!(f, "    {:?}: {}", id, if ok { "ok" } else { "missing" })?;
+            }
+        }
+        writeln!(f, "Vars:")?;
+        for (i, &access) in self.vars.iter().enumerate() {
+            if let Some(id) = VarId::from_u16(i as u16) {
+                writeln!(f, "    {:?}: {}", id, access)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// List of `PluginApi` fields, functions and variables that a plugin needs.
+#[derive(Clone, Default)]
+pub struct Requirements {
+    fields: Vec<&'static str>,
+    funcs: Vec<FuncId>,
+    vars: Vec<(VarId, bool)>,
+}
+
+impl Requirements {
+    pub fn new() -> Requirements {
+        Requirements::default()
+    }
+
+    pub fn field(mut self, name: &'static str) -> Requirements {
+        self.fields.push(name);
+        self
+    }
+
+    pub fn func(mut self, id: FuncId) -> Requirements {
+        self.funcs.push(id);
+        self
+    }
+
+    pub fn var(mut self, id: VarId) -> Requirements {
+        self.vars.push((id, false));
+        self
+    }
+
+    pub fn var_writable(mut self, id: VarId) -> Requirements {
+        self.vars.push((id, true));
+        self
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Missing {
+    /// `old_host` is true if the host is older than the version that added the field,
+    /// false if the host didn't provide it.
+    Field {
+        name: &'static str,
+        old_host: bool,
+    },
+    Func(FuncId),
+    /// Var id, write access needed
+    Var(VarId, bool),
+}
+
+#[derive(Debug)]
+pub struct MissingCapabilities {
+    pub version: u16,
+    pub missing: Vec<Missing>,
+}
+
+impl fmt::Display for Missing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Missing::Field { name, old_host: true } => match field_by_name(name) {
+                Some(field) => write!(f, "`{}` (requires API version {})", name, field.since),
+                None => write!(f, "`{}`", name),
+            },
+            Missing::Field { name, old_host: false } => write!(f, "`{}`", name),
+            Missing::Func(id) => write!(f, "function {:?}", id),
+            Missing::Var(id, true) => write!(f, "writable variable {:?}", id),
+            Missing::Var(id, false) => write!(f, "variable {:?}", id),
+        }
+    }
+}
+
+impl fmt::Display for MissingCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Samase (API version {}) is missing ", self.version)?;
+        for (i, missing) in self.missing.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", missing)?;
+        }
+        Ok(())
+    }
+}
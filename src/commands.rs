@@ -1,35 +1,106 @@
+pub mod decode;
+pub mod encode;
+pub mod lengths;
+pub mod plugin_command;
+pub mod recorder;
+pub mod replay;
+pub mod validate;
+
 use std::cell::RefCell;
 use std::ffi::c_void;
+use std::fmt;
 use std::mem;
 use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use thread_local::ThreadLocal;
-use once_cell::sync::{Lazy, OnceCell};
-use parking_lot::{Mutex, MutexGuard, RwLock, const_mutex, const_rwlock};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock, const_mutex, const_rwlock};
 
 pub use super::{IngameCommandHook, CommandLength};
 
+use self::replay::ReplayClass;
+use self::validate::{MalformedAction, MalformedCommand, MalformedReason};
+
+// Hooks of each chain in the order they get called, rebuilt from `INGAME_HOOK_ENTRIES`
+// on changes.
 static INGAME_HOOKS: RwLock<Vec<(u8, IngameCommandHook)>> = const_rwlock(Vec::new());
-static COMMAND_LENGTHS: OnceCell<&CommandLengths> = OnceCell::new();
-static COMMAND_LENGTHS_MUTABLE: Mutex<CommandLengths> = const_mutex(CommandLengths::new());
+static LOBBY_HOOKS: RwLock<Vec<(u8, IngameCommandHook)>> = const_rwlock(Vec::new());
+static SEND_HOOKS: RwLock<Vec<(u8, IngameCommandHook)>> = const_rwlock(Vec::new());
+static INGAME_HOOK_ENTRIES: Mutex<IngameHookEntries> = const_mutex(IngameHookEntries {
+    entries: Vec::new(),
+    next_handle: 0,
+});
+static COMMAND_LENGTHS: CommandLengths = CommandLengths::new();
+/// Set for command ids once `ingame_hook` has used their length.
+static COMMAND_PROCESSED: [AtomicBool; 0x100] = [const { AtomicBool::new(false) }; 0x100];
 
+/// Each entry is changed atomically, so the table can be changed while it is being used.
 pub struct CommandLengths {
     /// Either < 0x400, or usize::MAX in which case it is taken as integer, otherwise
     /// a function pointer.
-    data: [usize; 0x100],
+    data: [AtomicUsize; 0x100],
 }
 
 impl CommandLengths {
     const fn new() -> CommandLengths {
         CommandLengths {
-            data: [usize::MAX; 0x100],
+            data: [const { AtomicUsize::new(usize::MAX) }; 0x100],
+        }
+    }
+
+    /// Creates a table with `set_default_command_lengths` rules, without affecting the
+    /// global table.
+    pub fn with_defaults(lengths: &[u32]) -> CommandLengths {
+        let result = CommandLengths::new();
+        result.set_defaults(lengths, |_| ());
+        result
+    }
+
+    /// Calls `on_change(id)` before changing an entry.
+    fn set_defaults<F: Fn(u8)>(&self, lengths: &[u32], on_change: F) {
+        let set = |id: u8, value: usize| self.set(id, value, &on_change);
+        for (i, &value) in lengths.iter().enumerate() {
+            if i >= 0x100 {
+                break;
+            }
+            if (value as usize) < 0x400 {
+                set(i as u8, value as usize);
+            }
         }
+        set(0x6, save_command_length as *const() as usize);
+        set(0x7, save_command_length as *const() as usize);
+        set(0x9, select_command_legacy_length as *const() as usize);
+        set(0xa, select_command_legacy_length as *const() as usize);
+        set(0xb, select_command_legacy_length as *const() as usize);
+        set(0x63, select_command_extended_length as *const() as usize);
+        set(0x64, select_command_extended_length as *const() as usize);
+        set(0x65, select_command_extended_length as *const() as usize);
+        // This isn't set correctly for whatever reason, and it works out for bw since
+        // it's not replay skipped
+        set(0x37, 7);
+    }
+
+    fn set<F: Fn(u8)>(&self, id: u8, value: usize, on_change: F) {
+        if self.raw(id) != value {
+            on_change(id);
+            self.data[id as usize].store(value, Ordering::Relaxed);
+        }
+    }
+
+    fn raw(&self, id: u8) -> usize {
+        self.data[id as usize].load(Ordering::Relaxed)
+    }
+
+    /// Returns false if the length of `id` has not been set.
+    pub fn is_set(&self, id: u8) -> bool {
+        self.raw(id) != usize::MAX
     }
 
     /// Returns value larger than cmd.len() on error (Usually usize::MAX)
     pub fn command_len(&self, cmd: &[u8]) -> usize {
         let id = cmd.get(0).copied().unwrap_or(0);
-        let value = self.data[id as usize];
+        let value = self.raw(id);
         if value < 0x400 {
             return value;
         } else {
@@ -70,11 +141,18 @@ unsafe extern "C" fn select_command_extended_length(cmd: *const u8, len: u32) ->
     }
 }
 
-/// Will permamently lock the mutex on first access, preventing further mutation.
+/// The global table; `set_default_command_lengths` and `add_length_override` may still
+/// change it afterwards.
 pub fn get_command_lengths() -> &'static CommandLengths {
-    COMMAND_LENGTHS.get_or_init(|| {
-        MutexGuard::leak(COMMAND_LENGTHS_MUTABLE.lock())
-    })
+    &COMMAND_LENGTHS
+}
+
+/// Warns if a length is changed for a command id that has already been processed
+/// with the old length.
+fn warn_if_processed(id: u8) {
+    if COMMAND_PROCESSED[id as usize].load(Ordering::Relaxed) {
+        warn!("Length of command {:x} was changed after such command was processed", id);
+    }
 }
 
 static HOOK_CALL_STATE: Lazy<ThreadLocal<RefCell<Vec<HookCallState<'static>>>>> =
@@ -84,10 +162,45 @@ static HOOK_CALL_STATE: Lazy<ThreadLocal<RefCell<Vec<HookCallState<'static>>>>>
 struct HookCallState<'a> {
     orig_hooks: &'a [(u8, IngameCommandHook)],
     remaining_hooks: &'a [(u8, IngameCommandHook)],
-    globals: &'a IngameHookGlobals,
-    orig: unsafe extern "C" fn(*const c_void, u32, u32),
+    context: ChainContext,
     id: u8,
-    replayed_command: u32,
+}
+
+/// Hook chain that a hook is added to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CommandChain {
+    /// Commands being executed, through `ingame_hook`.
+    Ingame,
+    /// Lobby commands, through `lobby_hook`.
+    Lobby,
+    /// Commands being sent to other players, through `send_hook`.
+    Send,
+}
+
+impl CommandChain {
+    fn hooks(self) -> &'static RwLock<Vec<(u8, IngameCommandHook)>> {
+        match self {
+            CommandChain::Ingame => &INGAME_HOOKS,
+            CommandChain::Lobby => &LOBBY_HOOKS,
+            CommandChain::Send => &SEND_HOOKS,
+        }
+    }
+}
+
+/// What the hooks of a chain receive, and what gets called after them.
+#[derive(Clone, Copy)]
+struct ChainContext {
+    chain: CommandChain,
+    player: u32,
+    unique_player: u32,
+    orig: ChainOrig,
+}
+
+#[derive(Clone, Copy)]
+enum ChainOrig {
+    /// Original command processing function, and its `replayed_command` argument.
+    Process(unsafe extern "C" fn(*const c_void, u32, u32), u32),
+    Send(unsafe extern "C" fn(*mut c_void, u32)),
 }
 
 pub struct IngameHookGlobals {
@@ -105,41 +218,217 @@ pub unsafe extern "C" fn ingame_hook(
     orig: unsafe extern "C" fn(*const c_void, u32, u32),
 ) {
     let data = slice::from_raw_parts(data as *const u8, len as usize);
+    if recorder::is_recording() {
+        recorder::record_batch(data, replayed_command, globals);
+    }
     let hooks = INGAME_HOOKS.read();
     let cmd_lengths = get_command_lengths();
+    let context = ChainContext {
+        chain: CommandChain::Ingame,
+        player: globals.command_user,
+        unique_player: globals.unique_command_user,
+        orig: ChainOrig::Process(orig, replayed_command),
+    };
     let mut pos = data;
     while pos.len() > 0 {
-        let skip = globals.is_replay != 0 && replayed_command == 0 && !is_replay_command(pos[0]);
         let len = cmd_lengths.command_len(pos);
-        if len > pos.len() {
-            error!("Command {:x} too short for its length {:x}", pos[0], len);
+        COMMAND_PROCESSED[pos[0] as usize].store(true, Ordering::Relaxed);
+        if len == 0 || len > pos.len() {
+            let command = MalformedCommand {
+                player: globals.command_user,
+                unique_player: globals.unique_command_user,
+                data: pos,
+                reason: MalformedReason::Length(len),
+            };
+            validate::malformed(&command, 0);
             return;
         }
-        if !skip {
-            let bytes = &pos[..len];
-            handle_ingame_hooks(&hooks, bytes, replayed_command, globals, orig);
-            if globals.is_replay == 0 && !is_replay_command(pos[0]) {
-                (globals.add_to_replay_data)(bytes.as_ptr(), len);
+        let mut bytes = &pos[..len];
+        pos = &pos[len..];
+        if replayed_command != 0 {
+            if let Some(inner) = replay::unwrap(bytes) {
+                if inner.is_empty() {
+                    continue;
+                }
+                bytes = inner;
             }
         }
-        pos = &pos[len..];
+        if let Err(reason) = validate::validate(bytes) {
+            let command = MalformedCommand {
+                player: globals.command_user,
+                unique_player: globals.unique_command_user,
+                data: bytes,
+                reason: MalformedReason::Invalid(reason),
+            };
+            match validate::malformed(&command, pos.len()) {
+                MalformedAction::DropCommand => continue,
+                MalformedAction::DropRest => return,
+            }
+        }
+        let class = replay::replay_class(bytes[0]);
+        let execute = match class {
+            ReplayClass::Recorded => globals.is_replay == 0 || replayed_command != 0,
+            ReplayClass::Skipped => true,
+            ReplayClass::ReplayOnly => replayed_command != 0,
+        };
+        if execute {
+            handle_hooks(&hooks, bytes, context);
+        }
+        if globals.is_replay == 0 && class != ReplayClass::Skipped {
+            (globals.add_to_replay_data)(bytes.as_ptr(), bytes.len());
+        }
     }
 }
 
-fn is_replay_command(byte: u8) -> bool {
-    match byte {
-        0x5 | 0x8 | 0x10 | 0x11 | 0x55 | 0x56 | 0x5d => true,
-        _ => false,
+/// Runs lobby command hooks added with `CommandChain::Lobby` for each command.
+///
+/// If no hooks have been added, or the commands can't be split with the command
+/// lengths, the (remaining) data is passed to `orig` as is.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `orig` must be safe to call with
+/// them and `replayed_command`.
+pub unsafe extern "C" fn lobby_hook(
+    data: *const c_void,
+    len: u32,
+    replayed_command: u32,
+    player: u32,
+    unique_player: u32,
+    orig: unsafe extern "C" fn(*const c_void, u32, u32),
+) {
+    let hooks = LOBBY_HOOKS.read();
+    if hooks.is_empty() {
+        orig(data, len, replayed_command);
+        return;
     }
+    let data = slice::from_raw_parts(data as *const u8, len as usize);
+    let context = ChainContext {
+        chain: CommandChain::Lobby,
+        player,
+        unique_player,
+        orig: ChainOrig::Process(orig, replayed_command),
+    };
+    split_commands(data, "Lobby", &hooks, context);
 }
 
-unsafe fn handle_ingame_hooks(
+/// Runs send hooks added with `CommandChain::Send` for each command being sent.
+///
+/// The buffer is split with the command lengths, and every command goes through the
+/// chain separately, so a hook vetoing one command doesn't drop the rest. If no hooks
+/// have been added, or the commands can't be split, the (remaining) data is passed to
+/// `orig` as is.
+///
+/// `player` and `unique_player` are passed to the hooks; they should be the local
+/// player.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `orig` must be safe to call with
+/// them.
+pub unsafe extern "C" fn send_hook(
+    data: *mut c_void,
+    len: u32,
+    player: u32,
+    unique_player: u32,
+    orig: unsafe extern "C" fn(*mut c_void, u32),
+) {
+    let context = ChainContext {
+        chain: CommandChain::Send,
+        player,
+        unique_player,
+        orig: ChainOrig::Send(orig),
+    };
+    let hooks = SEND_HOOKS.read();
+    if len == 0 || hooks.is_empty() {
+        orig(data, len);
+        return;
+    }
+    let data = slice::from_raw_parts(data as *const u8, len as usize);
+    split_commands(data, "Sent", &hooks, context);
+}
+
+/// Runs `hooks` for each command of `data`; `kind` is only used in the error message.
+unsafe fn split_commands(
+    data: &[u8],
+    kind: &str,
     hooks: &[(u8, IngameCommandHook)],
-    cmd: &[u8],
+    context: ChainContext,
+) {
+    let cmd_lengths = get_command_lengths();
+    let mut pos = data;
+    while !pos.is_empty() {
+        // Unset lengths can't be called, so they are treated same as invalid ones.
+        let len = if cmd_lengths.is_set(pos[0]) {
+            cmd_lengths.command_len(pos)
+        } else {
+            usize::MAX
+        };
+        if len == 0 || len > pos.len() {
+            error!("{} command {:x} has invalid length {:x}", kind, pos[0], len);
+            call_game(pos, context);
+            return;
+        }
+        handle_hooks(hooks, &pos[..len], context);
+        pos = &pos[len..];
+    }
+}
+
+/// Returns `(player, unique_player)` for `lobby_commands_hook` / `send_command_hook`.
+pub type ChainPlayerFn = fn() -> (u32, u32);
+
+fn no_player() -> (u32, u32) {
+    (0, 0)
+}
+
+static LOBBY_PLAYER: RwLock<ChainPlayerFn> = const_rwlock(no_player);
+static SEND_PLAYER: RwLock<ChainPlayerFn> = const_rwlock(no_player);
+
+/// Sets how `lobby_commands_hook` gets the player who sent the lobby commands.
+pub fn set_lobby_player(player: ChainPlayerFn) {
+    *LOBBY_PLAYER.write() = player;
+}
+
+/// Sets how `send_command_hook` gets the local player.
+pub fn set_send_player(player: ChainPlayerFn) {
+    *SEND_PLAYER.write() = player;
+}
+
+/// `lobby_hook` with the signature of `PluginApi::hook_process_lobby_commands` hooks,
+/// using the player set with `set_lobby_player`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `orig` must be safe to call with
+/// them and `replayed_command`.
+pub unsafe extern "C" fn lobby_commands_hook(
+    data: *const c_void,
+    len: u32,
     replayed_command: u32,
-    globals: &IngameHookGlobals,
     orig: unsafe extern "C" fn(*const c_void, u32, u32),
 ) {
+    let (player, unique_player) = (*LOBBY_PLAYER.read())();
+    lobby_hook(data, len, replayed_command, player, unique_player, orig);
+}
+
+/// `send_hook` with the signature of `PluginApi::hook_send_command` hooks, using the
+/// player set with `set_send_player`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `orig` must be safe to call with
+/// them.
+pub unsafe extern "C" fn send_command_hook(
+    data: *mut c_void,
+    len: u32,
+    orig: unsafe extern "C" fn(*mut c_void, u32),
+) {
+    let (player, unique_player) = (*SEND_PLAYER.read())();
+    send_hook(data, len, player, unique_player, orig);
+}
+
+/// Calls the first hook for `cmd`, which can continue the chain with `call_orig`.
+unsafe fn handle_hooks(hooks: &[(u8, IngameCommandHook)], cmd: &[u8], context: ChainContext) {
     unsafe extern "C" fn call_orig(data: *const u8, len: u32) {
         if len == 0 {
             return;
@@ -152,6 +441,10 @@ unsafe fn handle_ingame_hooks(
             let mut state_guard = states.borrow_mut();
             let state_ref: &mut HookCallState = state_guard.last_mut().unwrap();
             state = *state_ref;
+            if state.context.chain == CommandChain::Ingame && recorder::is_recording() {
+                let caller = state.orig_hooks.len() - state.remaining_hooks.len() - 1;
+                recorder::record_forwarded(caller, slice::from_raw_parts(data, len as usize));
+            }
             if state.id == id {
                 for i in 0..state.remaining_hooks.len() {
                     let (other_id, h) = state.remaining_hooks[i];
@@ -163,25 +456,18 @@ unsafe fn handle_ingame_hooks(
                 }
             }
         }
+        let cmd = slice::from_raw_parts(data, len as usize);
         // If the command id gets changed, reset the hook position
         if state.id != id {
-            let cmd = slice::from_raw_parts(data, len as usize);
-            let HookCallState {
-                orig_hooks,
-                replayed_command,
-                globals,
-                orig,
-                ..
-            } = state;
-            handle_ingame_hooks(orig_hooks, cmd, replayed_command, globals, orig);
+            handle_hooks(state.orig_hooks, cmd, state.context);
         } else {
             if let Some(hook) = hook {
-                let player = state.globals.command_user;
-                let uniq = state.globals.unique_command_user;
+                let player = state.context.player;
+                let uniq = state.context.unique_player;
                 hook(data, len, player, uniq, call_orig);
             } else {
                 trace!("Calling orig for {:x}:{:x}", id, len);
-                (state.orig)(data as *const c_void, len, state.replayed_command);
+                call_game(cmd, state.context);
             }
         }
     }
@@ -193,14 +479,12 @@ unsafe fn handle_ingame_hooks(
             let state = HookCallState {
                 orig_hooks: hooks,
                 remaining_hooks: &hooks[i + 1..],
-                globals,
-                replayed_command,
-                orig,
+                context,
                 id,
             };
             let state: HookCallState<'static> = mem::transmute(state);
-            let player = globals.command_user;
-            let uniq = globals.unique_command_user;
+            let player = context.player;
+            let uniq = context.unique_player;
             let states = HOOK_CALL_STATE.get_or(|| RefCell::new(Vec::new()));
             states.borrow_mut().push(state);
             hook(cmd.as_ptr(), cmd.len() as u32, player, uniq, call_orig);
@@ -208,38 +492,234 @@ unsafe fn handle_ingame_hooks(
             return;
         }
     }
-    orig(cmd.as_ptr() as *const c_void, cmd.len() as u32, replayed_command);
+    call_game(cmd, context);
+}
+
+unsafe fn call_game(cmd: &[u8], context: ChainContext) {
+    match context.orig {
+        ChainOrig::Process(orig, replayed_command) => {
+            if context.chain == CommandChain::Ingame && recorder::is_recording() {
+                recorder::record_to_game(cmd);
+            }
+            orig(cmd.as_ptr() as *const c_void, cmd.len() as u32, replayed_command);
+        }
+        ChainOrig::Send(orig) => {
+            // The game copies the data, so it isn't actually written to.
+            orig(cmd.as_ptr() as *mut c_void, cmd.len() as u32);
+        }
+    }
 }
 
 /// Should be called before any function overrides are added for ones included in here.
+///
+/// `lengths` is usually `bw::command_lengths`, or one of the tables in `lengths` when
+/// the game isn't available.
 pub fn set_default_command_lengths(lengths: &[u32]) {
-    let mut out = COMMAND_LENGTHS_MUTABLE.lock();
-    for (i, &value) in lengths.iter().enumerate() {
-        if i >= 0x100 {
-            break;
+    COMMAND_LENGTHS.set_defaults(lengths, warn_if_processed);
+}
+
+/// Adds `hook` for command `cmd`, with priority 0 and no name.
+pub fn add_ingame_hook(cmd: u8, hook: IngameCommandHook) -> IngameHookHandle {
+    add_ingame_hook_with(cmd, hook, &IngameHookOptions::default())
+        .expect("Unnamed hooks can't conflict")
+}
+
+/// Adds a `CommandChain::Lobby` hook with priority 0 and no name.
+pub fn add_lobby_hook(cmd: u8, hook: IngameCommandHook) -> IngameHookHandle {
+    add_command_hook_with(CommandChain::Lobby, cmd, hook, &IngameHookOptions::default())
+        .expect("Unnamed hooks can't conflict")
+}
+
+/// Adds a `CommandChain::Send` hook with priority 0 and no name.
+///
+/// The hook can change the command, or not call `orig` to prevent it from being sent.
+pub fn add_send_hook(cmd: u8, hook: IngameCommandHook) -> IngameHookHandle {
+    add_command_hook_with(CommandChain::Send, cmd, hook, &IngameHookOptions::default())
+        .expect("Unnamed hooks can't conflict")
+}
+
+/// Adds `hook` for command `cmd`, ordered according to `options`.
+///
+/// Hooks with higher priority are called first, and receive the command before
+/// lower priority ones. Hooks with equal priority are called in the order they were
+/// added, unless `before` / `after` constraints name another hook of the same command.
+/// Constraints naming hooks that aren't registered are ignored.
+pub fn add_ingame_hook_with(
+    cmd: u8,
+    hook: IngameCommandHook,
+    options: &IngameHookOptions,
+) -> Result<IngameHookHandle, IngameHookError> {
+    add_command_hook_with(CommandChain::Ingame, cmd, hook, options)
+}
+
+/// Adds `hook` for command `cmd` to `chain`, like `add_ingame_hook_with`.
+///
+/// Names are shared by all chains, but `before` / `after` only apply to hooks of the same
+/// chain.
+pub fn add_command_hook_with(
+    chain: CommandChain,
+    cmd: u8,
+    hook: IngameCommandHook,
+    options: &IngameHookOptions,
+) -> Result<IngameHookHandle, IngameHookError> {
+    let mut entries = INGAME_HOOK_ENTRIES.lock();
+    if let Some(name) = options.name {
+        if entries.entries.iter().any(|x| x.name.as_deref() == Some(name)) {
+            return Err(IngameHookError::DuplicateName(name.into()));
         }
-        if (value as usize) < 0x400 {
-            out.data[i] = value as usize;
+    }
+    let handle = entries.next_handle;
+    entries.next_handle += 1;
+    entries.entries.push(IngameHookEntry {
+        handle,
+        chain,
+        cmd,
+        hook,
+        name: options.name.map(|x| x.into()),
+        priority: options.priority,
+        before: options.before.iter().map(|&x| x.into()).collect(),
+        after: options.after.iter().map(|&x| x.into()).collect(),
+    });
+    match entries.update_chain(chain) {
+        Ok(()) => Ok(IngameHookHandle(handle)),
+        Err(e) => {
+            entries.entries.pop();
+            Err(e)
+        }
+    }
+}
+
+/// Removes a hook of any chain. Returns false if it was already removed.
+///
+/// Must not be called from a hook of the same chain.
+pub fn remove_ingame_hook(handle: IngameHookHandle) -> bool {
+    let mut entries = INGAME_HOOK_ENTRIES.lock();
+    let index = match entries.entries.iter().position(|x| x.handle == handle.0) {
+        Some(s) => s,
+        None => return false,
+    };
+    let chain = entries.entries.remove(index).chain;
+    // Removing hooks can't add cycles
+    entries.update_chain(chain).unwrap();
+    true
+}
+
+/// Identifies a hook added with `add_ingame_hook`.
+///
+/// Dropping the handle doesn't remove the hook.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct IngameHookHandle(u64);
+
+impl IngameHookHandle {
+    pub fn remove(self) -> bool {
+        remove_ingame_hook(self)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IngameHookOptions<'a> {
+    /// Name that other hooks can refer to in `before` / `after`; must be unique.
+    pub name: Option<&'a str>,
+    pub priority: i32,
+    /// Names of hooks that this hook must be called before.
+    pub before: &'a [&'a str],
+    /// Names of hooks that this hook must be called after.
+    pub after: &'a [&'a str],
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IngameHookError {
+    DuplicateName(String),
+    /// The `before` / `after` constraints of the hook can't be satisfied.
+    Cycle(String),
+}
+
+impl fmt::Display for IngameHookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IngameHookError::DuplicateName(ref name) => {
+                write!(f, "Ingame command hook \"{}\" was already added", name)
+            }
+            IngameHookError::Cycle(ref name) => {
+                write!(f, "Ingame command hook \"{}\" has cyclic ordering constraints", name)
+            }
         }
     }
-    out.data[0x6] = save_command_length as *const() as usize;
-    out.data[0x7] = save_command_length as *const() as usize;
-    out.data[0x9] = select_command_legacy_length as *const() as usize;
-    out.data[0xa] = select_command_legacy_length as *const() as usize;
-    out.data[0xb] = select_command_legacy_length as *const() as usize;
-    out.data[0x63] = select_command_extended_length as *const() as usize;
-    out.data[0x64] = select_command_extended_length as *const() as usize;
-    out.data[0x65] = select_command_extended_length as *const() as usize;
-    // This isn't set correctly for whatever reason, and it works out for bw since
-    // it's not replay skipped
-    out.data[0x37] = 7;
 }
 
-pub fn add_ingame_hook(cmd: u8, hook: IngameCommandHook) {
-    INGAME_HOOKS.write().push((cmd, hook));
+impl std::error::Error for IngameHookError {}
+
+struct IngameHookEntries {
+    entries: Vec<IngameHookEntry>,
+    next_handle: u64,
+}
+
+struct IngameHookEntry {
+    handle: u64,
+    chain: CommandChain,
+    cmd: u8,
+    hook: IngameCommandHook,
+    name: Option<String>,
+    priority: i32,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+impl IngameHookEntry {
+    /// Returns true if `self` has to be called before `other`.
+    fn must_precede(&self, other: &IngameHookEntry) -> bool {
+        if self.cmd != other.cmd || self.chain != other.chain {
+            return false;
+        }
+        let named = |name: &Option<String>, list: &[String]| match *name {
+            Some(ref name) => list.contains(name),
+            None => false,
+        };
+        named(&other.name, &self.before) || named(&self.name, &other.after)
+    }
+}
+
+impl IngameHookEntries {
+    /// Rebuilds the hook list of `chain`.
+    fn update_chain(&self, chain: CommandChain) -> Result<(), IngameHookError> {
+        let order = self.order(chain)?;
+        *chain.hooks().write() = order;
+        Ok(())
+    }
+
+    /// Returns the hooks of `chain` in calling order.
+    ///
+    /// Hooks are sorted by priority and then by insertion, after which every hook is
+    /// placed at the first position where all of the hooks that it must be after
+    /// have been placed.
+    fn order(&self, chain: CommandChain) -> Result<Vec<(u8, IngameCommandHook)>, IngameHookError> {
+        let mut remaining = self.entries.iter().filter(|x| x.chain == chain).collect::<Vec<_>>();
+        // Stable, so insertion order is kept
+        remaining.sort_by_key(|x| std::cmp::Reverse(x.priority));
+        let mut result = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let next = (0..remaining.len()).find(|&i| {
+                let entry = remaining[i];
+                !remaining.iter().any(|other| other.must_precede(entry))
+            });
+            match next {
+                Some(i) => {
+                    let entry = remaining.remove(i);
+                    result.push((entry.cmd, entry.hook));
+                }
+                None => {
+                    // The previous order was valid, so the cycle has to involve
+                    // the hook that was just added.
+                    let newest = remaining.iter().max_by_key(|x| x.handle).unwrap();
+                    let name = newest.name.clone().unwrap_or_default();
+                    return Err(IngameHookError::Cycle(name));
+                }
+            }
+        }
+        Ok(result)
+    }
 }
 
 pub fn add_length_override(cmd: u8, fun: CommandLength) {
-    let mut out = COMMAND_LENGTHS_MUTABLE.lock();
-    out.data[cmd as usize] = fun as usize;
+    COMMAND_LENGTHS.set(cmd, fun as usize, warn_if_processed);
 }
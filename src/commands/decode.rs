@@ -0,0 +1,585 @@
+//! Decoding of the ingame commands BW and SC:R send.
+//!
+//! The length of every command is taken from a `CommandLengths` table, so that a buffer
+//! is split the same way `ingame_hook` splits it. Commands with a known layout are then
+//! decoded to their fields, anything else is returned as `Command::Other`. If the table
+//! disagrees with the layout, e.g. due to `add_length_override`, decoding fails instead
+//! of reading the fields at wrong positions.
+
+use std::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::{get_command_lengths, CommandLengths};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// There was no command id.
+    Empty,
+    /// The command `id` requires `needed` bytes, but only `have` were available.
+    Truncated { id: u8, needed: usize, have: usize },
+    /// Save / load command filename isn't null-terminated.
+    Unterminated { id: u8 },
+    /// `field` of command `id` has a value that the game doesn't accept.
+    InvalidField { id: u8, field: &'static str, value: u32 },
+    /// The length table entry of command `id` was not set, or is 0.
+    UnknownLength { id: u8 },
+    /// The length table gives command `id` a different length than its layout has.
+    LengthMismatch { id: u8, layout: usize, table: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Empty => write!(f, "Empty command"),
+            DecodeError::Truncated { id, needed, have } => write!(
+                f,
+                "Command {:x} is truncated: needs {} bytes, got {}",
+                id, needed, have,
+            ),
+            DecodeError::Unterminated { id } => {
+                write!(f, "Command {:x} filename is not terminated", id)
+            }
+            DecodeError::InvalidField { id, field, value } => {
+                write!(f, "Command {:x} has invalid {} {:x}", id, field, value)
+            }
+            DecodeError::UnknownLength { id } => {
+                write!(f, "Command {:x} has unknown length", id)
+            }
+            DecodeError::LengthMismatch { id, layout, table } => write!(
+                f,
+                "Command {:x} has length {} in the length table, but its layout has {}",
+                id, table, layout,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Unit reference in a command.
+///
+/// Legacy commands use 16-bit ids (unit index + 1, with generation in the high bits);
+/// SC:R added commands that use 32-bit ids.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum UnitRef {
+    Legacy(u16),
+    Extended(u32),
+}
+
+impl UnitRef {
+    pub fn raw(self) -> u32 {
+        match self {
+            UnitRef::Legacy(x) => x as u32,
+            UnitRef::Extended(x) => x,
+        }
+    }
+}
+
+/// Units of a selection command.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UnitRefs<'a> {
+    data: &'a [u8],
+    extended: bool,
+}
+
+impl<'a> UnitRefs<'a> {
+    pub fn is_extended(&self) -> bool {
+        self.extended
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() / self.entry_size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<UnitRef> {
+        let size = self.entry_size();
+        let entry = self.data.get(index * size..(index + 1) * size)?;
+        Some(match self.extended {
+            false => UnitRef::Legacy(LittleEndian::read_u16(entry)),
+            true => UnitRef::Extended(LittleEndian::read_u32(entry)),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = UnitRef> + 'a {
+        let copy = *self;
+        (0..self.len()).map(move |i| copy.get(i).unwrap())
+    }
+
+    fn entry_size(&self) -> usize {
+        if self.extended { 4 } else { 2 }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SelectMode {
+    /// 0x09 / 0x63
+    Select,
+    /// 0x0a / 0x64
+    ShiftSelect,
+    /// 0x0b / 0x65
+    ShiftDeselect,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HotkeyAction {
+    Assign,
+    Select,
+    Add,
+}
+
+/// A decoded command.
+///
+/// Byte strings are borrowed from the command data. `queued` fields are true if the order
+/// was shift-queued.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Command<'a> {
+    /// 0x05
+    KeepAlive,
+    /// 0x06
+    SaveGame { unk: u32, filename: &'a [u8] },
+    /// 0x07
+    LoadGame { unk: u32, filename: &'a [u8] },
+    /// 0x08
+    RestartGame,
+    /// 0x09 - 0x0b, 0x63 - 0x65
+    Select { mode: SelectMode, units: UnitRefs<'a> },
+    /// 0x0c
+    Build { order: u8, x: u16, y: u16, unit_id: u16 },
+    /// 0x0d
+    Vision { players: u16 },
+    /// 0x0e
+    Alliance { players: u32 },
+    /// 0x0f
+    GameSpeed { speed: u8 },
+    /// 0x10
+    Pause,
+    /// 0x11
+    Resume,
+    /// 0x12
+    Cheat { flags: u32 },
+    /// 0x13
+    Hotkey { action: HotkeyAction, group: u8 },
+    /// 0x14, 0x60
+    RightClick { x: u16, y: u16, target: UnitRef, fow_unit_id: u16, queued: bool },
+    /// 0x15, 0x61
+    TargetedOrder {
+        x: u16,
+        y: u16,
+        target: UnitRef,
+        fow_unit_id: u16,
+        order: u8,
+        queued: bool,
+    },
+    /// 0x18
+    CancelBuild,
+    /// 0x19
+    CancelMorph,
+    /// 0x1a
+    Stop { queued: bool },
+    /// 0x1b
+    CarrierStop,
+    /// 0x1c
+    ReaverStop,
+    /// 0x1d
+    OrderNothing,
+    /// 0x1e
+    ReturnCargo { queued: bool },
+    /// 0x1f
+    Train { unit_id: u16 },
+    /// 0x20
+    CancelTrain { slot: u16 },
+    /// 0x21
+    Cloak { queued: bool },
+    /// 0x22
+    Decloak { queued: bool },
+    /// 0x23
+    UnitMorph { unit_id: u16 },
+    /// 0x25
+    Unsiege { queued: bool },
+    /// 0x26
+    Siege { queued: bool },
+    /// 0x27
+    TrainFighter,
+    /// 0x28
+    UnloadAll { queued: bool },
+    /// 0x29, 0x62
+    Unload { target: UnitRef },
+    /// 0x2a
+    MergeArchon,
+    /// 0x2b
+    HoldPosition { queued: bool },
+    /// 0x2c
+    Burrow { queued: bool },
+    /// 0x2d
+    Unburrow { queued: bool },
+    /// 0x2e
+    CancelNuke,
+    /// 0x2f
+    LiftOff { x: u16, y: u16 },
+    /// 0x30
+    Research { tech: u8 },
+    /// 0x31
+    CancelResearch,
+    /// 0x32
+    Upgrade { upgrade: u8 },
+    /// 0x33
+    CancelUpgrade,
+    /// 0x34
+    CancelAddon,
+    /// 0x35
+    BuildingMorph { unit_id: u16 },
+    /// 0x36
+    Stim,
+    /// 0x57
+    LeaveGame { reason: u8 },
+    /// 0x58
+    MinimapPing { x: u16, y: u16 },
+    /// 0x5a
+    MergeDarkArchon,
+    /// 0x5c, `message` is cut at the first null.
+    Chat { player: u8, message: &'a [u8] },
+    /// Any command without a known layout, `data` includes the id.
+    Other { id: u8, data: &'a [u8] },
+}
+
+/// Length of the 0x5c chat message buffer.
+pub const CHAT_MESSAGE_LEN: usize = 80;
+
+/// Length of commands that have a fixed length and known layout.
+fn fixed_len(id: u8) -> Option<usize> {
+    Some(match id {
+        0x05 | 0x08 | 0x10 | 0x11 | 0x18 | 0x19 | 0x1b | 0x1c | 0x1d | 0x27 | 0x2a |
+            0x2e | 0x31 | 0x33 | 0x34 | 0x36 | 0x5a => 1,
+        0x0f | 0x1a | 0x1e | 0x21 | 0x22 | 0x25 | 0x26 | 0x28 | 0x2b | 0x2c | 0x2d |
+            0x30 | 0x32 | 0x57 => 2,
+        0x0d | 0x13 | 0x1f | 0x20 | 0x23 | 0x29 | 0x35 => 3,
+        0x0e | 0x12 | 0x2f | 0x58 | 0x62 => 5,
+        0x0c => 8,
+        0x14 => 10,
+        0x15 => 11,
+        0x60 => 12,
+        0x61 => 13,
+        0x5c => 2 + CHAT_MESSAGE_LEN,
+        _ => return None,
+    })
+}
+
+/// Returns length of a command according to its known layout, `None` for other commands.
+///
+/// Errors if `cmd` is shorter than the command. `decode` uses the length table instead,
+/// this is only for checking it.
+pub fn known_command_len(cmd: &[u8]) -> Result<Option<usize>, DecodeError> {
+    let id = *cmd.first().ok_or(DecodeError::Empty)?;
+    let len = match id {
+        0x06 | 0x07 => {
+            check_len(cmd, 6)?;
+            match cmd[5..].iter().position(|&x| x == 0) {
+                Some(pos) => 5 + pos + 1,
+                None => return Err(DecodeError::Unterminated { id }),
+            }
+        }
+        0x09..=0x0b => {
+            check_len(cmd, 2)?;
+            cmd[1] as usize * 2 + 2
+        }
+        0x63..=0x65 => {
+            check_len(cmd, 2)?;
+            cmd[1] as usize * 4 + 2
+        }
+        _ => match fixed_len(id) {
+            Some(s) => s,
+            None => return Ok(None),
+        },
+    };
+    check_len(cmd, len)?;
+    Ok(Some(len))
+}
+
+fn check_len(cmd: &[u8], needed: usize) -> Result<(), DecodeError> {
+    if cmd.len() < needed {
+        Err(DecodeError::Truncated {
+            id: cmd[0],
+            needed,
+            have: cmd.len(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Decodes the first command of `data`, returning it and its length.
+///
+/// The length is taken from `get_command_lengths`.
+pub fn decode(data: &[u8]) -> Result<(Command<'_>, usize), DecodeError> {
+    decode_with(get_command_lengths(), data)
+}
+
+/// Decodes the first command of `data`, taking its length from `lengths`.
+pub fn decode_with<'a>(
+    lengths: &CommandLengths,
+    data: &'a [u8],
+) -> Result<(Command<'a>, usize), DecodeError> {
+    let id = *data.first().ok_or(DecodeError::Empty)?;
+    if !lengths.is_set(id) {
+        return Err(DecodeError::UnknownLength { id });
+    }
+    let len = lengths.command_len(data);
+    if len == 0 {
+        return Err(DecodeError::UnknownLength { id });
+    }
+    let layout = known_command_len(data);
+    if len > data.len() {
+        // Prefer the more specific error of the layout
+        layout?;
+        check_len(data, len)?;
+    }
+    match layout {
+        Ok(None) => return Ok((Command::Other { id, data: &data[..len] }, len)),
+        Ok(Some(layout)) if layout == len => (),
+        Ok(Some(layout)) | Err(DecodeError::Truncated { needed: layout, .. }) => {
+            return Err(DecodeError::LengthMismatch { id, layout, table: len });
+        }
+        Err(e) => return Err(e),
+    }
+    let cmd = &data[..len];
+    let u8_at = |pos: usize| cmd[pos];
+    let u16_at = |pos: usize| LittleEndian::read_u16(&cmd[pos..]);
+    let u32_at = |pos: usize| LittleEndian::read_u32(&cmd[pos..]);
+    let queued = || cmd[1] != 0;
+    let id = cmd[0];
+    let command = match id {
+        0x05 => Command::KeepAlive,
+        0x06 | 0x07 => {
+            let unk = u32_at(1);
+            let filename = &cmd[5..len - 1];
+            match id {
+                0x06 => Command::SaveGame { unk, filename },
+                _ => Command::LoadGame { unk, filename },
+            }
+        }
+        0x08 => Command::RestartGame,
+        0x09 | 0x0a | 0x0b | 0x63 | 0x64 | 0x65 => {
+            if cmd[1] == 0 {
+                return Err(DecodeError::InvalidField { id, field: "unit count", value: 0 });
+            }
+            let mode = match id {
+                0x09 | 0x63 => SelectMode::Select,
+                0x0a | 0x64 => SelectMode::ShiftSelect,
+                _ => SelectMode::ShiftDeselect,
+            };
+            let units = UnitRefs {
+                data: &cmd[2..],
+                extended: id >= 0x63,
+            };
+            Command::Select { mode, units }
+        }
+        0x0c => Command::Build {
+            order: u8_at(1),
+            x: u16_at(2),
+            y: u16_at(4),
+            unit_id: u16_at(6),
+        },
+        0x0d => Command::Vision { players: u16_at(1) },
+        0x0e => Command::Alliance { players: u32_at(1) },
+        0x0f => Command::GameSpeed { speed: u8_at(1) },
+        0x10 => Command::Pause,
+        0x11 => Command::Resume,
+        0x12 => Command::Cheat { flags: u32_at(1) },
+        0x13 => {
+            let action = match u8_at(1) {
+                0 => HotkeyAction::Assign,
+                1 => HotkeyAction::Select,
+                2 => HotkeyAction::Add,
+                x => {
+                    return Err(DecodeError::InvalidField {
+                        id,
+                        field: "hotkey action",
+                        value: x as u32,
+                    });
+                }
+            };
+            Command::Hotkey { action, group: u8_at(2) }
+        }
+        0x14 => Command::RightClick {
+            x: u16_at(1),
+            y: u16_at(3),
+            target: UnitRef::Legacy(u16_at(5)),
+            fow_unit_id: u16_at(7),
+            queued: u8_at(9) != 0,
+        },
+        0x60 => Command::RightClick {
+            x: u16_at(1),
+            y: u16_at(3),
+            target: UnitRef::Extended(u32_at(5)),
+            fow_unit_id: u16_at(9),
+            queued: u8_at(11) != 0,
+        },
+        0x15 => Command::TargetedOrder {
+            x: u16_at(1),
+            y: u16_at(3),
+            target: UnitRef::Legacy(u16_at(5)),
+            fow_unit_id: u16_at(7),
+            order: u8_at(9),
+            queued: u8_at(10) != 0,
+        },
+        0x61 => Command::TargetedOrder {
+            x: u16_at(1),
+            y: u16_at(3),
+            target: UnitRef::Extended(u32_at(5)),
+            fow_unit_id: u16_at(9),
+            order: u8_at(11),
+            queued: u8_at(12) != 0,
+        },
+        0x18 => Command::CancelBuild,
+        0x19 => Command::CancelMorph,
+        0x1a => Command::Stop { queued: queued() },
+        0x1b => Command::CarrierStop,
+        0x1c => Command::ReaverStop,
+        0x1d => Command::OrderNothing,
+        0x1e => Command::ReturnCargo { queued: queued() },
+        0x1f => Command::Train { unit_id: u16_at(1) },
+        0x20 => Command::CancelTrain { slot: u16_at(1) },
+        0x21 => Command::Cloak { queued: queued() },
+        0x22 => Command::Decloak { queued: queued() },
+        0x23 => Command::UnitMorph { unit_id: u16_at(1) },
+        0x25 => Command::Unsiege { queued: queued() },
+        0x26 => Command::Siege { queued: queued() },
+        0x27 => Command::TrainFighter,
+        0x28 => Command::UnloadAll { queued: queued() },
+        0x29 => Command::Unload { target: UnitRef::Legacy(u16_at(1)) },
+        0x62 => Command::Unload { target: UnitRef::Extended(u32_at(1)) },
+        0x2a => Command::MergeArchon,
+        0x2b => Command::HoldPosition { queued: queued() },
+        0x2c => Command::Burrow { queued: queued() },
+        0x2d => Command::Unburrow { queued: queued() },
+        0x2e => Command::CancelNuke,
+        0x2f => Command::LiftOff { x: u16_at(1), y: u16_at(3) },
+        0x30 => Command::Research { tech: u8_at(1) },
+        0x31 => Command::CancelResearch,
+        0x32 => Command::Upgrade { upgrade: u8_at(1) },
+        0x33 => Command::CancelUpgrade,
+        0x34 => Command::CancelAddon,
+        0x35 => Command::BuildingMorph { unit_id: u16_at(1) },
+        0x36 => Command::Stim,
+        0x57 => Command::LeaveGame { reason: u8_at(1) },
+        0x58 => Command::MinimapPing { x: u16_at(1), y: u16_at(3) },
+        0x5a => Command::MergeDarkArchon,
+        0x5c => {
+            let message = &cmd[2..];
+            let end = message.iter().position(|&x| x == 0).unwrap_or(message.len());
+            Command::Chat { player: u8_at(1), message: &message[..end] }
+        }
+        _ => unreachable!("Command {:x} has length but no layout", id),
+    };
+    Ok((command, len))
+}
+
+/// Iterator decoding every command in a buffer.
+///
+/// Stops after the first error, as the position of the next command can't be known.
+pub struct Commands<'a, 'b> {
+    lengths: &'b CommandLengths,
+    data: &'a [u8],
+}
+
+pub fn iter(data: &[u8]) -> Commands<'_, 'static> {
+    iter_with(get_command_lengths(), data)
+}
+
+pub fn iter_with<'a, 'b>(lengths: &'b CommandLengths, data: &'a [u8]) -> Commands<'a, 'b> {
+    Commands { lengths, data }
+}
+
+impl<'a, 'b> Iterator for Commands<'a, 'b> {
+    type Item = Result<Command<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        match decode_with(self.lengths, self.data) {
+            Ok((command, len)) => {
+                self.data = &self.data[len..];
+                Some(Ok(command))
+            }
+            Err(e) => {
+                self.data = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'a> Command<'a> {
+    /// Id of the command; for variants with legacy and SC:R forms the id depends on
+    /// whether the unit references are `Extended`.
+    pub fn id(&self) -> u8 {
+        match *self {
+            Command::KeepAlive => 0x05,
+            Command::SaveGame { .. } => 0x06,
+            Command::LoadGame { .. } => 0x07,
+            Command::RestartGame => 0x08,
+            Command::Select { mode, units } => {
+                let base = if units.extended { 0x63 } else { 0x09 };
+                match mode {
+                    SelectMode::Select => base,
+                    SelectMode::ShiftSelect => base + 1,
+                    SelectMode::ShiftDeselect => base + 2,
+                }
+            }
+            Command::Build { .. } => 0x0c,
+            Command::Vision { .. } => 0x0d,
+            Command::Alliance { .. } => 0x0e,
+            Command::GameSpeed { .. } => 0x0f,
+            Command::Pause => 0x10,
+            Command::Resume => 0x11,
+            Command::Cheat { .. } => 0x12,
+            Command::Hotkey { .. } => 0x13,
+            Command::RightClick { target: UnitRef::Legacy(_), .. } => 0x14,
+            Command::RightClick { target: UnitRef::Extended(_), .. } => 0x60,
+            Command::TargetedOrder { target: UnitRef::Legacy(_), .. } => 0x15,
+            Command::TargetedOrder { target: UnitRef::Extended(_), .. } => 0x61,
+            Command::CancelBuild => 0x18,
+            Command::CancelMorph => 0x19,
+            Command::Stop { .. } => 0x1a,
+            Command::CarrierStop => 0x1b,
+            Command::ReaverStop => 0x1c,
+            Command::OrderNothing => 0x1d,
+            Command::ReturnCargo { .. } => 0x1e,
+            Command::Train { .. } => 0x1f,
+            Command::CancelTrain { .. } => 0x20,
+            Command::Cloak { .. } => 0x21,
+            Command::Decloak { .. } => 0x22,
+            Command::UnitMorph { .. } => 0x23,
+            Command::Unsiege { .. } => 0x25,
+            Command::Siege { .. } => 0x26,
+            Command::TrainFighter => 0x27,
+            Command::UnloadAll { .. } => 0x28,
+            Command::Unload { target: UnitRef::Legacy(_) } => 0x29,
+            Command::Unload { target: UnitRef::Extended(_) } => 0x62,
+            Command::MergeArchon => 0x2a,
+            Command::HoldPosition { .. } => 0x2b,
+            Command::Burrow { .. } => 0x2c,
+            Command::Unburrow { .. } => 0x2d,
+            Command::CancelNuke => 0x2e,
+            Command::LiftOff { .. } => 0x2f,
+            Command::Research { .. } => 0x30,
+            Command::CancelResearch => 0x31,
+            Command::Upgrade { .. } => 0x32,
+            Command::CancelUpgrade => 0x33,
+            Command::CancelAddon => 0x34,
+            Command::BuildingMorph { .. } => 0x35,
+            Command::Stim => 0x36,
+            Command::LeaveGame { .. } => 0x57,
+            Command::MinimapPing { .. } => 0x58,
+            Command::MergeDarkArchon => 0x5a,
+            Command::Chat { .. } => 0x5c,
+            Command::Other { id, .. } => id,
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Building commands for `send_command`.
+//!
+//! ```ignore
+//! let mut buf = CommandBuffer::new();
+//! buf.select(SelectMode::Select, &[UnitRef::Extended(unit_id)])?;
+//! buf.command(&Command::Train { unit_id: 0x7 })?;
+//! api.send_command(buf.as_slice());
+//! ```
+//!
+//! Every command is checked against the `CommandLengths` table after it has been
+//! written, so that a command the game would read with a different length is an error
+//! instead of a desync.
+
+use std::fmt;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use super::decode::{Command, HotkeyAction, SelectMode, UnitRef, CHAT_MESSAGE_LEN};
+use super::{get_command_lengths, CommandLengths};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EncodeError {
+    /// Select commands take 1 to 255 units.
+    UnitCount(usize),
+    /// Select command had both `Legacy` and `Extended` units.
+    MixedUnitRefs,
+    /// Save / load filename contains a null byte.
+    InvalidFilename,
+    /// Chat message doesn't fit in `CHAT_MESSAGE_LEN` bytes with a terminator.
+    ChatTooLong(usize),
+    /// `Command::Other` data doesn't start with its id.
+    OtherIdMismatch { id: u8 },
+    /// The length table expects a different length for command `id`.
+    LengthMismatch { id: u8, encoded: usize, expected: usize },
+    /// The length table entry of command `id` was not set.
+    UnknownLength { id: u8 },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodeError::UnitCount(count) => {
+                write!(f, "Can't select {} units at once", count)
+            }
+            EncodeError::MixedUnitRefs => {
+                write!(f, "Select command mixes legacy and extended unit ids")
+            }
+            EncodeError::InvalidFilename => write!(f, "Filename contains a null byte"),
+            EncodeError::ChatTooLong(len) => {
+                write!(f, "Chat message is too long ({} bytes)", len)
+            }
+            EncodeError::OtherIdMismatch { id } => {
+                write!(f, "Command data for {:x} starts with a different id", id)
+            }
+            EncodeError::UnknownLength { id } => {
+                write!(f, "Command {:x} has unknown length", id)
+            }
+            EncodeError::LengthMismatch { id, encoded, expected } => write!(
+                f,
+                "Command {:x} was encoded with length {}, expected {}",
+                id, encoded, expected,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// Buffer of encoded commands.
+///
+/// A command that fails to encode isn't added to the buffer.
+pub struct CommandBuffer<'a> {
+    lengths: &'a CommandLengths,
+    buf: Vec<u8>,
+}
+
+impl CommandBuffer<'static> {
+    /// Creates a buffer checking lengths against `get_command_lengths`.
+    pub fn new() -> CommandBuffer<'static> {
+        CommandBuffer::with_lengths(get_command_lengths())
+    }
+}
+
+impl Default for CommandBuffer<'static> {
+    fn default() -> Self {
+        CommandBuffer::new()
+    }
+}
+
+impl<'a> CommandBuffer<'a> {
+    pub fn with_lengths(lengths: &'a CommandLengths) -> CommandBuffer<'a> {
+        CommandBuffer {
+            lengths,
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Adds a select command for `units`.
+    ///
+    /// `units` must all be either `Legacy`, which encodes 0x09 - 0x0b, or `Extended`,
+    /// which encodes 0x63 - 0x65.
+    pub fn select(&mut self, mode: SelectMode, units: &[UnitRef]) -> Result<&mut Self, EncodeError> {
+        if units.is_empty() || units.len() > 0xff {
+            return Err(EncodeError::UnitCount(units.len()));
+        }
+        let extended = matches!(units[0], UnitRef::Extended(_));
+        if units.iter().any(|x| matches!(x, UnitRef::Extended(_)) != extended) {
+            return Err(EncodeError::MixedUnitRefs);
+        }
+        let base = if extended { 0x63 } else { 0x09 };
+        let id = match mode {
+            SelectMode::Select => base,
+            SelectMode::ShiftSelect => base + 1,
+            SelectMode::ShiftDeselect => base + 2,
+        };
+        self.write(|buf| {
+            buf.push(id);
+            buf.push(units.len() as u8);
+            for &unit in units {
+                write_unit_ref(buf, unit);
+            }
+            Ok(())
+        })
+    }
+
+    /// Adds `command`.
+    pub fn command(&mut self, command: &Command) -> Result<&mut Self, EncodeError> {
+        self.write(|buf| encode_command(buf, command))
+    }
+
+    fn write<F>(&mut self, func: F) -> Result<&mut Self, EncodeError>
+    where F: FnOnce(&mut Vec<u8>) -> Result<(), EncodeError>
+    {
+        let start = self.buf.len();
+        let result = func(&mut self.buf).and_then(|()| {
+            let cmd = &self.buf[start..];
+            if !self.lengths.is_set(cmd[0]) {
+                return Err(EncodeError::UnknownLength { id: cmd[0] });
+            }
+            let expected = self.lengths.command_len(cmd);
+            if expected != cmd.len() {
+                Err(EncodeError::LengthMismatch {
+                    id: cmd[0],
+                    encoded: cmd.len(),
+                    expected,
+                })
+            } else {
+                Ok(())
+            }
+        });
+        match result {
+            Ok(()) => Ok(self),
+            Err(e) => {
+                self.buf.truncate(start);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Encodes a single command, checking its length against `get_command_lengths`.
+pub fn encode(command: &Command) -> Result<Vec<u8>, EncodeError> {
+    encode_with(get_command_lengths(), command)
+}
+
+pub fn encode_with(lengths: &CommandLengths, command: &Command) -> Result<Vec<u8>, EncodeError> {
+    let mut buf = CommandBuffer::with_lengths(lengths);
+    buf.command(command)?;
+    Ok(buf.into_vec())
+}
+
+fn write_unit_ref(buf: &mut Vec<u8>, unit: UnitRef) {
+    match unit {
+        UnitRef::Legacy(x) => buf.write_u16::<LittleEndian>(x).unwrap(),
+        UnitRef::Extended(x) => buf.write_u32::<LittleEndian>(x).unwrap(),
+    }
+}
+
+fn encode_command(buf: &mut Vec<u8>, command: &Command) -> Result<(), EncodeError> {
+    // Writes to a Vec can't fail
+    let u16 = |buf: &mut Vec<u8>, val: u16| buf.write_u16::<LittleEndian>(val).unwrap();
+    let u32 = |buf: &mut Vec<u8>, val: u32| buf.write_u32::<LittleEndian>(val).unwrap();
+    if let Command::Other { id, data } = *command {
+        if data.first() != Some(&id) {
+            return Err(EncodeError::OtherIdMismatch { id });
+        }
+        buf.extend_from_slice(data);
+        return Ok(());
+    }
+    buf.push(command.id());
+    match *command {
+        Command::SaveGame { unk, filename } | Command::LoadGame { unk, filename } => {
+            if filename.contains(&0) {
+                return Err(EncodeError::InvalidFilename);
+            }
+            u32(buf, unk);
+            buf.extend_from_slice(filename);
+            buf.push(0);
+        }
+        Command::Select { units, .. } => {
+            if units.is_empty() || units.len() > 0xff {
+                return Err(EncodeError::UnitCount(units.len()));
+            }
+            buf.push(units.len() as u8);
+            for unit in units.iter() {
+                write_unit_ref(buf, unit);
+            }
+        }
+        Command::Build { order, x, y, unit_id } => {
+            buf.push(order);
+            u16(buf, x);
+            u16(buf, y);
+            u16(buf, unit_id);
+        }
+        Command::Vision { players } => u16(buf, players),
+        Command::Alliance { players } => u32(buf, players),
+        Command::GameSpeed { speed } => buf.push(speed),
+        Command::Cheat { flags } => u32(buf, flags),
+        Command::Hotkey { action, group } => {
+            buf.push(match action {
+                HotkeyAction::Assign => 0,
+                HotkeyAction::Select => 1,
+                HotkeyAction::Add => 2,
+            });
+            buf.push(group);
+        }
+        Command::RightClick { x, y, target, fow_unit_id, queued } => {
+            u16(buf, x);
+            u16(buf, y);
+            write_unit_ref(buf, target);
+            u16(buf, fow_unit_id);
+            buf.push(queued as u8);
+        }
+        Command::TargetedOrder { x, y, target, fow_unit_id, order, queued } => {
+            u16(buf, x);
+            u16(buf, y);
+            write_unit_ref(buf, target);
+            u16(buf, fow_unit_id);
+            buf.push(order);
+            buf.push(queued as u8);
+        }
+        Command::Stop { queued } | Command::ReturnCargo { queued } |
+            Command::Cloak { queued } | Command::Decloak { queued } |
+            Command::Unsiege { queued } | Command::Siege { queued } |
+            Command::UnloadAll { queued } | Command::HoldPosition { queued } |
+            Command::Burrow { queued } | Command::Unburrow { queued } =>
+        {
+            buf.push(queued as u8);
+        }
+        Command::Train { unit_id } | Command::UnitMorph { unit_id } |
+            Command::BuildingMorph { unit_id } =>
+        {
+            u16(buf, unit_id);
+        }
+        Command::CancelTrain { slot } => u16(buf, slot),
+        Command::Unload { target } => write_unit_ref(buf, target),
+        Command::LiftOff { x, y } | Command::MinimapPing { x, y } => {
+            u16(buf, x);
+            u16(buf, y);
+        }
+        Command::Research { tech } => buf.push(tech),
+        Command::Upgrade { upgrade } => buf.push(upgrade),
+        Command::LeaveGame { reason } => buf.push(reason),
+        Command::Chat { player, message } => {
+            if message.len() >= CHAT_MESSAGE_LEN {
+                return Err(EncodeError::ChatTooLong(message.len()));
+            }
+            buf.push(player);
+            buf.extend_from_slice(message);
+            buf.resize(buf.len() + CHAT_MESSAGE_LEN - message.len(), 0);
+        }
+        Command::KeepAlive | Command::RestartGame | Command::Pause | Command::Resume |
+            Command::CancelBuild | Command::CancelMorph | Command::CarrierStop |
+            Command::ReaverStop | Command::OrderNothing | Command::TrainFighter |
+            Command::MergeArchon | Command::CancelNuke | Command::CancelResearch |
+            Command::CancelUpgrade | Command::CancelAddon | Command::Stim |
+            Command::MergeDarkArchon => (),
+        Command::Other { .. } => unreachable!(),
+    }
+    Ok(())
+}
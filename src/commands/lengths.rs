@@ -0,0 +1,169 @@
+//! Default command length tables of 1.16.1 and SC:R.
+//!
+//! The tables have the same layout as `bw::command_lengths`: one `u32` per command id,
+//! with `NO_LENGTH` for ids that aren't commands or don't have a fixed length. They can
+//! be given to `set_default_command_lengths` as is, which also sets the length functions
+//! of the variable length commands (0x6, 0x7, 0x9 - 0xb, 0x63 - 0x65).
+//!
+//! Unlike the game's own table, 0x37 (sync) has its actual length of 7.
+
+use std::fmt;
+
+use super::{set_default_command_lengths, CommandLengths};
+
+/// Table value of ids that have no fixed length.
+pub const NO_LENGTH: u32 = u32::MAX;
+
+/// Game whose command ids are used.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    V1161,
+    Scr,
+}
+
+const N: u32 = NO_LENGTH;
+
+/// `bw::command_lengths` of 1.16.1, with 0x37 fixed.
+#[rustfmt::skip]
+pub static V1161_LENGTHS: [u32; 0x60] = [
+    // 0x00: 0x05 keep alive, 0x06 / 0x07 save / load game, 0x08 restart,
+    // 0x09 - 0x0b select / shift select / shift deselect, 0x0c build, 0x0d vision,
+    // 0x0e alliance, 0x0f game speed
+    N, N, N, N, N, 1, N, N, 1, N, N, N, 8, 3, 5, 2,
+    // 0x10: 0x10 / 0x11 pause / resume, 0x12 cheat, 0x13 hotkey, 0x14 right click,
+    // 0x15 targeted order, 0x18 / 0x19 cancel build / morph, 0x1a stop,
+    // 0x1b / 0x1c carrier / reaver stop, 0x1d order nothing, 0x1e return cargo, 0x1f train
+    1, 1, 5, 3, 10, 11, N, N, 1, 1, 2, 1, 1, 1, 2, 3,
+    // 0x20: 0x20 cancel train, 0x21 / 0x22 cloak / decloak, 0x23 unit morph,
+    // 0x25 / 0x26 unsiege / siege, 0x27 train fighter, 0x28 unload all, 0x29 unload,
+    // 0x2a merge archon, 0x2b hold position, 0x2c / 0x2d burrow / unburrow,
+    // 0x2e cancel nuke, 0x2f lift off
+    3, 2, 2, 3, N, 2, 2, 1, 2, 3, 1, 2, 2, 2, 1, 5,
+    // 0x30: 0x30 / 0x31 tech / cancel tech, 0x32 / 0x33 upgrade / cancel upgrade,
+    // 0x34 cancel addon, 0x35 building morph, 0x36 stim, 0x37 sync,
+    // 0x38 - 0x3b voice enable / disable / squelch / unsquelch, 0x3c start game,
+    // 0x3d download percentage, 0x3e change game slot, 0x3f new net player
+    2, 1, 2, 1, 1, 3, 1, 7, 2, 2, 2, 2, 1, 2, 6, 8,
+    // 0x40: 0x40 joined game, 0x41 change race, 0x42 team game team, 0x43 ums team,
+    // 0x44 melee team, 0x45 swap players, 0x48 saved data
+    18, 3, 2, 2, 3, 3, N, N, 13, N, N, N, N, N, N, N,
+    // 0x50: 0x54 briefing start, 0x55 latency, 0x56 replay speed, 0x57 leave game,
+    // 0x58 minimap ping, 0x5a merge dark archon, 0x5b make game public, 0x5c chat
+    N, N, N, N, 1, 2, 10, 2, 5, N, 1, 1, 82, N, N, N,
+];
+
+/// Lengths of the commands that SC:R added, starting from id 0x60.
+///
+/// 0x60 right click, 0x61 targeted order, 0x62 unload; 0x63 - 0x65 are the select
+/// commands with 32-bit unit ids.
+const SCR_EXTENDED_LENGTHS: [u32; 6] = [12, 13, 5, N, N, N];
+
+/// Command length table of SC:R; 1.16.1 commands followed by the extended ones.
+pub static SCR_LENGTHS: [u32; 0x66] = {
+    let mut result = [N; 0x66];
+    let mut i = 0;
+    while i < V1161_LENGTHS.len() {
+        result[i] = V1161_LENGTHS[i];
+        i += 1;
+    }
+    let mut i = 0;
+    while i < SCR_EXTENDED_LENGTHS.len() {
+        result[0x60 + i] = SCR_EXTENDED_LENGTHS[i];
+        i += 1;
+    }
+    result
+};
+
+impl Target {
+    pub fn lengths(self) -> &'static [u32] {
+        match self {
+            Target::V1161 => &V1161_LENGTHS,
+            Target::Scr => &SCR_LENGTHS,
+        }
+    }
+}
+
+/// Creates a length table for `target` without affecting the global table.
+///
+/// Can be used to split command streams outside the game.
+pub fn command_lengths(target: Target) -> CommandLengths {
+    CommandLengths::with_defaults(target.lengths())
+}
+
+/// Calls `set_default_command_lengths` with the table of `target`.
+pub fn set_defaults(target: Target) {
+    set_default_command_lengths(target.lengths());
+}
+
+/// An entry where a host-provided table differs from the embedded one.
+///
+/// `None` means that the table has no fixed length for the id.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LengthDifference {
+    pub id: u8,
+    pub expected: Option<u32>,
+    pub host: Option<u32>,
+}
+
+impl fmt::Display for LengthDifference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Command {:x} has length ", self.id)?;
+        match self.host {
+            Some(len) => write!(f, "{}", len)?,
+            None => write!(f, "none")?,
+        }
+        match self.expected {
+            Some(len) => write!(f, ", expected {}", len),
+            None => write!(f, ", expected none"),
+        }
+    }
+}
+
+fn fixed(value: u32) -> Option<u32> {
+    // Same limit that `set_default_command_lengths` uses
+    if value < 0x400 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Compares `host` against the table of `target`.
+///
+/// Only ids that `host` has entries for are compared. Ids which
+/// `set_default_command_lengths` always overrides are skipped, as their value doesn't
+/// matter.
+pub fn diff(target: Target, host: &[u32]) -> Vec<LengthDifference> {
+    let expected = target.lengths();
+    host.iter()
+        .enumerate()
+        .take(0x100)
+        .filter(|&(id, _)| !is_overridden(id as u8))
+        .filter_map(|(id, &value)| {
+            let host = fixed(value);
+            let expected = expected.get(id).and_then(|&x| fixed(x));
+            if host != expected {
+                Some(LengthDifference {
+                    id: id as u8,
+                    expected,
+                    host,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Like `diff`, but also logs a warning for each difference.
+pub fn check(target: Target, host: &[u32]) -> Vec<LengthDifference> {
+    let result = diff(target, host);
+    for difference in &result {
+        warn!("{}", difference);
+    }
+    result
+}
+
+fn is_overridden(id: u8) -> bool {
+    matches!(id, 0x6 | 0x7 | 0x9..=0xb | 0x37 | 0x63..=0x65)
+}
@@ -0,0 +1,276 @@
+//! Plugin commands sharing a single command id.
+//!
+//! Raw command ids picked by plugins may collide without anyone noticing. Plugin
+//! commands instead use `PLUGIN_COMMAND_ID`, with the command data being
+//!
+//! ```text
+//! u8 PLUGIN_COMMAND_ID
+//! u8 tag_len
+//! [u8; tag_len] tag
+//! u16 payload_len
+//! [u8; payload_len] payload
+//! ```
+//!
+//! Hooks are registered by tag, similar to `extend_save`, and are called only with the
+//! payload of commands using that tag. Every plugin has its own copy of the registry, and
+//! commands with tags that aren't registered in it are passed on to the next hook of the
+//! command chain, so that other plugins' registries see them too. Registering a tag
+//! twice within one plugin is an error.
+//!
+//! Tags registered by other plugins can't be seen when registering, so after a registry
+//! handles a command, it passes a claim notice down the chain instead of the command.
+//! The notice is a plugin command with an empty tag, and the handled tag as its payload.
+//! Registries that have the same tag don't run their hook, but log a warning and
+//! remember the tag, see `Registry::duplicate_tags`. The first plugin in the chain keeps
+//! receiving the commands.
+
+use std::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+use parking_lot::{const_rwlock, RwLock};
+
+use super::{add_ingame_hook, add_length_override, replay, IngameCommandHook};
+
+/// Command id reserved for plugin commands; unused by BW and SC:R.
+pub const PLUGIN_COMMAND_ID: u8 = 0xc0;
+
+pub const MAX_TAG_LEN: usize = 0xff;
+/// Longest encoded plugin command, including the tag and header.
+///
+/// Limited to what `replay::wrap` can store, so that hosts writing commands
+/// through it can record every plugin command.
+pub const MAX_COMMAND_LEN: usize = replay::MAX_WRAPPED_LEN;
+
+static REGISTRY: Registry = Registry::new();
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PluginCommandError {
+    EmptyTag,
+    TagTooLong(usize),
+    PayloadTooLong(usize),
+    /// Another hook was already registered with the tag in the same registry.
+    TagInUse(String),
+}
+
+impl fmt::Display for PluginCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PluginCommandError::EmptyTag => write!(f, "Plugin command tag is empty"),
+            PluginCommandError::TagTooLong(len) => {
+                write!(f, "Plugin command tag is too long ({} bytes)", len)
+            }
+            PluginCommandError::PayloadTooLong(len) => {
+                write!(f, "Plugin command payload is too long ({} bytes)", len)
+            }
+            PluginCommandError::TagInUse(ref tag) => {
+                write!(f, "Plugin command tag \"{}\" is already registered", tag)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PluginCommandError {}
+
+fn check_tag(tag: &str) -> Result<(), PluginCommandError> {
+    if tag.is_empty() {
+        Err(PluginCommandError::EmptyTag)
+    } else if tag.len() > MAX_TAG_LEN {
+        Err(PluginCommandError::TagTooLong(tag.len()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Adds the length function and hook for `PLUGIN_COMMAND_ID`.
+///
+/// Should be called once, at the same time as `set_default_command_lengths`.
+pub fn enable() {
+    add_length_override(PLUGIN_COMMAND_ID, plugin_command_length);
+    add_ingame_hook(PLUGIN_COMMAND_ID, plugin_command_hook);
+}
+
+/// Registers `hook` to receive payloads of plugin commands with `tag`.
+///
+/// Use `duplicate_tags` to check if other plugins have registered the same tags.
+///
+/// `hook` receives only the payload as its data; the `orig` function it receives does
+/// nothing, as the game doesn't know plugin commands.
+pub fn register(tag: &str, hook: IngameCommandHook) -> Result<(), PluginCommandError> {
+    REGISTRY.register(tag, hook)
+}
+
+/// Tags and their hooks.
+///
+/// `register` and `enable` use a global registry; separate ones are only needed when
+/// dispatching commands outside the ingame command hook.
+pub struct Registry {
+    hooks: RwLock<Vec<(Vec<u8>, IngameCommandHook)>>,
+    /// Tags of this registry that an earlier registry in the chain has handled.
+    duplicates: RwLock<Vec<Vec<u8>>>,
+}
+
+impl Registry {
+    pub const fn new() -> Registry {
+        Registry {
+            hooks: const_rwlock(Vec::new()),
+            duplicates: const_rwlock(Vec::new()),
+        }
+    }
+
+    /// Fails with `TagInUse` only if `tag` was registered to this registry.
+    pub fn register(&self, tag: &str, hook: IngameCommandHook) -> Result<(), PluginCommandError> {
+        check_tag(tag)?;
+        let mut hooks = self.hooks.write();
+        if hooks.iter().any(|x| x.0 == tag.as_bytes()) {
+            return Err(PluginCommandError::TagInUse(tag.into()));
+        }
+        hooks.push((tag.as_bytes().into(), hook));
+        Ok(())
+    }
+
+    /// Calls the hook registered for the tag of `cmd`, or `orig` with `cmd` if the tag
+    /// isn't registered.
+    ///
+    /// After the hook, `orig` is called with a claim notice of the tag, which other
+    /// registries use to detect duplicate tags. Claim notices are checked against the
+    /// registered tags and passed on.
+    ///
+    /// # Safety
+    ///
+    /// The hooks and `orig` must be safe to call with any command; `cmd` is passed to
+    /// them as a pointer that is only valid during the call.
+    pub unsafe fn dispatch(
+        &self,
+        cmd: &[u8],
+        player: u32,
+        unique_player: u32,
+        orig: unsafe extern "C" fn(*const u8, u32),
+    ) {
+        unsafe extern "C" fn no_orig(_: *const u8, _: u32) {
+        }
+
+        let (tag, payload) = match decode(cmd) {
+            Some(s) => s,
+            None => {
+                error!("Invalid plugin command of length {:x}", cmd.len());
+                return;
+            }
+        };
+        if tag.is_empty() {
+            self.check_claim(payload);
+            orig(cmd.as_ptr(), cmd.len() as u32);
+            return;
+        }
+        let hook = self.hooks.read().iter().find(|x| x.0 == tag).map(|x| x.1);
+        match hook {
+            Some(hook) => {
+                hook(payload.as_ptr(), payload.len() as u32, player, unique_player, no_orig);
+                let claim = claim_notice(tag);
+                orig(claim.as_ptr(), claim.len() as u32);
+            }
+            None => {
+                trace!("Passing on plugin command \"{}\"", String::from_utf8_lossy(tag));
+                orig(cmd.as_ptr(), cmd.len() as u32);
+            }
+        }
+    }
+
+    fn check_claim(&self, tag: &[u8]) {
+        if !self.hooks.read().iter().any(|x| x.0 == tag) {
+            return;
+        }
+        let mut duplicates = self.duplicates.write();
+        if !duplicates.iter().any(|x| x == tag) {
+            warn!(
+                "Plugin command tag \"{}\" is registered by multiple plugins, \
+                only the first one in the command chain receives the commands",
+                String::from_utf8_lossy(tag),
+            );
+            duplicates.push(tag.into());
+        }
+    }
+
+    /// Returns tags registered to this registry that were also handled by another
+    /// registry earlier in the command chain.
+    ///
+    /// The duplicates are only noticed when commands using the tag are dispatched.
+    pub fn duplicate_tags(&self) -> Vec<String> {
+        self.duplicates.read().iter().map(|x| String::from_utf8_lossy(x).into()).collect()
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+/// Returns tags of the global registry that other plugins have also registered, see
+/// `Registry::duplicate_tags`.
+pub fn duplicate_tags() -> Vec<String> {
+    REGISTRY.duplicate_tags()
+}
+
+/// Creates a plugin command which can be given to `send_command`.
+///
+/// Fails with `PayloadTooLong` if the command would be longer than `MAX_COMMAND_LEN`.
+pub fn encode(tag: &str, payload: &[u8]) -> Result<Vec<u8>, PluginCommandError> {
+    check_tag(tag)?;
+    if 4 + tag.len() + payload.len() > MAX_COMMAND_LEN {
+        return Err(PluginCommandError::PayloadTooLong(payload.len()));
+    }
+    let mut out = Vec::with_capacity(4 + tag.len() + payload.len());
+    out.push(PLUGIN_COMMAND_ID);
+    out.push(tag.len() as u8);
+    out.extend_from_slice(tag.as_bytes());
+    out.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    out.extend_from_slice(payload);
+    Ok(out)
+}
+
+/// Plugin command with an empty tag, telling later registries that `tag` was handled.
+fn claim_notice(tag: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + tag.len());
+    out.push(PLUGIN_COMMAND_ID);
+    out.push(0);
+    out.extend_from_slice(&(tag.len() as u16).to_le_bytes());
+    out.extend_from_slice(tag);
+    out
+}
+
+/// Returns tag and payload of a plugin command, or `None` if `cmd` isn't a complete
+/// plugin command.
+pub fn decode(cmd: &[u8]) -> Option<(&[u8], &[u8])> {
+    if cmd.first() != Some(&PLUGIN_COMMAND_ID) {
+        return None;
+    }
+    let tag_len = *cmd.get(1)? as usize;
+    let tag = cmd.get(2..2 + tag_len)?;
+    let payload_len_pos = 2 + tag_len;
+    let payload_len = LittleEndian::read_u16(cmd.get(payload_len_pos..payload_len_pos + 2)?);
+    let payload = cmd.get(payload_len_pos + 2..payload_len_pos + 2 + payload_len as usize)?;
+    Some((tag, payload))
+}
+
+unsafe extern "C" fn plugin_command_length(cmd: *const u8, len: u32) -> u32 {
+    let cmd = std::slice::from_raw_parts(cmd, len as usize);
+    if cmd.len() < 2 {
+        return u32::MAX;
+    }
+    let payload_len_pos = 2 + cmd[1] as usize;
+    match cmd.get(payload_len_pos..payload_len_pos + 2) {
+        Some(s) => (payload_len_pos + 2 + LittleEndian::read_u16(s) as usize) as u32,
+        None => u32::MAX,
+    }
+}
+
+unsafe extern "C" fn plugin_command_hook(
+    data: *const u8,
+    len: u32,
+    player: u32,
+    unique_player: u32,
+    orig: unsafe extern "C" fn(*const u8, u32),
+) {
+    let cmd = std::slice::from_raw_parts(data, len as usize);
+    REGISTRY.dispatch(cmd, player, unique_player, orig);
+}
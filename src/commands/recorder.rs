@@ -0,0 +1,296 @@
+//! Recording of the commands going through `ingame_hook`.
+//!
+//! While recording, every `ingame_hook` call (the commands of one player on one frame)
+//! is written as a batch, followed by the data each hook forwarded to its `orig`, and
+//! the data that was given to the game. `Recording::read` parses the file, and `play`
+//! runs its batches through the currently registered hooks again:
+//!
+//! ```ignore
+//! let recording = Recording::read(&mut File::open("desync.rec")?)?;
+//! add_ingame_hook(0x70, my_hook);
+//! assert_eq!(play(&recording), recording);
+//! ```
+//!
+//! The file starts with `MAGIC`, followed by records starting with a tag byte:
+//!
+//! ```text
+//! 1: Batch     u32 frame, u8 flags (1 = is_replay, 2 = replayed), u32 command_user,
+//!              u32 unique_command_user, u32 len, [u8; len] data
+//! 2: Forwarded u32 hook (index among all ingame hooks), u32 len, [u8; len] data
+//! 3: ToGame    u32 len, [u8; len] data
+//! ```
+
+use std::ffi::c_void;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use parking_lot::{const_mutex, Mutex};
+
+use super::{ingame_hook, IngameHookGlobals};
+
+pub const MAGIC: [u8; 6] = *b"SPCRC\x01";
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static RECORDER: Mutex<Option<Recorder>> = const_mutex(None);
+
+struct Recorder {
+    out: Box<dyn Write + Send>,
+    frame: fn() -> u32,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Recording {
+    pub batches: Vec<Batch>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Batch {
+    /// Game frame on which the commands were processed.
+    pub frame: u32,
+    pub is_replay: bool,
+    pub replayed: bool,
+    pub command_user: u32,
+    pub unique_command_user: u32,
+    pub data: Vec<u8>,
+    /// Data passed onwards by hooks and to the game, in order.
+    pub outputs: Vec<Output>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Output {
+    /// Hook at `hook` index of the hook chain called `orig` with `data`.
+    Forwarded { hook: u32, data: Vec<u8> },
+    /// The game received `data`.
+    ToGame(Vec<u8>),
+}
+
+/// Starts writing every command through `ingame_hook` to `out`.
+///
+/// `frame` is called for each batch to get the current game frame.
+///
+/// Replaces any earlier recording. A write error stops recording.
+pub fn start_recording<W: Write + Send + 'static>(
+    mut out: W,
+    frame: fn() -> u32,
+) -> io::Result<()> {
+    out.write_all(&MAGIC)?;
+    *RECORDER.lock() = Some(Recorder {
+        out: Box::new(out),
+        frame,
+    });
+    RECORDING.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Stops recording and flushes the output.
+pub fn stop_recording() -> io::Result<()> {
+    RECORDING.store(false, Ordering::Release);
+    match RECORDER.lock().take() {
+        Some(mut recorder) => recorder.out.flush(),
+        None => Ok(()),
+    }
+}
+
+pub fn is_recording() -> bool {
+    RECORDING.load(Ordering::Acquire)
+}
+
+fn write_record<F>(func: F)
+where F: FnOnce(&mut Recorder) -> io::Result<()>
+{
+    let mut recorder = RECORDER.lock();
+    if let Some(ref mut rec) = *recorder {
+        if let Err(e) = func(rec) {
+            error!("Couldn't write command recording: {}", e);
+            RECORDING.store(false, Ordering::Release);
+            *recorder = None;
+        }
+    }
+}
+
+fn write_data(out: &mut dyn Write, data: &[u8]) -> io::Result<()> {
+    out.write_u32::<LE>(data.len() as u32)?;
+    out.write_all(data)
+}
+
+/// Writes the batch record, without outputs.
+fn write_batch(out: &mut dyn Write, batch: &Batch) -> io::Result<()> {
+    let flags = batch.is_replay as u8 | ((batch.replayed as u8) << 1);
+    out.write_u8(1)?;
+    out.write_u32::<LE>(batch.frame)?;
+    out.write_u8(flags)?;
+    out.write_u32::<LE>(batch.command_user)?;
+    out.write_u32::<LE>(batch.unique_command_user)?;
+    write_data(out, &batch.data)
+}
+
+fn write_output(out: &mut dyn Write, output: &Output) -> io::Result<()> {
+    match *output {
+        Output::Forwarded { hook, ref data } => {
+            out.write_u8(2)?;
+            out.write_u32::<LE>(hook)?;
+            write_data(out, data)
+        }
+        Output::ToGame(ref data) => {
+            out.write_u8(3)?;
+            write_data(out, data)
+        }
+    }
+}
+
+pub(super) fn record_batch(data: &[u8], replayed: u32, globals: &IngameHookGlobals) {
+    write_record(|rec| {
+        let batch = Batch {
+            frame: (rec.frame)(),
+            is_replay: globals.is_replay != 0,
+            replayed: replayed != 0,
+            command_user: globals.command_user,
+            unique_command_user: globals.unique_command_user,
+            data: data.into(),
+            outputs: Vec::new(),
+        };
+        write_batch(&mut rec.out, &batch)
+    });
+}
+
+pub(super) fn record_forwarded(hook: usize, data: &[u8]) {
+    write_record(|rec| {
+        let output = Output::Forwarded { hook: hook as u32, data: data.into() };
+        write_output(&mut rec.out, &output)
+    });
+}
+
+pub(super) fn record_to_game(data: &[u8]) {
+    write_record(|rec| write_output(&mut rec.out, &Output::ToGame(data.into())));
+}
+
+fn read_data<R: Read>(input: &mut R) -> io::Result<Vec<u8>> {
+    let len = input.read_u32::<LE>()?;
+    let mut data = Vec::new();
+    input.take(len as u64).read_to_end(&mut data)?;
+    if data.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+impl Recording {
+    pub fn read<R: Read>(input: &mut R) -> io::Result<Recording> {
+        let mut magic = [0u8; 6];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a command recording"));
+        }
+        let mut batches: Vec<Batch> = Vec::new();
+        loop {
+            let tag = match input.read_u8() {
+                Ok(o) => o,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            let output = match tag {
+                1 => {
+                    let frame = input.read_u32::<LE>()?;
+                    let flags = input.read_u8()?;
+                    batches.push(Batch {
+                        frame,
+                        is_replay: flags & 1 != 0,
+                        replayed: flags & 2 != 0,
+                        command_user: input.read_u32::<LE>()?,
+                        unique_command_user: input.read_u32::<LE>()?,
+                        data: read_data(input)?,
+                        outputs: Vec::new(),
+                    });
+                    continue;
+                }
+                2 => {
+                    let hook = input.read_u32::<LE>()?;
+                    Output::Forwarded { hook, data: read_data(input)? }
+                }
+                3 => Output::ToGame(read_data(input)?),
+                _ => {
+                    let msg = format!("Invalid record tag {:x}", tag);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+            };
+            match batches.last_mut() {
+                Some(batch) => batch.outputs.push(output),
+                None => {
+                    let msg = "Recording doesn't start with a batch";
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+            }
+        }
+        Ok(Recording { batches })
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&MAGIC)?;
+        for batch in &self.batches {
+            write_batch(out, batch)?;
+            for output in &batch.outputs {
+                write_output(out, output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(std::sync::Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.lock().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs the batches of `recording` through `ingame_hook` with the currently registered
+/// hooks, and returns what got recorded this time.
+///
+/// Commands reaching the game are discarded. Any active recording is paused while
+/// playing.
+pub fn play(recording: &Recording) -> Recording {
+    unsafe extern "C" fn add_to_replay_data(_: *const u8, _: usize) {
+    }
+
+    unsafe extern "C" fn to_game(_: *const c_void, _: u32, _: u32) {
+    }
+
+    static PLAYED_FRAME: AtomicU32 = AtomicU32::new(0);
+    fn played_frame() -> u32 {
+        PLAYED_FRAME.load(Ordering::Relaxed)
+    }
+
+    let buffer = SharedBuffer::default();
+    buffer.0.lock().extend_from_slice(&MAGIC);
+    let was_recording = RECORDING.swap(true, Ordering::AcqRel);
+    let previous = RECORDER.lock().replace(Recorder {
+        out: Box::new(buffer.clone()),
+        frame: played_frame,
+    });
+    for batch in &recording.batches {
+        PLAYED_FRAME.store(batch.frame, Ordering::Relaxed);
+        let globals = IngameHookGlobals {
+            is_replay: batch.is_replay as u32,
+            unique_command_user: batch.unique_command_user,
+            command_user: batch.command_user,
+            add_to_replay_data,
+        };
+        let data = batch.data.as_ptr() as *const c_void;
+        unsafe {
+            ingame_hook(data, batch.data.len() as u32, batch.replayed as u32, &globals, to_game);
+        }
+    }
+    *RECORDER.lock() = previous;
+    RECORDING.store(was_recording, Ordering::Release);
+    let data = mem::take(&mut *buffer.0.lock());
+    Recording::read(&mut &data[..]).expect("Recorded data was invalid")
+}
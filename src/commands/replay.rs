@@ -0,0 +1,108 @@
+//! How commands interact with replays.
+//!
+//! Each command id has a `ReplayClass`, which `ingame_hook` uses to decide whether the
+//! command is written to the replay and whether it is executed while watching one.
+//!
+//! Hosts whose replay writer only understands the game's own command ids can use `wrap`
+//! to store other commands as a select command (0x09) of which the first unit is
+//! `ENVELOPE_MARKER`, which can't be a valid unit. `ingame_hook` unwraps such commands
+//! when they are played back.
+//!
+//! A game without the plugins executes the select command as is. Only the marker is
+//! guaranteed to be an invalid unit; the length and the command bytes are read as unit
+//! ids too, and may refer to real units. The unit count also goes up to 0xff, while the
+//! game selects at most 12 units. Such replays can therefore change the selection of the
+//! player, and the player's later commands may act on different units than when the
+//! replay was recorded.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ReplayClass {
+    /// Written to the replay. While watching a replay, only the recorded commands
+    /// are executed.
+    Recorded,
+    /// Not written to the replay, and executed also while watching a replay.
+    /// E.g. pausing or leaving the game.
+    Skipped,
+    /// Written to the replay, but only executed when played back from a replay.
+    ReplayOnly,
+}
+
+impl ReplayClass {
+    fn from_raw(raw: u8) -> ReplayClass {
+        match raw {
+            1 => ReplayClass::Skipped,
+            2 => ReplayClass::ReplayOnly,
+            _ => ReplayClass::Recorded,
+        }
+    }
+
+    fn to_raw(self) -> u8 {
+        match self {
+            ReplayClass::Recorded => 0,
+            ReplayClass::Skipped => 1,
+            ReplayClass::ReplayOnly => 2,
+        }
+    }
+}
+
+static CLASSES: [AtomicU8; 0x100] = {
+    let mut result = [const { AtomicU8::new(0) }; 0x100];
+    let skipped = [0x5, 0x8, 0x10, 0x11, 0x55, 0x56, 0x5d];
+    let mut i = 0;
+    while i < skipped.len() {
+        result[skipped[i]] = AtomicU8::new(1);
+        i += 1;
+    }
+    result
+};
+
+pub fn replay_class(cmd: u8) -> ReplayClass {
+    ReplayClass::from_raw(CLASSES[cmd as usize].load(Ordering::Relaxed))
+}
+
+/// Changes the class of command `cmd`.
+///
+/// All players and replays must use the same classes, so this should be called during
+/// plugin initialization.
+pub fn set_replay_class(cmd: u8, class: ReplayClass) {
+    CLASSES[cmd as usize].store(class.to_raw(), Ordering::Relaxed);
+}
+
+/// Unit id in the first slot of a wrapped command.
+pub const ENVELOPE_MARKER: u16 = 0xffff;
+/// Longest command that `wrap` accepts.
+pub const MAX_WRAPPED_LEN: usize = 0xff * 2 - 4;
+
+/// Stores `cmd` in a select command, returning `None` if it is longer than
+/// `MAX_WRAPPED_LEN`.
+///
+/// The result is `[0x09, count, marker: u16, len: u16, cmd, padding]`, where the padding
+/// is a zero byte if needed to make the length even. See the module documentation for
+/// what happens if the result is played back without unwrapping.
+pub fn wrap(cmd: &[u8]) -> Option<Vec<u8>> {
+    if cmd.len() > MAX_WRAPPED_LEN {
+        return None;
+    }
+    let unit_count = (4 + cmd.len()).div_ceil(2);
+    let mut out = Vec::with_capacity(2 + unit_count * 2);
+    out.push(0x09);
+    out.push(unit_count as u8);
+    out.extend_from_slice(&ENVELOPE_MARKER.to_le_bytes());
+    out.extend_from_slice(&(cmd.len() as u16).to_le_bytes());
+    out.extend_from_slice(cmd);
+    out.resize(2 + unit_count * 2, 0);
+    Some(out)
+}
+
+/// Returns the command stored by `wrap`, or `None` if `cmd` isn't a wrapped command.
+pub fn unwrap(cmd: &[u8]) -> Option<&[u8]> {
+    if cmd.len() < 6 || cmd[0] != 0x09 || LittleEndian::read_u16(&cmd[2..]) != ENVELOPE_MARKER {
+        return None;
+    }
+    let len = LittleEndian::read_u16(&cmd[4..]) as usize;
+    cmd.get(6..6 + len)
+}
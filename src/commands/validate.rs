@@ -0,0 +1,151 @@
+//! Validation of commands before `ingame_hook` passes them to hooks.
+//!
+//! A command is malformed if its length function rejects it, or if a validator added
+//! with `add_validator` rejects it. What happens then is decided by the
+//! `MalformedPolicy`. Each `ingame_hook` call only contains commands of a single player,
+//! so dropping commands never affects other players.
+//!
+//! Commands whose length is unknown can't be skipped, so they always drop the rest of
+//! the commands.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use parking_lot::{const_rwlock, RwLock};
+
+use super::decode::{self, DecodeError};
+use super::get_command_lengths;
+
+/// Returns `Err` with a short description if the command is invalid.
+///
+/// The command has the length given by the command length table.
+pub type Validator = fn(&[u8]) -> Result<(), &'static str>;
+
+static VALIDATORS: RwLock<Vec<(u8, Validator)>> = const_rwlock(Vec::new());
+static POLICY: RwLock<MalformedPolicy> = const_rwlock(MalformedPolicy::DropCommand);
+static MALFORMED: AtomicU64 = AtomicU64::new(0);
+static DROPPED_BYTES: AtomicU64 = AtomicU64::new(0);
+static PLAYER_MALFORMED: [AtomicU64; MAX_PLAYERS] = [const { AtomicU64::new(0) }; MAX_PLAYERS];
+
+/// Amount of players that have their own counter in `ValidationStats`.
+pub const MAX_PLAYERS: usize = 12;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MalformedAction {
+    /// Skip the command and continue from the next one.
+    DropCommand,
+    /// Skip the rest of the commands in this `ingame_hook` call.
+    DropRest,
+}
+
+#[derive(Copy, Clone)]
+pub enum MalformedPolicy {
+    DropCommand,
+    DropRest,
+    /// Let a function decide, e.g. to kick the player.
+    Callback(fn(&MalformedCommand) -> MalformedAction),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MalformedReason {
+    /// The length function returned `len`, which is either 0, or longer than the
+    /// remaining data.
+    Length(usize),
+    Invalid(&'static str),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MalformedCommand<'a> {
+    pub player: u32,
+    pub unique_player: u32,
+    /// The malformed command; for `MalformedReason::Length` all remaining data.
+    pub data: &'a [u8],
+    pub reason: MalformedReason,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ValidationStats {
+    /// Amount of malformed commands.
+    pub malformed: u64,
+    /// Amount of bytes that were skipped, including the malformed commands.
+    pub dropped_bytes: u64,
+    /// Malformed commands by `command_user`.
+    pub per_player: [u64; MAX_PLAYERS],
+}
+
+pub fn add_validator(cmd: u8, validator: Validator) {
+    VALIDATORS.write().push((cmd, validator));
+}
+
+pub fn set_malformed_policy(policy: MalformedPolicy) {
+    *POLICY.write() = policy;
+}
+
+pub fn stats() -> ValidationStats {
+    let mut per_player = [0; MAX_PLAYERS];
+    for (out, count) in per_player.iter_mut().zip(PLAYER_MALFORMED.iter()) {
+        *out = count.load(Ordering::Relaxed);
+    }
+    ValidationStats {
+        malformed: MALFORMED.load(Ordering::Relaxed),
+        dropped_bytes: DROPPED_BYTES.load(Ordering::Relaxed),
+        per_player,
+    }
+}
+
+/// Validator checking that the command can be decoded by `decode::decode`.
+///
+/// Can be added for any command with a layout known to `decode`.
+pub fn decodable(cmd: &[u8]) -> Result<(), &'static str> {
+    match decode::decode_with(get_command_lengths(), cmd) {
+        Ok((_, len)) if len == cmd.len() => Ok(()),
+        Ok(_) | Err(DecodeError::LengthMismatch { .. }) => {
+            Err("Length differs from the command layout")
+        }
+        Err(DecodeError::Empty) => Err("Empty command"),
+        Err(DecodeError::Truncated { .. }) => Err("Truncated"),
+        Err(DecodeError::Unterminated { .. }) => Err("Unterminated filename"),
+        Err(DecodeError::InvalidField { field, .. }) => Err(field),
+        Err(DecodeError::UnknownLength { .. }) => Err("Unknown length"),
+    }
+}
+
+/// Runs validators for `cmd`.
+pub(super) fn validate(cmd: &[u8]) -> Result<(), &'static str> {
+    let validators = VALIDATORS.read();
+    for &(id, validator) in validators.iter() {
+        if id == cmd[0] {
+            validator(cmd)?;
+        }
+    }
+    Ok(())
+}
+
+/// Counts and logs a malformed command, and returns what to do with it.
+///
+/// `rest_len` is the amount of data after the command.
+pub(super) fn malformed(command: &MalformedCommand, rest_len: usize) -> MalformedAction {
+    MALFORMED.fetch_add(1, Ordering::Relaxed);
+    if let Some(counter) = PLAYER_MALFORMED.get(command.player as usize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    error!(
+        "Malformed command {:x} from player {:x}: {:?}",
+        command.data[0], command.player, command.reason,
+    );
+    let policy = *POLICY.read();
+    let action = match policy {
+        MalformedPolicy::DropCommand => MalformedAction::DropCommand,
+        MalformedPolicy::DropRest => MalformedAction::DropRest,
+        MalformedPolicy::Callback(func) => func(command),
+    };
+    let action = match command.reason {
+        MalformedReason::Length(_) => MalformedAction::DropRest,
+        MalformedReason::Invalid(_) => action,
+    };
+    let dropped = match action {
+        MalformedAction::DropCommand => command.data.len(),
+        MalformedAction::DropRest => command.data.len() + rest_len,
+    };
+    DROPPED_BYTES.fetch_add(dropped as u64, Ordering::Relaxed);
+    action
+}
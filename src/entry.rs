@@ -0,0 +1,125 @@
+//! Support functions for the `entry!` macro.
+//!
+//! ```ignore
+//! samase_plugin::entry! {
+//!     name: "my_plugin",
+//!     min_version: samase_plugin::VERSION,
+//!     // Optional, require the host to know at least this many `FuncId`s.
+//!     min_max_func_id: FuncId::DamageUnit as u16 + 1,
+//!     init: init,
+//! }
+//!
+//! unsafe fn init(api: &'static SamaseApi) {
+//!     ...
+//! }
+//! ```
+//!
+//! The macro exports `samase_plugin_init`, which refuses to start if the host is too
+//! old, and otherwise calls `init` with a `SamaseApi` that lives for the rest of the
+//! process. A panic in `init` is reported with `crash_with_message`.
+//! As `SamaseApi` only reads fields of hosts that have at least this crate's `VERSION`,
+//! `min_version` should usually be `VERSION`.
+//!
+//! The expansion uses `std::panic::catch_unwind`, so the plugin crate has to link `std`
+//! even though this crate doesn't. With the `std` feature the API is also given to
+//! `panic_safe::set_api`.
+
+use alloc::boxed::Box;
+use alloc::format;
+use core::any::Any;
+
+use crate::api::{c_string, SamaseApi};
+use crate::PluginApi;
+
+/// Exports `samase_plugin_init` for a plugin; see the `entry` module documentation.
+#[macro_export]
+macro_rules! entry {
+    (
+        name: $name:expr,
+        min_version: $version:expr,
+        $(min_max_func_id: $func_id:expr,)?
+        init: $init:expr $(,)?
+    ) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn samase_plugin_init(api: *const $crate::PluginApi) {
+            let min_max_func_id: u16 = 0 $(+ $func_id)?;
+            let api = match $crate::entry::check_api(api, $name, $version, min_max_func_id) {
+                Some(s) => s,
+                None => return,
+            };
+            let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                ($init)(api)
+            }));
+            if let Err(payload) = result {
+                $crate::entry::init_panicked(api, $name, &*payload);
+            }
+        }
+    };
+}
+
+/// Returns `None` after showing a warning if the host is older than required.
+///
+/// The returned `SamaseApi` is leaked so that it can be stored by the plugin.
+///
+/// # Safety
+///
+/// `api` must be null or the pointer given to `samase_plugin_init`, see
+/// `SamaseApi::new`.
+pub unsafe fn check_api(
+    api: *const PluginApi,
+    name: &str,
+    min_version: u16,
+    min_max_func_id: u16,
+) -> Option<&'static SamaseApi> {
+    if api.is_null() {
+        return None;
+    }
+    let wrapper = SamaseApi::new(api);
+    let message = if wrapper.version() < min_version {
+        format!(
+            "{} requires samase API version {}, this samase has version {}. \
+            The plugin was not loaded, update samase.",
+            name, min_version, wrapper.version(),
+        )
+    } else if wrapper.max_func_id() < min_max_func_id {
+        format!(
+            "{} requires samase to support {} functions, this samase supports {}. \
+            The plugin was not loaded, update samase.",
+            name, min_max_func_id, wrapper.max_func_id(),
+        )
+    } else {
+        let api: &'static SamaseApi = Box::leak(Box::new(wrapper));
+        #[cfg(feature = "std")]
+        crate::panic_safe::set_api(api);
+        return Some(api);
+    };
+    // Called through the raw struct, as the host was just refused and `SamaseApi` may not
+    // consider its fields present. Every host has `warn_unsupported_feature`.
+    let message = c_string(&message);
+    ((*api).warn_unsupported_feature)(message.as_ptr());
+    None
+}
+
+/// Reports a panic from plugin initialization by crashing the game with a message.
+///
+/// # Safety
+///
+/// `api` has to be the `SamaseApi` returned by `check_api`. The process is terminated
+/// without unwinding.
+pub unsafe fn init_panicked(api: &SamaseApi, name: &str, payload: &(dyn Any + Send)) -> ! {
+    let message = format!("{} panicked during initialization: {}", name, panic_message(payload));
+    let message = c_string(&message);
+    // Every host has `crash_with_message`, see `check_api`.
+    ((*api.raw()).crash_with_message)(message.as_ptr())
+}
+
+/// Returns the message of a panic payload, if it was created by `panic!` with a string.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<alloc::string::String>() {
+        s
+    } else {
+        "(Non-string panic payload)"
+    }
+}
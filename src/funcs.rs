@@ -0,0 +1,491 @@
+//! Typed `hook_func` / `get_func` wrappers.
+//!
+//! `FUNC_SIGNATURES` describes arguments and return type of every `FuncId`, and for each
+//! function there is a `hook_x` function taking a closure
+//! `|args..., orig| -> ret` and a `call_x` function calling the host function.
+//!
+//! Hosts pass every argument and return value as `usize`; the conversions are done with
+//! `FuncArg`.
+//!
+//! ```ignore
+//! funcs::hook_damage_unit(&api, |target, damage, attacker, player, show, orig| {
+//!     orig(target, damage / 2, attacker, player, show)
+//! })?;
+//! ```
+//!
+//! The hook closures cannot capture anything, as the host has no way to pass context to
+//! them. Trying to use a capturing closure fails to build.
+//!
+//! With the `std` feature, panics in the hooks are caught and reported like in
+//! `panic_safe`.
+
+use core::ffi::c_void;
+use core::mem;
+
+use crate::api::{ApiError, SamaseApi};
+use crate::FuncId;
+
+/// Filter function used by the unit search functions.
+/// Unit, param -> nonzero to accept the unit.
+pub type UnitFilter = unsafe extern "C" fn(*mut c_void, *mut c_void) -> u32;
+
+/// Conversion between hook argument types and the `usize` that hosts use.
+pub trait FuncArg {
+    fn from_usize(value: usize) -> Self;
+    fn to_usize(self) -> usize;
+}
+
+macro_rules! int_func_arg {
+    ($($ty:ty,)*) => {
+        $(
+            impl FuncArg for $ty {
+                #[inline]
+                fn from_usize(value: usize) -> Self {
+                    value as $ty
+                }
+
+                #[inline]
+                fn to_usize(self) -> usize {
+                    self as usize
+                }
+            }
+        )*
+    };
+}
+
+int_func_arg! {
+    u8, u16, u32, i32, usize,
+}
+
+impl FuncArg for () {
+    #[inline]
+    fn from_usize(_: usize) -> Self {
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize {
+        0
+    }
+}
+
+impl<T> FuncArg for *mut T {
+    #[inline]
+    fn from_usize(value: usize) -> Self {
+        value as *mut T
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl<T> FuncArg for *const T {
+    #[inline]
+    fn from_usize(value: usize) -> Self {
+        value as *const T
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl FuncArg for Option<UnitFilter> {
+    #[inline]
+    fn from_usize(value: usize) -> Self {
+        unsafe { mem::transmute::<usize, Option<UnitFilter>>(value) }
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize {
+        match self {
+            Some(func) => func as usize,
+            None => 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FuncSignature {
+    pub id: FuncId,
+    /// Snake case name, used in `hook_{name}` / `call_{name}`.
+    pub name: &'static str,
+    /// (Argument name, Rust type)
+    pub args: &'static [(&'static str, &'static str)],
+    /// Rust type, `()` if the function doesn't return anything meaningful.
+    pub ret: &'static str,
+}
+
+pub fn signature(id: FuncId) -> Option<&'static FuncSignature> {
+    FUNC_SIGNATURES.get(id as usize)
+}
+
+macro_rules! usize_for {
+    ($arg:ident) => { usize };
+}
+
+macro_rules! ret_str {
+    () => { "()" };
+    ($ret:ty) => { stringify!($ret) };
+}
+
+macro_rules! ret_or_unit {
+    () => { () };
+    ($ret:ty) => { $ret };
+}
+
+macro_rules! func_signatures {
+    ($(
+        $id:ident => $hook:ident, $call:ident, $name:literal
+            ($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;
+    )*) => {
+        /// Signatures of every `FuncId`, indexed by the id.
+        pub static FUNC_SIGNATURES: &[FuncSignature] = &[
+            $(FuncSignature {
+                id: FuncId::$id,
+                name: $name,
+                args: &[$((stringify!($arg), stringify!($ty))),*],
+                ret: ret_str!($($ret)?),
+            },)*
+        ];
+
+        $(
+            /// Hooks `FuncId::
+            #[doc = stringify!($id)]
+            /// `.
+            ///
+            /// # Safety
+            ///
+            /// The hook is called by the game with the arguments of the function, and
+            /// pointer arguments are only valid for the duration of the call. Must be
+            /// called during plugin initialization.
+            pub unsafe fn $hook<F>(api: &SamaseApi, hook: F) -> Result<(), ApiError>
+            where F: Fn($($ty,)* &dyn Fn($($ty),*) -> ret_or_unit!($($ret)?))
+                -> ret_or_unit!($($ret)?) + Copy + 'static
+            {
+                unsafe extern "C" fn shim<F>(
+                    $($arg: usize,)*
+                    orig: unsafe extern "C" fn($(usize_for!($arg)),*) -> usize,
+                ) -> usize
+                where F: Fn($($ty,)* &dyn Fn($($ty),*) -> ret_or_unit!($($ret)?))
+                    -> ret_or_unit!($($ret)?) + Copy + 'static
+                {
+                    const {
+                        assert!(
+                            mem::size_of::<F>() == 0,
+                            "Hook closures cannot capture variables",
+                        );
+                    }
+                    // Zero-sized, so there is no data to initialize
+                    let hook: F = mem::zeroed();
+                    let orig = |$($arg: $ty),*| -> ret_or_unit!($($ret)?) {
+                        FuncArg::from_usize(orig($($arg.to_usize()),*))
+                    };
+                    let call = move || hook($(FuncArg::from_usize($arg),)* &orig).to_usize();
+                    #[cfg(feature = "std")]
+                    let result = crate::panic_safe::catch($name, call);
+                    #[cfg(not(feature = "std"))]
+                    let result = call();
+                    result
+                }
+
+                let _ = hook;
+                api.hook_func(FuncId::$id, shim::<F> as *const () as usize)
+            }
+
+            /// Calls `FuncId::
+            #[doc = stringify!($id)]
+            /// `, returns `None` if the host doesn't support it.
+            ///
+            /// # Safety
+            ///
+            /// Pointer arguments must be valid for the function, and the game has to
+            /// be in a state where it can be called.
+            #[allow(clippy::too_many_arguments)]
+            pub unsafe fn $call(api: &SamaseApi, $($arg: $ty),*)
+                -> Option<ret_or_unit!($($ret)?)>
+            {
+                let func = api.get_func(FuncId::$id)?;
+                let func: unsafe extern "C" fn($(usize_for!($arg)),*) -> usize =
+                    mem::transmute(func);
+                Some(FuncArg::from_usize(func($($arg.to_usize()),*)))
+            }
+        )*
+    };
+}
+
+func_signatures! {
+    UnitCanRally => hook_unit_can_rally, call_unit_can_rally, "unit_can_rally"
+        (unit: *mut c_void) -> u32;
+    UnitCanBeInfested => hook_unit_can_be_infested, call_unit_can_be_infested,
+        "unit_can_be_infested"
+        (unit: *mut c_void) -> u32;
+    DoMissileDamage => hook_do_missile_damage, call_do_missile_damage, "do_missile_damage"
+        (bullet: *mut c_void);
+    HitUnit => hook_hit_unit, call_hit_unit, "hit_unit"
+        (target: *mut c_void, bullet: *mut c_void, damage_divisor: u32);
+    HallucinationHit => hook_hallucination_hit, call_hallucination_hit, "hallucination_hit"
+        (target: *mut c_void, weapon_id: u32, direction: u32, attacker: *mut c_void);
+    DamageUnit => hook_damage_unit, call_damage_unit, "damage_unit"
+        (
+            target: *mut c_void,
+            damage: i32,
+            attacker: *mut c_void,
+            attacker_player: u32,
+            show_attacker: u32
+        );
+    KillUnit => hook_kill_unit, call_kill_unit, "kill_unit"
+        (unit: *mut c_void);
+    UnitSetHp => hook_unit_set_hp, call_unit_set_hp, "unit_set_hp"
+        (unit: *mut c_void, value: i32);
+    TransformUnit => hook_transform_unit, call_transform_unit, "transform_unit"
+        (unit: *mut c_void, new_unit_id: u32);
+    GiveUnit => hook_give_unit, call_give_unit, "give_unit"
+        (unit: *mut c_void, player: u32);
+    PlaceCreepRect => hook_place_creep_rect, call_place_creep_rect, "place_creep_rect"
+        (x_tile: u32, y_tile: u32, w_tile: u32, h_tile: u32, round_corners: u32);
+    PlaceFinishedUnitCreep => hook_place_finished_unit_creep, call_place_finished_unit_creep,
+        "place_finished_unit_creep"
+        (unit_id: u32, x: i32, y: i32);
+    AddAiToTrainedUnit => hook_add_ai_to_trained_unit, call_add_ai_to_trained_unit,
+        "add_ai_to_trained_unit"
+        (parent: *mut c_void, unit: *mut c_void);
+    AddBuildingAi => hook_add_building_ai, call_add_building_ai, "add_building_ai"
+        (parent: *mut c_void, unit: *mut c_void);
+    AddTownUnitAi => hook_add_town_unit_ai, call_add_town_unit_ai, "add_town_unit_ai"
+        (town: *mut c_void, unit: *mut c_void);
+    AddMilitaryAi => hook_add_military_ai, call_add_military_ai, "add_military_ai"
+        (region: *mut c_void, unit: *mut c_void, always_use_this_region: u32);
+    AiRemoveUnit => hook_ai_remove_unit, call_ai_remove_unit, "ai_remove_unit"
+        (unit: *mut c_void, was_killed: u32);
+    AiRemoveUnitMilitary => hook_ai_remove_unit_military, call_ai_remove_unit_military,
+        "ai_remove_unit_military"
+        (unit: *mut c_void, was_killed: u32);
+    AiRemoveUnitTown => hook_ai_remove_unit_town, call_ai_remove_unit_town,
+        "ai_remove_unit_town"
+        (unit: *mut c_void, for_finished_unit_morph: u32);
+    UnitMaxEnergy => hook_unit_max_energy, call_unit_max_energy, "unit_max_energy"
+        (unit: *mut c_void) -> u32;
+    UnitAttackRange => hook_unit_attack_range, call_unit_attack_range, "unit_attack_range"
+        (unit: *mut c_void, weapon_id: u32) -> u32;
+    UnitTargetAcquisitionRange => hook_unit_target_acquisition_range,
+        call_unit_target_acquisition_range, "unit_target_acquisition_range"
+        (unit: *mut c_void) -> u32;
+    UnitSightRange => hook_unit_sight_range, call_unit_sight_range, "unit_sight_range"
+        (unit: *mut c_void, ignore_blind: u32) -> u32;
+    CheckWeaponTargetingFlags => hook_check_weapon_targeting_flags,
+        call_check_weapon_targeting_flags, "check_weapon_targeting_flags"
+        (unit: *mut c_void, weapon_id: u32, target: *mut c_void) -> u32;
+    CheckTechTargeting => hook_check_tech_targeting, call_check_tech_targeting,
+        "check_tech_targeting"
+        (
+            unit: *mut c_void,
+            tech_id: u32,
+            target: *mut c_void,
+            fow_unit_id: u32,
+            x: i32,
+            y: i32,
+            error_string_id: *mut u16
+        ) -> u32;
+    CheckOrderTargeting => hook_check_order_targeting, call_check_order_targeting,
+        "check_order_targeting"
+        (
+            unit: *mut c_void,
+            order: u32,
+            target: *mut c_void,
+            x: i32,
+            y: i32,
+            error_string_id: *mut u16
+        ) -> u32;
+    CheckFowOrderTargeting => hook_check_fow_order_targeting, call_check_fow_order_targeting,
+        "check_fow_order_targeting"
+        (
+            unit: *mut c_void,
+            order: u32,
+            fow_unit_id: u32,
+            x: i32,
+            y: i32,
+            error_string_id: *mut u16
+        ) -> u32;
+    HideUnit => hook_hide_unit, call_hide_unit, "hide_unit"
+        (unit: *mut c_void);
+    ShowUnit => hook_show_unit, call_show_unit, "show_unit"
+        (unit: *mut c_void);
+    GetRenderTarget => hook_get_render_target, call_get_render_target, "get_render_target"
+        (id: u32) -> *mut c_void;
+    MoveScreen => hook_move_screen, call_move_screen, "move_screen"
+        (x: u32, y: u32);
+    SelectUnits => hook_select_units, call_select_units, "select_units"
+        (length: u32, units: *mut *mut c_void, a3: u32, a4: u32);
+    AiAddMilitaryToRegion => hook_ai_add_military_to_region, call_ai_add_military_to_region,
+        "ai_add_military_to_region"
+        (region: *mut c_void, unit_id: u32, priority: u32) -> u32;
+    AiTrainMilitary => hook_ai_train_military, call_ai_train_military, "ai_train_military"
+        (player: u32);
+    AiAttackPrepare => hook_ai_attack_prepare, call_ai_attack_prepare, "ai_attack_prepare"
+        (player: u32, x: u32, y: u32, always_override: u32, allow_air_fallback: u32) -> u32;
+    AiAttackClear => hook_ai_attack_clear, call_ai_attack_clear, "ai_attack_clear"
+        (player: u32, zero_last_attack_second: u32);
+    AiRegionUpdateStrength => hook_ai_region_update_strength, call_ai_region_update_strength,
+        "ai_region_update_strength"
+        (region: *mut c_void);
+    AiRegionUpdateTarget => hook_ai_region_update_target, call_ai_region_update_target,
+        "ai_region_update_target"
+        (region: *mut c_void);
+    AiRegionAbandonIfOverwhelmed => hook_ai_region_abandon_if_overwhelmed,
+        call_ai_region_abandon_if_overwhelmed, "ai_region_abandon_if_overwhelmed"
+        (region: *mut c_void) -> u32;
+    AiRegionPickAttackTarget => hook_ai_region_pick_attack_target,
+        call_ai_region_pick_attack_target, "ai_region_pick_attack_target"
+        (region: *mut c_void) -> u32;
+    AiStepRegion => hook_ai_step_region, call_ai_step_region, "ai_step_region"
+        (player: u32, region_id: u32);
+    AiTargetExpansion => hook_ai_target_expansion, call_ai_target_expansion,
+        "ai_target_expansion"
+        (player: u32) -> u32;
+    StepGameLogic => hook_step_game_logic, call_step_game_logic, "step_game_logic"
+        (a1: u32);
+    StepUnitTimers => hook_step_unit_timers, call_step_unit_timers, "step_unit_timers"
+        (unit: *mut c_void);
+    StartCloaking => hook_start_cloaking, call_start_cloaking, "start_cloaking"
+        (unit: *mut c_void);
+    UnitAiWorker => hook_unit_ai_worker, call_unit_ai_worker, "unit_ai_worker"
+        (unit: *mut c_void);
+    UnitAiMilitary => hook_unit_ai_military, call_unit_ai_military, "unit_ai_military"
+        (unit: *mut c_void);
+    UnitAiBuilding => hook_unit_ai_building, call_unit_ai_building, "unit_ai_building"
+        (unit: *mut c_void);
+    FindNearestUnitInArea => hook_find_nearest_unit_in_area, call_find_nearest_unit_in_area,
+        "find_nearest_unit_in_area"
+        (
+            unit: *mut c_void,
+            rect: *const u16,
+            filter: Option<UnitFilter>,
+            filter_param: *mut c_void
+        ) -> *mut c_void;
+    FindNearestUnitAroundUnit => hook_find_nearest_unit_around_unit,
+        call_find_nearest_unit_around_unit, "find_nearest_unit_around_unit"
+        (
+            unit: *mut c_void,
+            radius: u32,
+            filter: Option<UnitFilter>,
+            filter_param: *mut c_void
+        ) -> *mut c_void;
+    CanAttackUnit => hook_can_attack_unit, call_can_attack_unit, "can_attack_unit"
+        (unit: *mut c_void, target: *mut c_void, check_detection: u32) -> u32;
+    IsOutsideAttackRange => hook_is_outside_attack_range, call_is_outside_attack_range,
+        "is_outside_attack_range"
+        (unit: *mut c_void, target: *mut c_void) -> u32;
+    AiCanTargetAttackThis => hook_ai_can_target_attack_this, call_ai_can_target_attack_this,
+        "ai_can_target_attack_this"
+        (unit: *mut c_void, target: *mut c_void) -> u32;
+    AiTryReturnHome => hook_ai_try_return_home, call_ai_try_return_home, "ai_try_return_home"
+        (unit: *mut c_void, dont_issue_order: u32) -> u32;
+    ForEachUnitInArea => hook_for_each_unit_in_area, call_for_each_unit_in_area,
+        "for_each_unit_in_area"
+        (rect: *const u16, filter: Option<UnitFilter>, filter_param: *mut c_void)
+            -> *mut c_void;
+    PrepareBuildUnit => hook_prepare_build_unit, call_prepare_build_unit, "prepare_build_unit"
+        (builder: *mut c_void, unit_id: u32) -> u32;
+    CalculatePath => hook_calculate_path, call_calculate_path, "calculate_path"
+        (path_context: *mut c_void) -> u32;
+    AiPlaceBuilding => hook_ai_place_building, call_ai_place_building, "ai_place_building"
+        (worker: *mut c_void, unit_id: u32, pos_xy: u32, out_xy: *mut u32, area_tiles: u32)
+            -> u32;
+    GetChokePointRegions => hook_get_choke_point_regions, call_get_choke_point_regions,
+        "get_choke_point_regions"
+        (
+            source_region: u32,
+            dest_region: u32,
+            max_distance_regions: u32,
+            out_regions: *mut u16,
+            out_error: *mut u32,
+            min_distance_regions: u32
+        ) -> u32;
+    AiUpdateBuildingPlacementState => hook_ai_update_building_placement_state,
+        call_ai_update_building_placement_state, "ai_update_building_placement_state"
+        (unit_id: u32, placement_data: *mut u8, player: u32, pos_xy: u32, radius_tiles: u32);
+    UpdateBuildingPlacementState => hook_update_building_placement_state,
+        call_update_building_placement_state, "update_building_placement_state"
+        (
+            unit: *mut c_void,
+            player: u32,
+            x_tile: u32,
+            y_tile: u32,
+            unit_id: u32,
+            placement_entry: u32,
+            check_vision: u32,
+            also_invisible: u32,
+            without_vision: u32
+        ) -> u32;
+    FindNearestUnitInAreaPoint => hook_find_nearest_unit_in_area_point,
+        call_find_nearest_unit_in_area_point, "find_nearest_unit_in_area_point"
+        (
+            x: i32,
+            y: i32,
+            rect: *const u16,
+            filter: Option<UnitFilter>,
+            filter_param: *mut c_void
+        ) -> *mut c_void;
+    AiPickBestPlacementPosition => hook_ai_pick_best_placement_position,
+        call_ai_pick_best_placement_position, "ai_pick_best_placement_position"
+        (
+            builder: *mut c_void,
+            placement_data: *mut u8,
+            player: u32,
+            unit_id: u32,
+            placement_center: u32,
+            search_pos: u32,
+            out: *mut u32
+        ) -> u32;
+    AiPlacementFlags => hook_ai_placement_flags, call_ai_placement_flags, "ai_placement_flags"
+        (builder: *mut c_void, player: u32, unit_id: u32) -> u32;
+    AiAddToAttackForce => hook_ai_add_to_attack_force, call_ai_add_to_attack_force,
+        "ai_add_to_attack_force"
+        (player: u32, unit_id: u32, amount: u32);
+    AiRemoveFromAttackForce => hook_ai_remove_from_attack_force,
+        call_ai_remove_from_attack_force, "ai_remove_from_attack_force"
+        (player: u32, unit_id: u32, amount: u32);
+    CreateLoneSprite => hook_create_lone_sprite, call_create_lone_sprite, "create_lone_sprite"
+        (sprite_id: u32, x: i32, y: i32, player: u32) -> *mut c_void;
+    LobbyScreenOnWebUiMessage => hook_lobby_screen_on_web_ui_message,
+        call_lobby_screen_on_web_ui_message, "lobby_screen_on_web_ui_message"
+        (lobby_screen: *mut c_void, json_value: *mut c_void);
+    ShowInfoMessageWithSound => hook_show_info_message_with_sound,
+        call_show_info_message_with_sound, "show_info_message_with_sound"
+        (player: u32, sound_id: u32, string_id: u32);
+    CreateStartingUnits => hook_create_starting_units, call_create_starting_units,
+        "create_starting_units"
+        ();
+    CreateTeamGameStartingUnits => hook_create_team_game_starting_units,
+        call_create_team_game_starting_units, "create_team_game_starting_units"
+        ();
+    UnitSetDirection => hook_unit_set_direction, call_unit_set_direction, "unit_set_direction"
+        (unit: *mut c_void, direction: u32);
+    AiGetAttackForce => hook_ai_get_attack_force, call_ai_get_attack_force,
+        "ai_get_attack_force"
+        (player: u32, buffer: *mut u32, buffer_size: u32) -> u32;
+    CancelUnit => hook_cancel_unit, call_cancel_unit, "cancel_unit"
+        (unit: *mut c_void);
+    FinishUnitPre => hook_finish_unit_pre, call_finish_unit_pre, "finish_unit_pre"
+        (unit: *mut c_void);
+    FinishUnitPost => hook_finish_unit_post, call_finish_unit_post, "finish_unit_post"
+        (unit: *mut c_void);
+    DoSave => hook_do_save, call_do_save, "do_save"
+        (
+            file_handle: *mut c_void,
+            filename: *const u8,
+            save_time: u32,
+            name_unused: *const u8,
+            unk_unused: u32,
+            game_elapsed_seconds: u32
+        ) -> u32;
+    RenderScreen => hook_render_screen, call_render_screen, "render_screen"
+        (a1: usize, a2: usize);
+    StepGameLoop => hook_step_game_loop, call_step_game_loop, "step_game_loop"
+        ();
+    ProcessEvents => hook_process_events, call_process_events, "process_events"
+        (flags: u32);
+}
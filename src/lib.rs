@@ -1,21 +1,38 @@
-#![cfg_attr(all(not(feature = "implementer_helpers"), not(feature = "save")), no_std)]
+#![cfg_attr(
+    all(not(feature = "implementer_helpers"), not(feature = "save"), not(feature = "std")),
+    no_std
+)]
 
 extern crate alloc;
 
 #[cfg(feature = "implementer_helpers")]
 #[macro_use] extern crate log;
 
+pub mod abi;
+pub mod api;
+pub mod capabilities;
 #[cfg(feature = "implementer_helpers")]
 pub mod commands;
+pub mod entry;
+pub mod funcs;
+#[cfg(feature = "mock_host")]
+pub mod mock_host;
+#[cfg(feature = "std")]
+pub mod panic_safe;
 #[cfg(feature = "implementer_helpers")]
 pub mod save;
 #[cfg(feature = "save")]
 pub mod save_file;
+#[cfg(feature = "trampoline")]
+pub mod trampoline;
+pub mod vars;
 
 use alloc::string::String;
 use core::ffi::c_void;
 use core::ptr::{null_mut};
 
+pub use crate::api::{ApiError, SamaseApi};
+
 // data, len, game player, unique player, orig
 pub type IngameCommandHook =
     unsafe extern "C" fn(*const u8, u32, u32, u32, unsafe extern "C" fn(*const u8, u32));
@@ -625,6 +642,26 @@ pub enum VarId {
     _Last,
 }
 
+impl FuncId {
+    pub fn from_u16(id: u16) -> Option<FuncId> {
+        if id < MAX_FUNC_ID {
+            Some(unsafe { core::mem::transmute::<u8, FuncId>(id as u8) })
+        } else {
+            None
+        }
+    }
+}
+
+impl VarId {
+    pub fn from_u16(id: u16) -> Option<VarId> {
+        if id < MAX_VAR_ID {
+            Some(unsafe { core::mem::transmute::<u16, VarId>(id) })
+        } else {
+            None
+        }
+    }
+}
+
 impl FfiStr {
     pub fn from_str(input: &str) -> FfiStr {
         Self::from_bytes(input.as_bytes())
@@ -0,0 +1,940 @@
+//! In-process fake host for testing plugins without StarCraft.
+//!
+//! `MockHost::new` builds a real `PluginApi` whose functions record everything the plugin
+//! registers, and lets tests fire those hooks afterwards. State is thread-local, so every
+//! test thread can have its own `MockHost`, but hooks must be registered and fired from
+//! the thread that created it.
+//!
+//! Getters for game variables (`game`, `first_active_unit`, `is_replay`, ...) return the
+//! value set with `MockHost::set_var` for the corresponding `VarId`, and `None` if the
+//! var hasn't been set. Other getters return `None`, except `print_text` and
+//! `send_command` which record their input.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr};
+use std::ptr::null_mut;
+use std::sync::Mutex;
+
+use crate::{
+    CommandLength, ComplexLineParam, DebugUiDrawCb, DebugUiLog, ExtendedArray, FfiStr, FuncId,
+    IngameCommandHook, LoadHook, PluginApi, SaveHook, VarId, MAX_FUNC_ID, MAX_VAR_ID, VERSION,
+};
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+    static CURRENT_SAVE: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static COMMAND_CHAIN: RefCell<Vec<ChainState>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Memory given to plugins from `read_file`, so that `free_memory` can free it.
+static ALLOCATIONS: Mutex<Vec<(usize, usize)>> = Mutex::new(Vec::new());
+/// Messages given to `crash_with_message`, from all threads.
+static CRASHES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub struct SaveExtension {
+    pub tag: String,
+    pub save: SaveHook,
+    pub load: LoadHook,
+    pub init: unsafe extern "C" fn(),
+}
+
+#[derive(Clone, Copy)]
+pub struct IngameCommand {
+    pub id: u8,
+    pub hook: IngameCommandHook,
+    pub length: Option<CommandLength>,
+}
+
+#[derive(Default)]
+struct State {
+    files: HashMap<String, Vec<u8>>,
+    map_files: HashMap<String, Vec<u8>>,
+    /// VarId -> (value, load_vars result)
+    vars: HashMap<u16, (usize, u8)>,
+    funcs: HashMap<u16, unsafe extern "C" fn()>,
+    printed: Vec<String>,
+    warnings: Vec<String>,
+    park_on_crash: bool,
+    sent_commands: Vec<Vec<u8>>,
+    processed_commands: Vec<Vec<u8>>,
+    exe_patches: Vec<(usize, Vec<u8>)>,
+    /// (`PluginApi` field name, hook fnptr). Includes hook_step_objects `after`
+    /// and opcode hooks through `hook_args`.
+    hooks: Vec<(&'static str, usize)>,
+    hook_args: Vec<(&'static str, u32, usize)>,
+    file_read_hooks: Vec<(String, usize)>,
+    func_hooks: Vec<(FuncId, usize)>,
+    ingame_commands: Vec<IngameCommand>,
+    save_extensions: Vec<SaveExtension>,
+    extended_unit_fields: Vec<String>,
+    extended_unit_values: HashMap<(u32, u32), u32>,
+}
+
+fn with_state<F: FnOnce(&mut State) -> R, R>(func: F) -> R {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().expect("No MockHost on this thread");
+        func(state)
+    })
+}
+
+#[derive(Clone)]
+struct ChainState {
+    hooks: Vec<(u8, IngameCommandHook)>,
+    next: usize,
+    player: u32,
+    unique_player: u32,
+}
+
+pub struct MockHost {
+    api: Box<PluginApi>,
+}
+
+impl MockHost {
+    /// Creates a host with current `VERSION`.
+    ///
+    /// Panics if there already is a `MockHost` on this thread.
+    pub fn new() -> MockHost {
+        MockHost::with_version(VERSION)
+    }
+
+    /// Creates a host claiming to be `version`, for testing behaviour on older hosts.
+    pub fn with_version(version: u16) -> MockHost {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            assert!(state.is_none(), "Only one MockHost per thread is allowed");
+            *state = Some(State::default());
+        });
+        let mut api = Box::new(api());
+        api.version = version;
+        MockHost {
+            api,
+        }
+    }
+
+    pub fn api(&self) -> *const PluginApi {
+        &*self.api
+    }
+
+    /// Makes `read_file` return `data` for `path`.
+    pub fn add_file(&self, path: &str, data: Vec<u8>) {
+        with_state(|s| s.files.insert(path.into(), data));
+    }
+
+    /// Makes `read_map_file` return `data` for `path`.
+    pub fn add_map_file(&self, path: &str, data: Vec<u8>) {
+        with_state(|s| s.map_files.insert(path.into(), data));
+    }
+
+    /// Sets var to be readable (and writable if `writable`) with `value`.
+    pub fn set_var(&self, var: VarId, value: usize, writable: bool) {
+        let access = if writable { 3 } else { 2 };
+        with_state(|s| s.vars.insert(var as u16, (value, access)));
+    }
+
+    pub fn var(&self, var: VarId) -> Option<usize> {
+        with_state(|s| s.vars.get(&(var as u16)).map(|x| x.0))
+    }
+
+    /// Makes `get_func(id)` return `func`, and `hook_func(id)` succeed.
+    pub fn set_func(&self, id: FuncId, func: unsafe extern "C" fn()) {
+        with_state(|s| s.funcs.insert(id as u16, func));
+    }
+
+    pub fn printed_text(&self) -> Vec<String> {
+        with_state(|s| s.printed.clone())
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        with_state(|s| s.warnings.clone())
+    }
+
+    /// Makes `crash_with_message` park this thread forever instead of panicking.
+    ///
+    /// As `crash_with_message` can't return or unwind, the default panic aborts the
+    /// entire test process. To test crashes, run the plugin code in a separate thread
+    /// with this set, and wait for `crash_messages` from another thread.
+    pub fn park_on_crash(&self) {
+        with_state(|s| s.park_on_crash = true);
+    }
+
+    /// Commands given to `send_command`.
+    pub fn sent_commands(&self) -> Vec<Vec<u8>> {
+        with_state(|s| s.sent_commands.clone())
+    }
+
+    /// Commands that reached the end of ingame command hook chain in `fire_ingame_command`.
+    pub fn processed_commands(&self) -> Vec<Vec<u8>> {
+        with_state(|s| s.processed_commands.clone())
+    }
+
+    pub fn exe_patches(&self) -> Vec<(usize, Vec<u8>)> {
+        with_state(|s| s.exe_patches.clone())
+    }
+
+    /// Hooks registered with `PluginApi` field `field`, e.g. `"hook_step_order"`.
+    ///
+    /// The values are function pointers which have to be transmuted to the type
+    /// that the field takes.
+    pub fn hooks(&self, field: &str) -> Vec<usize> {
+        with_state(|s| {
+            s.hooks.iter()
+                .filter(|x| x.0 == field)
+                .map(|x| x.1)
+                .chain(s.hook_args.iter().filter(|x| x.0 == field).map(|x| x.2))
+                .collect()
+        })
+    }
+
+    /// Hooks registered with `hook_aiscript_opcode` / `hook_iscript_opcode` /
+    /// `hook_renderer` for `arg`.
+    pub fn hooks_with_arg(&self, field: &str, arg: u32) -> Vec<usize> {
+        with_state(|s| {
+            s.hook_args.iter()
+                .filter(|x| x.0 == field && x.1 == arg)
+                .map(|x| x.2)
+                .collect()
+        })
+    }
+
+    pub fn func_hooks(&self, id: FuncId) -> Vec<usize> {
+        with_state(|s| {
+            s.func_hooks.iter().filter(|x| x.0 == id).map(|x| x.1).collect()
+        })
+    }
+
+    pub fn file_read_hooks(&self) -> Vec<(String, usize)> {
+        with_state(|s| s.file_read_hooks.clone())
+    }
+
+    pub fn ingame_commands(&self) -> Vec<IngameCommand> {
+        with_state(|s| s.ingame_commands.clone())
+    }
+
+    pub fn save_extension_tags(&self) -> Vec<String> {
+        with_state(|s| s.save_extensions.iter().map(|x| x.tag.clone()).collect())
+    }
+
+    /// Calls `unsafe extern "C" fn()` hooks registered through `field`
+    /// (`hook_on_first_file_access`, `hook_game_loop_start`).
+    ///
+    /// # Safety
+    ///
+    /// The hooks are called as is; they must be valid to call now.
+    pub unsafe fn fire_hooks(&self, field: &str) {
+        for hook in self.hooks(field) {
+            let hook: unsafe extern "C" fn() = std::mem::transmute(hook);
+            hook();
+        }
+    }
+
+    /// Calls `hook_step_objects` hooks; `after` selects hooks that were registered to
+    /// run after step_objects.
+    ///
+    /// # Safety
+    ///
+    /// The hooks are called as is; they must be valid to call now.
+    pub unsafe fn step_objects(&self, after: bool) {
+        for hook in self.hooks_with_arg("hook_step_objects", after as u32) {
+            let hook: unsafe extern "C" fn() = std::mem::transmute(hook);
+            hook();
+        }
+    }
+
+    /// Calls `(unit, orig)` hooks registered through `field` (`hook_step_order`,
+    /// `hook_draw_image`, ...), chaining them like a real host would.
+    ///
+    /// # Safety
+    ///
+    /// `field` must be a hook field taking `(unit, orig)` hooks, and `unit` has to be
+    /// valid for both the hooks and `orig`.
+    pub unsafe fn fire_unit_hooks(
+        &self,
+        field: &str,
+        unit: *mut c_void,
+        orig: unsafe extern "C" fn(*mut c_void),
+    ) {
+        // Hooks get called in reverse order of registration, last registered hook
+        // is called first, and the orig chain ends with `orig`.
+        thread_local! {
+            static UNIT_CHAIN: RefCell<Vec<(Vec<usize>, unsafe extern "C" fn(*mut c_void))>> =
+                const { RefCell::new(Vec::new()) };
+        }
+        unsafe extern "C" fn next(unit: *mut c_void) {
+            let (hook, orig) = UNIT_CHAIN.with(|chain| {
+                let mut chain = chain.borrow_mut();
+                let (hooks, orig) = chain.last_mut().unwrap();
+                (hooks.pop(), *orig)
+            });
+            match hook {
+                Some(hook) => {
+                    let hook: unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void)) =
+                        std::mem::transmute(hook);
+                    hook(unit, next);
+                }
+                None => orig(unit),
+            }
+        }
+
+        let hooks = self.hooks(field);
+        UNIT_CHAIN.with(|chain| chain.borrow_mut().push((hooks, orig)));
+        next(unit);
+        UNIT_CHAIN.with(|chain| chain.borrow_mut().pop());
+    }
+
+    /// Passes a single command through `hook_ingame_command` hooks.
+    ///
+    /// Hooks for the same id are chained in registration order; whatever reaches the end
+    /// of the chain is stored in `processed_commands`.
+    ///
+    /// # Safety
+    ///
+    /// The hooks are called with `data`; they must be valid to call now.
+    pub unsafe fn fire_ingame_command(&self, data: &[u8], player: u32, unique_player: u32) {
+        if data.is_empty() {
+            return;
+        }
+        let hooks = with_state(|s| {
+            s.ingame_commands.iter().map(|x| (x.id, x.hook)).collect::<Vec<_>>()
+        });
+        let state = ChainState {
+            hooks,
+            next: 0,
+            player,
+            unique_player,
+        };
+        run_command_chain(state, data);
+    }
+
+    /// Calls all save hooks, returning (tag, data) for each extension that wrote data.
+    ///
+    /// # Safety
+    ///
+    /// The hooks are called as is; they must be valid to call now.
+    pub unsafe fn save_extensions(&self) -> Vec<(String, Vec<u8>)> {
+        unsafe extern "C" fn add_data(data: *const u8, len: usize) {
+            let slice = std::slice::from_raw_parts(data, len);
+            CURRENT_SAVE.with(|x| x.borrow_mut().extend_from_slice(slice));
+        }
+
+        let hooks = with_state(|s| {
+            s.save_extensions.iter().map(|x| (x.tag.clone(), x.save)).collect::<Vec<_>>()
+        });
+        let mut result = Vec::new();
+        for (tag, save) in hooks {
+            if let Some(save) = save {
+                CURRENT_SAVE.with(|x| x.borrow_mut().clear());
+                save(add_data);
+                let data = CURRENT_SAVE.with(|x| std::mem::take(&mut *x.borrow_mut()));
+                if !data.is_empty() {
+                    result.push((tag, data));
+                }
+            }
+        }
+        result
+    }
+
+    /// Calls load hooks of extension `tag`. Returns false if any of them failed.
+    ///
+    /// # Safety
+    ///
+    /// The hooks are called as is; they must be valid to call now.
+    pub unsafe fn load_extension(&self, tag: &str, data: &[u8]) -> bool {
+        let hooks = with_state(|s| {
+            s.save_extensions.iter()
+                .filter(|x| x.tag == tag)
+                .filter_map(|x| x.load)
+                .collect::<Vec<_>>()
+        });
+        let mut ok = true;
+        for load in hooks {
+            if load(data.as_ptr(), data.len()) == 0 {
+                ok = false;
+            }
+        }
+        ok
+    }
+
+    /// Calls init hooks of all save extensions.
+    ///
+    /// # Safety
+    ///
+    /// The hooks are called as is; they must be valid to call now.
+    pub unsafe fn init_extensions(&self) {
+        let hooks = with_state(|s| {
+            s.save_extensions.iter().map(|x| x.init).collect::<Vec<_>>()
+        });
+        for init in hooks {
+            init();
+        }
+    }
+}
+
+impl Default for MockHost {
+    fn default() -> MockHost {
+        MockHost::new()
+    }
+}
+
+impl Drop for MockHost {
+    fn drop(&mut self) {
+        STATE.with(|state| *state.borrow_mut() = None);
+    }
+}
+
+unsafe fn run_command_chain(mut state: ChainState, data: &[u8]) {
+    let id = data[0];
+    let pos = state.hooks[state.next..].iter().position(|x| x.0 == id);
+    match pos {
+        Some(pos) => {
+            let hook = state.hooks[state.next + pos].1;
+            state.next += pos + 1;
+            let (player, unique_player) = (state.player, state.unique_player);
+            COMMAND_CHAIN.with(|x| x.borrow_mut().push(state));
+            hook(data.as_ptr(), data.len() as u32, player, unique_player, command_chain_orig);
+            COMMAND_CHAIN.with(|x| x.borrow_mut().pop());
+        }
+        None => {
+            with_state(|s| s.processed_commands.push(data.into()));
+        }
+    }
+}
+
+unsafe extern "C" fn command_chain_orig(data: *const u8, len: u32) {
+    if len == 0 {
+        return;
+    }
+    let data = std::slice::from_raw_parts(data, len as usize);
+    let mut state = COMMAND_CHAIN.with(|x| x.borrow().last().cloned())
+        .expect("Ingame command orig called outside hook");
+    let prev_id = state.hooks[state.next - 1].0;
+    if prev_id != data[0] {
+        // Command id changed, start from first hook of the new id
+        state.next = 0;
+    }
+    run_command_chain(state, data);
+}
+
+unsafe fn c_str(ptr: *const u8) -> String {
+    CStr::from_ptr(ptr as *const _).to_string_lossy().into()
+}
+
+unsafe fn alloc_copy(data: &[u8], size: *mut usize) -> *mut u8 {
+    let boxed: Box<[u8]> = data.into();
+    let len = boxed.len();
+    let ptr = Box::into_raw(boxed) as *mut u8;
+    ALLOCATIONS.lock().unwrap().push((ptr as usize, len));
+    if !size.is_null() {
+        *size = len;
+    }
+    ptr
+}
+
+unsafe extern "C" fn free_memory(ptr: *mut u8) {
+    let mut allocations = ALLOCATIONS.lock().unwrap();
+    if let Some(pos) = allocations.iter().position(|x| x.0 == ptr as usize) {
+        let (_, len) = allocations.swap_remove(pos);
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+}
+
+unsafe extern "C" fn write_exe_memory(addr: usize, data: *const u8, len: usize) -> u32 {
+    let data = std::slice::from_raw_parts(data, len);
+    with_state(|s| s.exe_patches.push((addr, data.into())));
+    1
+}
+
+unsafe extern "C" fn warn_unsupported_feature(feature: *const u8) {
+    let feature = c_str(feature);
+    with_state(|s| s.warnings.push(feature));
+}
+
+unsafe extern "C" fn read_file() -> unsafe extern "C" fn(*const u8, *mut usize) -> *mut u8 {
+    unsafe extern "C" fn actual(path: *const u8, size: *mut usize) -> *mut u8 {
+        let path = c_str(path);
+        with_state(|s| match s.files.get(&path) {
+            Some(data) => alloc_copy(data, size),
+            None => null_mut(),
+        })
+    }
+    actual
+}
+
+unsafe extern "C" fn read_map_file() -> Option<unsafe extern "C" fn(*const u8, *mut usize) -> *mut u8> {
+    unsafe extern "C" fn actual(path: *const u8, size: *mut usize) -> *mut u8 {
+        let path = c_str(path);
+        with_state(|s| match s.map_files.get(&path) {
+            Some(data) => alloc_copy(data, size),
+            None => null_mut(),
+        })
+    }
+    Some(actual)
+}
+
+// Only used with function pointer `T`, which makes the Option FFI-safe.
+#[allow(improper_ctypes_definitions)]
+unsafe extern "C" fn none<T>() -> Option<T> {
+    None
+}
+
+fn var_value(var: VarId) -> Option<usize> {
+    with_state(|s| s.vars.get(&(var as u16)).map(|x| x.0))
+}
+
+macro_rules! var_getters {
+    ($($name:ident: $var:ident -> $ret:ty,)*) => {
+        $(
+            unsafe extern "C" fn $name() -> Option<unsafe extern "C" fn() -> $ret> {
+                unsafe extern "C" fn actual() -> $ret {
+                    var_value(VarId::$var).unwrap_or(0) as $ret
+                }
+                var_value(VarId::$var).map(|_| actual as unsafe extern "C" fn() -> $ret)
+            }
+        )*
+    };
+}
+
+var_getters! {
+    game: Game -> *mut c_void,
+    rng_seed: RngSeed -> u32,
+    ai_regions: AiRegions -> *mut c_void,
+    player_ai: PlayerAi -> *mut c_void,
+    first_active_unit: FirstActiveUnit -> *mut c_void,
+    first_hidden_unit: FirstHiddenUnit -> *mut c_void,
+    units: Units -> *mut c_void,
+    selections: Selections -> *mut c_void,
+    first_ai_script: FirstAiScript -> *mut c_void,
+    client_selection: ClientSelection -> *mut c_void,
+    first_guard_ai: FirstGuardAi -> *mut c_void,
+    pathing: Pathing -> *mut c_void,
+    first_free_ai_script: FirstFreeAiScript -> *mut c_void,
+    player_ai_towns: ActiveAiTowns -> *mut c_void,
+    map_tile_flags: MapTileFlags -> *mut u32,
+    players: Players -> *mut c_void,
+    get_iscript_bin: IscriptBin -> *mut c_void,
+    sprite_hlines: SpriteHlines -> *mut *mut c_void,
+    sprite_hlines_end: SpriteHlinesEnd -> *mut *mut c_void,
+    first_active_bullet: FirstActiveBullet -> *mut c_void,
+    first_lone_sprite: FirstLoneSprite -> *mut c_void,
+    first_fow_sprite: FirstFowSprite -> *mut c_void,
+    is_replay: IsReplay -> u32,
+    local_player_id: LocalPlayerId -> u32,
+    graphic_layers: GraphicLayers -> *mut c_void,
+    is_multiplayer: IsMultiplayer -> u32,
+}
+
+unsafe extern "C" fn print_text() -> Option<unsafe extern "C" fn(*const u8)> {
+    unsafe extern "C" fn actual(text: *const u8) {
+        let text = c_str(text);
+        with_state(|s| s.printed.push(text));
+    }
+    Some(actual)
+}
+
+unsafe extern "C" fn send_command() -> Option<unsafe extern "C" fn(*const c_void, u32)> {
+    unsafe extern "C" fn actual(data: *const c_void, len: u32) {
+        let data = std::slice::from_raw_parts(data as *const u8, len as usize);
+        with_state(|s| s.sent_commands.push(data.into()));
+    }
+    Some(actual)
+}
+
+macro_rules! recording_hooks {
+    ($($name:ident: $hook:ty,)*) => {
+        $(
+            unsafe extern "C" fn $name(hook: $hook) -> u32 {
+                with_state(|s| s.hooks.push((stringify!($name), hook as usize)));
+                1
+            }
+        )*
+    };
+}
+
+type UnitHook = unsafe extern "C" fn(*mut c_void, unsafe extern "C" fn(*mut c_void));
+type CommandHook =
+    unsafe extern "C" fn(*const c_void, u32, u32, unsafe extern "C" fn(*const c_void, u32, u32));
+type DialogHook = unsafe extern "C" fn(
+    *mut c_void,
+    usize,
+    *mut c_void,
+    unsafe extern "C" fn(*mut c_void, usize, *mut c_void) -> u32,
+) -> u32;
+
+recording_hooks! {
+    hook_step_order: UnitHook,
+    hook_step_order_hidden: UnitHook,
+    hook_process_commands: CommandHook,
+    hook_process_lobby_commands: CommandHook,
+    hook_send_command: unsafe extern "C" fn(*mut c_void, u32, unsafe extern "C" fn(*mut c_void, u32)),
+    hook_step_secondary_order: UnitHook,
+    hook_game_screen_rclick: UnitHook,
+    hook_draw_image: UnitHook,
+    hook_run_dialog: DialogHook,
+    hook_spawn_dialog: DialogHook,
+    hook_create_bullet: unsafe extern "C" fn(
+        u32, i32, i32, u32, u32, *mut c_void,
+        unsafe extern "C" fn(u32, i32, i32, u32, u32, *mut c_void) -> *mut c_void,
+    ) -> *mut c_void,
+    hook_create_unit: unsafe extern "C" fn(
+        u32, i32, i32, u32, *const u8,
+        unsafe extern "C" fn(u32, i32, i32, u32, *const u8) -> *mut c_void,
+    ) -> *mut c_void,
+    hook_init_units: unsafe extern "C" fn(unsafe extern "C" fn()),
+    hook_layout_draw_text: unsafe extern "C" fn(
+        u32, u32, *const u8, *mut u32, u32, *mut u32, u32, u32,
+        unsafe extern "C" fn(u32, u32, *const u8, *mut u32, u32, *mut u32, u32, u32) -> *const u8,
+    ) -> *const u8,
+    hook_draw_graphic_layers: unsafe extern "C" fn(u32, unsafe extern "C" fn(u32)),
+    hook_ai_step_region: unsafe extern "C" fn(u32, u32, unsafe extern "C" fn(u32, u32)),
+    hook_play_sound: unsafe extern "C" fn(
+        u32, f32, *mut c_void, *mut i32, *mut i32,
+        unsafe extern "C" fn(u32, f32, *mut c_void, *mut i32, *mut i32) -> u32,
+    ) -> u32,
+    hook_game_loop_start: unsafe extern "C" fn(),
+    hook_ai_focus_disabled: UnitHook,
+    hook_ai_focus_air: UnitHook,
+}
+
+unsafe extern "C" fn hook_on_first_file_access(hook: unsafe extern "C" fn()) {
+    with_state(|s| s.hooks.push(("hook_on_first_file_access", hook as usize)));
+}
+
+unsafe extern "C" fn hook_step_objects(hook: unsafe extern "C" fn(), after: u32) -> u32 {
+    with_state(|s| s.hook_args.push(("hook_step_objects", after, hook as usize)));
+    1
+}
+
+unsafe extern "C" fn hook_aiscript_opcode(
+    opcode: u32,
+    hook: unsafe extern "C" fn(*mut c_void),
+) -> u32 {
+    with_state(|s| s.hook_args.push(("hook_aiscript_opcode", opcode, hook as usize)));
+    1
+}
+
+unsafe extern "C" fn hook_iscript_opcode(
+    opcode: u32,
+    hook: unsafe extern "C" fn(*mut c_void, *mut c_void, *mut c_void, u32, *mut u32),
+) -> u32 {
+    with_state(|s| s.hook_args.push(("hook_iscript_opcode", opcode, hook as usize)));
+    1
+}
+
+unsafe extern "C" fn hook_renderer(ty: u32, hook: unsafe extern "C" fn()) -> u32 {
+    with_state(|s| s.hook_args.push(("hook_renderer", ty, hook as usize)));
+    1
+}
+
+unsafe extern "C" fn hook_file_read(
+    prefix: *const u8,
+    hook: unsafe extern "C" fn(*const u8, *mut u32) -> *mut u8,
+) {
+    let prefix = c_str(prefix);
+    with_state(|s| s.file_read_hooks.push((prefix, hook as usize)));
+}
+
+unsafe extern "C" fn dat(_: u32) -> Option<unsafe extern "C" fn() -> *mut c_void> {
+    None
+}
+
+unsafe extern "C" fn extended_dat(
+    _: u32,
+) -> Option<unsafe extern "C" fn(*mut usize) -> *mut c_void> {
+    None
+}
+
+unsafe extern "C" fn misc_ui_state(_: usize) -> Option<unsafe extern "C" fn(*mut u8)> {
+    None
+}
+
+unsafe extern "C" fn extend_save(
+    tag: *const u8,
+    save: SaveHook,
+    load: LoadHook,
+    init: unsafe extern "C" fn(),
+) -> u32 {
+    let tag = c_str(tag);
+    with_state(|s| s.save_extensions.push(SaveExtension {
+        tag,
+        save,
+        load,
+        init,
+    }));
+    1
+}
+
+unsafe extern "C" fn hook_ingame_command(
+    cmd: u32,
+    hook: IngameCommandHook,
+    length: Option<CommandLength>,
+) -> u32 {
+    if cmd >= 0x100 {
+        return 0;
+    }
+    with_state(|s| s.ingame_commands.push(IngameCommand {
+        id: cmd as u8,
+        hook,
+        length,
+    }));
+    1
+}
+
+unsafe extern "C" fn set_campaigns(_: *const *mut c_void) -> u32 {
+    0
+}
+
+unsafe extern "C" fn set_prism_shaders(_: u32, _: u32, _: *const u8, _: u32) -> u32 {
+    0
+}
+
+/// Returns all messages given to `crash_with_message` by any `MockHost`.
+pub fn crash_messages() -> Vec<String> {
+    CRASHES.lock().unwrap().clone()
+}
+
+unsafe extern "C" fn crash_with_message(msg: *const u8) -> ! {
+    let msg = c_str(msg);
+    CRASHES.lock().unwrap().push(msg.clone());
+    if with_state(|s| s.park_on_crash) {
+        loop {
+            std::thread::park();
+        }
+    }
+    panic!("crash_with_message: {}", msg);
+}
+
+unsafe extern "C" fn extended_arrays(out: *mut *mut ExtendedArray) -> usize {
+    if !out.is_null() {
+        *out = null_mut();
+    }
+    0
+}
+
+unsafe extern "C" fn hook_func(id: u16, hook: usize) -> u32 {
+    let func = match FuncId::from_u16(id) {
+        Some(s) => s,
+        None => return 0,
+    };
+    with_state(|s| {
+        if s.funcs.contains_key(&id) {
+            s.func_hooks.push((func, hook));
+            1
+        } else {
+            0
+        }
+    })
+}
+
+unsafe extern "C" fn get_func(id: u16) -> Option<unsafe extern "C" fn()> {
+    if id >= MAX_FUNC_ID {
+        return None;
+    }
+    with_state(|s| s.funcs.get(&id).copied())
+}
+
+unsafe extern "C" fn load_vars(vars: *const u16, results: *mut u8, len: usize) {
+    let vars = std::slice::from_raw_parts(vars, len);
+    let results = std::slice::from_raw_parts_mut(results, len);
+    with_state(|s| {
+        for (&var, out) in vars.iter().zip(results.iter_mut()) {
+            *out = if var >= MAX_VAR_ID {
+                1
+            } else {
+                s.vars.get(&var).map(|x| x.1).unwrap_or(0)
+            };
+        }
+    });
+}
+
+unsafe extern "C" fn read_vars(vars: *const u16, results: *mut usize, len: usize) {
+    let vars = std::slice::from_raw_parts(vars, len);
+    let results = std::slice::from_raw_parts_mut(results, len);
+    with_state(|s| {
+        for (var, out) in vars.iter().zip(results.iter_mut()) {
+            *out = s.vars.get(var).map(|x| x.0).unwrap_or(0);
+        }
+    });
+}
+
+unsafe extern "C" fn write_vars(vars: *const u16, values: *const usize, len: usize) {
+    let vars = std::slice::from_raw_parts(vars, len);
+    let values = std::slice::from_raw_parts(values, len);
+    with_state(|s| {
+        for (var, &value) in vars.iter().zip(values.iter()) {
+            if let Some(entry) = s.vars.get_mut(var) {
+                if entry.1 == 3 {
+                    entry.0 = value;
+                }
+            }
+        }
+    });
+}
+
+unsafe extern "C" fn debug_ui_add_tab(
+    _: *const FfiStr,
+    _: *const FfiStr,
+    _: DebugUiDrawCb,
+    _: *mut c_void,
+) -> usize {
+    0
+}
+
+unsafe extern "C" fn debug_ui_add_log() -> *mut DebugUiLog {
+    null_mut()
+}
+
+unsafe extern "C" fn debug_log_add_data(
+    _: *mut DebugUiLog,
+    _: *const FfiStr,
+    _: *const ComplexLineParam,
+    _: usize,
+    _: *mut c_void,
+) {
+}
+
+unsafe extern "C" fn debug_log_clear(_: *mut DebugUiLog) {
+}
+
+unsafe extern "C" fn create_extended_unit_field(name: *const FfiStr) -> u32 {
+    let name = (*name).string_lossy().into_owned();
+    with_state(|s| {
+        let index = match s.extended_unit_fields.iter().position(|x| *x == name) {
+            Some(s) => s,
+            None => {
+                s.extended_unit_fields.push(name);
+                s.extended_unit_fields.len() - 1
+            }
+        };
+        index as u32 + 1
+    })
+}
+
+unsafe extern "C" fn read_extended_unit_field(unit: u32, field: u32) -> u32 {
+    with_state(|s| s.extended_unit_values.get(&(unit, field)).copied().unwrap_or(0))
+}
+
+unsafe extern "C" fn write_extended_unit_field(unit: u32, field: u32, value: u32) -> u32 {
+    with_state(|s| s.extended_unit_values.insert((unit, field), value).unwrap_or(0))
+}
+
+unsafe extern "C" fn mutate_dat(_: u32, _: u32) -> *mut c_void {
+    null_mut()
+}
+
+fn api() -> PluginApi {
+    PluginApi {
+        version: VERSION,
+        max_func_id: MAX_FUNC_ID,
+        free_memory,
+        write_exe_memory,
+        warn_unsupported_feature,
+        read_file,
+        game,
+        rng_seed,
+        hook_step_objects,
+        hook_aiscript_opcode,
+        ai_regions,
+        player_ai,
+        get_region: none,
+        change_ai_region_state: none,
+        first_active_unit,
+        first_hidden_unit,
+        issue_order: none,
+        print_text,
+        hook_on_first_file_access,
+        hook_step_order,
+        hook_step_order_hidden,
+        dat,
+        hook_process_commands,
+        hook_process_lobby_commands,
+        hook_send_command,
+        hook_step_secondary_order,
+        extend_save,
+        hook_ingame_command,
+        units,
+        selections,
+        first_ai_script,
+        hook_game_screen_rclick,
+        client_selection,
+        dat_requirements: none,
+        first_guard_ai,
+        pathing,
+        set_first_ai_script: none,
+        first_free_ai_script,
+        set_first_free_ai_script: none,
+        player_ai_towns,
+        map_tile_flags,
+        players,
+        hook_draw_image,
+        hook_renderer,
+        get_iscript_bin,
+        set_iscript_bin: none,
+        hook_iscript_opcode,
+        sprite_hlines,
+        sprite_hlines_end,
+        hook_file_read,
+        first_active_bullet,
+        first_lone_sprite,
+        add_overlay_iscript: none,
+        set_campaigns,
+        hook_run_dialog,
+        send_command,
+        ai_update_attack_target: none,
+        update_visibility_point: none,
+        create_lone_sprite: none,
+        step_iscript: none,
+        is_outside_game_screen: none,
+        screen_pos: none,
+        ui_scale: none,
+        first_fow_sprite,
+        is_replay,
+        local_player_id,
+        unit_array_len: none,
+        draw_cursor_marker: none,
+        hook_spawn_dialog,
+        misc_ui_state,
+        create_bullet: none,
+        hook_create_bullet,
+        create_unit: none,
+        hook_create_unit,
+        finish_unit_pre: none,
+        finish_unit_post: none,
+        get_sprite_position: none,
+        set_sprite_position: none,
+        hook_init_units,
+        get_tooltip_draw_func: none,
+        set_tooltip_draw_func: none,
+        hook_layout_draw_text,
+        hook_draw_graphic_layers,
+        graphic_layers,
+        set_prism_shaders,
+        crash_with_message,
+        ai_attack_prepare: none,
+        hook_ai_step_region,
+        extended_arrays,
+        extended_dat,
+        give_ai: none,
+        hook_play_sound,
+        is_multiplayer,
+        hook_game_loop_start,
+        active_iscript_objects: none,
+        hook_ai_focus_disabled,
+        hook_ai_focus_air,
+        unit_base_strength: none,
+        read_map_file,
+        hook_func,
+        get_func,
+        load_vars,
+        read_vars,
+        write_vars,
+        debug_ui_add_tab,
+        debug_ui_add_log,
+        debug_log_add_data,
+        debug_log_clear,
+        create_extended_unit_field,
+        read_extended_unit_field,
+        write_extended_unit_field,
+        mutate_dat,
+    }
+}
@@ -0,0 +1,188 @@
+//! Panic-catching adapters for `PluginApi` hook callbacks.
+//!
+//! Unwinding out of an `extern "C"` function aborts the process without saying why.
+//! Each function here takes a Rust function or closure with the signature of one
+//! `PluginApi` callback and returns an `extern "C"` function that calls it inside
+//! `catch_unwind`. A panic is reported through `crash_with_message` with the hook name
+//! and panic message.
+//!
+//! ```ignore
+//! api.hook_step_order(panic_safe::step_order(|unit, orig| unsafe {
+//!     orig(unit);
+//! }))?;
+//! ```
+//!
+//! Like `funcs`, the closures cannot capture anything; closures that need state can use
+//! `trampoline` instead. `set_api` has to be called before a panic can be reported; the
+//! `entry!` macro does that. Without it the message is written to stderr and the process
+//! aborted.
+
+use std::any::Any;
+use std::ffi::c_void;
+use std::mem;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::api::SamaseApi;
+use crate::entry::panic_message;
+use crate::DebugUiDraw;
+
+static API: AtomicPtr<SamaseApi> = AtomicPtr::new(null_mut());
+
+/// Sets the `SamaseApi` used to report panics.
+pub fn set_api(api: &'static SamaseApi) {
+    API.store(api as *const SamaseApi as *mut SamaseApi, Ordering::Release);
+}
+
+/// Calls `func`, reporting a panic as a crash in `kind` hook.
+pub fn catch<R, F: FnOnce() -> R>(kind: &str, func: F) -> R {
+    match catch_unwind(AssertUnwindSafe(func)) {
+        Ok(o) => o,
+        Err(payload) => hook_panicked(kind, &*payload),
+    }
+}
+
+fn hook_panicked(kind: &str, payload: &(dyn Any + Send)) -> ! {
+    let message = format!("Panic in {} hook: {}", kind, panic_message(payload));
+    let api = API.load(Ordering::Acquire);
+    if !api.is_null() {
+        unsafe {
+            let api = &*api;
+            // Returns only if the host doesn't have it.
+            let _ = api.crash_with_message(&message);
+        }
+    }
+    eprintln!("{}", message);
+    std::process::abort();
+}
+
+macro_rules! safe_hooks {
+    ($(
+        $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;
+    )*) => {
+        $(
+            /// Wraps a `
+            #[doc = stringify!($name)]
+            /// ` hook.
+            pub fn $name<F>(func: F) -> unsafe extern "C" fn($($ty),*) $(-> $ret)?
+            where F: Fn($($ty),*) $(-> $ret)? + Copy + 'static
+            {
+                unsafe extern "C" fn shim<F>($($arg: $ty),*) $(-> $ret)?
+                where F: Fn($($ty),*) $(-> $ret)? + Copy + 'static
+                {
+                    const {
+                        assert!(
+                            mem::size_of::<F>() == 0,
+                            "Hook closures cannot capture variables",
+                        );
+                    }
+                    // Zero-sized, so there is no data to initialize
+                    let func: F = mem::zeroed();
+                    catch(stringify!($name), move || func($($arg),*))
+                }
+
+                let _ = func;
+                shim::<F>
+            }
+        )*
+    };
+}
+
+pub(crate) type UnitOrig = unsafe extern "C" fn(*mut c_void);
+pub(crate) type CommandOrig = unsafe extern "C" fn(*const c_void, u32, u32);
+pub(crate) type DialogOrig = unsafe extern "C" fn(*mut c_void, usize, *mut c_void) -> u32;
+pub(crate) type CreateBulletOrig =
+    unsafe extern "C" fn(u32, i32, i32, u32, u32, *mut c_void) -> *mut c_void;
+pub(crate) type CreateUnitOrig =
+    unsafe extern "C" fn(u32, i32, i32, u32, *const u8) -> *mut c_void;
+pub(crate) type LayoutDrawTextOrig =
+    unsafe extern "C" fn(u32, u32, *const u8, *mut u32, u32, *mut u32, u32, u32) -> *const u8;
+pub(crate) type PlaySoundOrig =
+    unsafe extern "C" fn(u32, f32, *mut c_void, *mut i32, *mut i32) -> u32;
+
+safe_hooks! {
+    step_objects();
+    aiscript_opcode(script: *mut c_void);
+    on_first_file_access();
+    step_order(unit: *mut c_void, orig: UnitOrig);
+    step_order_hidden(unit: *mut c_void, orig: UnitOrig);
+    step_secondary_order(unit: *mut c_void, orig: UnitOrig);
+    game_screen_rclick(event: *mut c_void, orig: UnitOrig);
+    draw_image(image: *mut c_void, orig: UnitOrig);
+    ai_focus_disabled(unit: *mut c_void, orig: UnitOrig);
+    ai_focus_air(unit: *mut c_void, orig: UnitOrig);
+    process_commands(data: *const c_void, len: u32, replayed: u32, orig: CommandOrig);
+    process_lobby_commands(data: *const c_void, len: u32, replayed: u32, orig: CommandOrig);
+    send_command(
+        data: *mut c_void,
+        len: u32,
+        orig: unsafe extern "C" fn(*mut c_void, u32)
+    );
+    save(add_data: unsafe extern "C" fn(*const u8, usize));
+    load(data: *const u8, len: usize) -> u32;
+    save_init();
+    ingame_command(
+        data: *const u8,
+        len: u32,
+        player: u32,
+        unique_player: u32,
+        orig: unsafe extern "C" fn(*const u8, u32)
+    );
+    command_length(data: *const u8, max_len: u32) -> u32;
+    renderer();
+    iscript_opcode(
+        pos: *mut c_void,
+        iscript: *mut c_void,
+        image: *mut c_void,
+        dry_run: u32,
+        speed_out: *mut u32
+    );
+    file_read(path: *const u8, size_out: *mut u32) -> *mut u8;
+    run_dialog(dialog: *mut c_void, unk: usize, event_handler: *mut c_void, orig: DialogOrig)
+        -> u32;
+    spawn_dialog(dialog: *mut c_void, unk: usize, event_handler: *mut c_void, orig: DialogOrig)
+        -> u32;
+    create_bullet(
+        bullet_id: u32,
+        x: i32,
+        y: i32,
+        player: u32,
+        direction: u32,
+        parent: *mut c_void,
+        orig: CreateBulletOrig
+    ) -> *mut c_void;
+    create_unit(
+        unit_id: u32,
+        x: i32,
+        y: i32,
+        player: u32,
+        skin: *const u8,
+        orig: CreateUnitOrig
+    ) -> *mut c_void;
+    init_units(orig: unsafe extern "C" fn());
+    tooltip_draw(control: *mut c_void);
+    layout_draw_text(
+        a1: u32,
+        a2: u32,
+        text: *const u8,
+        a4: *mut u32,
+        a5: u32,
+        a6: *mut u32,
+        a7: u32,
+        a8: u32,
+        orig: LayoutDrawTextOrig
+    ) -> *const u8;
+    draw_graphic_layers(layer: u32, orig: unsafe extern "C" fn(u32));
+    ai_step_region(player: u32, region: u32, orig: unsafe extern "C" fn(u32, u32));
+    play_sound(
+        sound: u32,
+        volume: f32,
+        unit: *mut c_void,
+        x: *mut i32,
+        y: *mut i32,
+        orig: PlaySoundOrig
+    ) -> u32;
+    game_loop_start();
+    debug_ui_draw(draw: *const DebugUiDraw, ctx: *mut c_void);
+}
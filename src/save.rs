@@ -1,10 +1,12 @@
 //! Helper code for implementing save hooks that both 1.16.1 and SC:R patches can use.
 //! `mod save_file` has the code for reading save format extensions, while this module has
 //! hook code / state.
-use std::cell::{RefCell};
-use std::io::{self, Write, SeekFrom};
+use std::cell::{Cell};
+use std::io::{self, SeekFrom};
+use std::ptr::null_mut;
 
 use byteorder::{WriteBytesExt, LE};
+use flate2::{Compress, Compression, FlushCompress, Status};
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, MutexGuard, const_mutex};
 use quick_error::quick_error;
@@ -12,10 +14,32 @@ use thread_local::ThreadLocal;
 
 pub use super::{SaveHook, LoadHook};
 pub use crate::save_file::{File};
-use crate::save_file::{self, SAVE_MAGIC, SAVE_VERSION, SerializedChunk};
+pub use crate::save_file::Limits;
+use crate::save_file::{
+    self, COMPRESSION_DEFLATE, COMPRESSION_NONE, FLAG_CHECKSUM, SAVE_CONTINUATION_MAGIC,
+    SAVE_MAGIC, SAVE_VERSION, SerializedChunk,
+};
 
 static SAVE_HOOKS: Mutex<Vec<Hook>> = const_mutex(Vec::new());
-static CURRENT_HOOK: Lazy<ThreadLocal<RefCell<Vec<u8>>>> = Lazy::new(|| ThreadLocal::new());
+static LIMITS: Mutex<Limits> = const_mutex(Limits::DEFAULT);
+static CURRENT_SAVE: Lazy<ThreadLocal<Cell<CurrentSave>>> = Lazy::new(ThreadLocal::new);
+static LOADING_CHUNK: Lazy<ThreadLocal<Cell<Option<ChunkHeader>>>> =
+    Lazy::new(ThreadLocal::new);
+
+/// State of `call_save_hooks` on this thread, null if a save hook isn't being called.
+#[derive(Copy, Clone)]
+struct CurrentSave(*mut SaveState<'static>);
+
+unsafe impl Send for CurrentSave {}
+
+/// Per-chunk header values of the chunk being loaded.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ChunkHeader {
+    /// Set by the plugin with `set_chunk_version` when saving.
+    pub version: u32,
+    /// `save_file` chunk flags.
+    pub flags: u32,
+}
 
 quick_error! {
     #[derive(Debug)]
@@ -55,6 +79,39 @@ pub fn add_hook(tag: String, save: SaveHook, load: LoadHook, init: unsafe extern
     });
 }
 
+/// Sets version of the data that the currently running save hook writes.
+///
+/// Does nothing if called outside a save hook. The version is 0 if this isn't called.
+pub fn set_chunk_version(version: u32) {
+    let state = current_save();
+    if !state.is_null() {
+        unsafe {
+            if let Some(ref mut chunk) = (*state).chunk {
+                chunk.version = version;
+            }
+        }
+    }
+}
+
+fn current_save() -> *mut SaveState<'static> {
+    CURRENT_SAVE.get().map(|x| x.get().0).unwrap_or(null_mut())
+}
+
+/// Returns header of the chunk that the currently running load hook is loading,
+/// or `None` if called outside a load hook.
+pub fn loading_chunk() -> Option<ChunkHeader> {
+    LOADING_CHUNK.get().and_then(|x| x.get())
+}
+
+/// Sets size limits used by `call_save_hooks` and `call_load_hooks`.
+pub fn set_limits(limits: Limits) {
+    *LIMITS.lock() = limits;
+}
+
+pub fn limits() -> Limits {
+    *LIMITS.lock()
+}
+
 pub fn call_init_hooks() {
     let hooks = save_hooks();
     for hook in hooks.iter() {
@@ -64,119 +121,510 @@ pub fn call_init_hooks() {
     }
 }
 
-pub fn call_load_hooks<T: File>(mut file: T) -> Result<(), Error> {
+/// What `call_load_hooks_with` does when a chunk is corrupted.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CorruptChunkAction {
+    /// Don't call the load hook for the chunk, and continue with the next chunk.
+    Skip,
+    /// Return `save_file::Error::CorruptChunk`.
+    Abort,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Requirement {
+    /// Failing to load the chunk fails the entire load.
+    Required,
+    /// If the chunk is corrupted, it is skipped. If a load hook fails, all hooks of the
+    /// tag are reset by calling their init hook, and loading continues without the
+    /// rest of the chunks with that tag.
+    Optional,
+}
+
+/// A chunk, or the entire extension data if `tag` is `None`, failed to load.
+#[derive(Debug)]
+pub struct LoadFailure<'a> {
+    pub tag: Option<&'a str>,
+    pub error: &'a Error,
+    /// False if loading continues after this failure.
+    pub fatal: bool,
+}
+
+/// Returns message given to `File::warn`, or `None` to not show anything.
+pub type FailureReporter = fn(&LoadFailure) -> Option<String>;
+
+#[derive(Clone, Debug)]
+pub struct LoadPolicy {
+    /// Requirement of tags that aren't in `tags`.
+    pub default: Requirement,
+    pub tags: Vec<(String, Requirement)>,
+    /// Called for every failure.
+    pub report: FailureReporter,
+}
+
+impl LoadPolicy {
+    pub const DEFAULT: LoadPolicy = LoadPolicy {
+        default: Requirement::Required,
+        tags: Vec::new(),
+        report: default_report,
+    };
+
+    pub fn requirement(&self, tag: &str) -> Requirement {
+        self.tags.iter()
+            .rev()
+            .find(|x| x.0 == tag)
+            .map(|x| x.1)
+            .unwrap_or(self.default)
+    }
+}
+
+impl Default for LoadPolicy {
+    fn default() -> LoadPolicy {
+        LoadPolicy::DEFAULT
+    }
+}
+
+fn default_report(failure: &LoadFailure) -> Option<String> {
+    let result = if failure.fatal { "Loading failed" } else { "Continuing without it" };
+    match failure.tag {
+        Some(tag) => Some(format!("Couldn't load {}: {}. {}", tag, failure.error, result)),
+        None => Some(format!("Couldn't load plugin data: {}. {}", failure.error, result)),
+    }
+}
+
+static LOAD_POLICY: Mutex<LoadPolicy> = const_mutex(LoadPolicy::DEFAULT);
+
+pub fn set_load_policy(policy: LoadPolicy) {
+    *LOAD_POLICY.lock() = policy;
+}
+
+pub fn load_policy() -> LoadPolicy {
+    LOAD_POLICY.lock().clone()
+}
+
+/// Sets requirement of a single tag in the current `LoadPolicy`.
+pub fn set_requirement(tag: &str, requirement: Requirement) {
+    let mut policy = LOAD_POLICY.lock();
+    policy.tags.retain(|x| x.0 != tag);
+    policy.tags.push((tag.into(), requirement));
+}
+
+/// Loads all extension chunks, handling failures according to the current `LoadPolicy`.
+///
+/// Failures are reported to the player through `File::warn`.
+pub fn call_load_hooks<T: File>(file: T) -> Result<(), Error> {
+    let policy = load_policy();
+    call_load_hooks_with(file, |tag| match policy.requirement(tag) {
+        Requirement::Required => CorruptChunkAction::Abort,
+        Requirement::Optional => CorruptChunkAction::Skip,
+    })
+}
+
+/// Loads all extension chunks, calling `on_corrupt` with the tag of any chunk whose
+/// data is corrupted. Failing load hooks are handled according to the current
+/// `LoadPolicy`.
+pub fn call_load_hooks_with<T, F>(mut file: T, on_corrupt: F) -> Result<(), Error>
+where T: File,
+      F: FnMut(&str) -> CorruptChunkAction,
+{
+    let policy = load_policy();
+    let result = load_chunks(&mut file, &policy, on_corrupt);
+    if let Err((ref tag, ref error)) = result {
+        let failure = LoadFailure {
+            tag: tag.as_deref(),
+            error,
+            fatal: true,
+        };
+        report_failure(&mut file, &policy, &failure);
+    }
+    result.map_err(|x| x.1)
+}
+
+fn report_failure(file: &mut dyn File, policy: &LoadPolicy, failure: &LoadFailure) {
+    warn!("Save extension failure: {:?}", failure);
+    if let Some(msg) = (policy.report)(failure) {
+        file.warn(&msg);
+    }
+}
+
+/// On error returns also tag of the chunk that failed.
+fn load_chunks<T, F>(
+    file: &mut T,
+    policy: &LoadPolicy,
+    mut on_corrupt: F,
+) -> Result<(), (Option<String>, Error)>
+where T: File,
+      F: FnMut(&str) -> CorruptChunkAction,
+{
     let hooks = save_hooks();
-    let orig_pos = file.seek(SeekFrom::Current(0))?;
-    for chunk in save_file::iter_extensions(&mut file)? {
-        let chunk = chunk?;
+    let orig_pos = file.seek(SeekFrom::Current(0)).map_err(|e| (None, e.into()))?;
+    let chunks = save_file::iter_extensions_with_limits(file, &limits())
+        .map_err(|e| (None, e.into()))?;
+    // Tags that failed and were reset; rest of their chunks are skipped.
+    let mut failed_tags: Vec<String> = Vec::new();
+    for chunk in chunks {
+        let chunk = match chunk {
+            Ok(o) => o,
+            Err(save_file::Error::CorruptChunk(tag)) => {
+                let error = save_file::Error::CorruptChunk(tag.clone()).into();
+                match on_corrupt(&tag) {
+                    CorruptChunkAction::Skip => {
+                        let failure = LoadFailure {
+                            tag: Some(&tag),
+                            error: &error,
+                            fatal: false,
+                        };
+                        report_failure(file, policy, &failure);
+                        continue;
+                    }
+                    CorruptChunkAction::Abort => return Err((Some(tag), error)),
+                }
+            }
+            Err(e) => return Err((None, e.into())),
+        };
+        if chunk.data.is_empty() {
+            // Hook didn't save anything, or saved too much.
+            continue;
+        }
+        if failed_tags.contains(&chunk.tag) {
+            continue;
+        }
         debug!("Loading {}", chunk.tag);
         for hook in hooks.iter() {
             if hook.tag == chunk.tag {
                 trace!("Hook found");
                 if let Some(load) = hook.load {
                     trace!("Load hook found");
+                    let header = ChunkHeader {
+                        version: chunk.version,
+                        flags: chunk.flags,
+                    };
+                    let loading = LOADING_CHUNK.get_or(|| Cell::new(None));
+                    loading.set(Some(header));
                     let ok = unsafe {
                         load(chunk.data.as_ptr(), chunk.data.len())
                     };
+                    loading.set(None);
                     if ok == 0 {
-                        return Err(Error::HookFail(chunk.tag.into()));
+                        let error = Error::HookFail(chunk.tag.clone());
+                        if policy.requirement(&chunk.tag) == Requirement::Required {
+                            return Err((Some(chunk.tag), error));
+                        }
+                        let failure = LoadFailure {
+                            tag: Some(&chunk.tag),
+                            error: &error,
+                            fatal: false,
+                        };
+                        report_failure(file, policy, &failure);
+                        for hook in hooks.iter().filter(|x| x.tag == chunk.tag) {
+                            unsafe {
+                                (hook.init)();
+                            }
+                        }
+                        failed_tags.push(chunk.tag.clone());
+                        break;
                     }
                 }
             }
         }
     }
-    file.seek(SeekFrom::Start(orig_pos))?;
+    file.seek(SeekFrom::Start(orig_pos)).map_err(|e| (None, e.into()))?;
     Ok(())
 }
 
+/// Writes extension data to the file, splitting it to blocks of `Limits::block_size`.
+///
+/// Sizes of the blocks are written as `block_size`, and the last one gets fixed by
+/// `finish`.
+struct BlockWriter<'a> {
+    file: &'a mut dyn File,
+    /// File offset of the first block.
+    start: u64,
+    /// Amount of data written, not counting block headers.
+    pos: u64,
+    block_size: u64,
+    max_total_size: u64,
+}
+
+impl<'a> BlockWriter<'a> {
+    fn file_offset(&self, pos: u64) -> u64 {
+        let block = pos / self.block_size;
+        self.start + block * (self.block_size + 8) + 8 + pos % self.block_size
+    }
+
+    fn write(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        let total = self.pos + data.len() as u64;
+        if total > self.max_total_size {
+            let what = "extension data".into();
+            return Err(save_file::Error::LimitExceeded(what, total, self.max_total_size).into());
+        }
+        while !data.is_empty() {
+            let pos_in_block = self.pos % self.block_size;
+            if pos_in_block == 0 {
+                let magic = if self.pos == 0 { SAVE_MAGIC } else { SAVE_CONTINUATION_MAGIC };
+                self.file.write_u32::<LE>(magic)?;
+                self.file.write_u32::<LE>(self.block_size as u32)?;
+            }
+            let amount = ((self.block_size - pos_in_block) as usize).min(data.len());
+            self.file.write_all(&data[..amount])?;
+            self.pos += amount as u64;
+            data = &data[amount..];
+        }
+        Ok(())
+    }
+
+    /// Overwrites already written data at `pos`.
+    fn patch(&mut self, mut pos: u64, mut data: &[u8]) -> Result<(), Error> {
+        while !data.is_empty() {
+            let amount = ((self.block_size - pos % self.block_size) as usize).min(data.len());
+            self.file.seek(SeekFrom::Start(self.file_offset(pos)))?;
+            self.file.write_all(&data[..amount])?;
+            pos += amount as u64;
+            data = &data[amount..];
+        }
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    /// Fixes size of the last block.
+    fn finish(&mut self) -> Result<(), Error> {
+        if self.pos == 0 {
+            return Ok(());
+        }
+        let last = (self.pos - 1) / self.block_size;
+        let size = self.pos - last * self.block_size;
+        self.file.seek(SeekFrom::Start(self.start + last * (self.block_size + 8) + 4))?;
+        self.file.write_u32::<LE>(size as u32)?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    /// Makes the blocks look like unknown extensions after a failed save, as the file
+    /// can't be truncated.
+    fn invalidate(&mut self) {
+        if self.pos == 0 {
+            return;
+        }
+        let _ = self.finish();
+        if self.file.seek(SeekFrom::Start(self.start)).is_ok() {
+            let _ = self.file.write_u32::<LE>(0);
+        }
+        let _ = self.file.seek(SeekFrom::End(0));
+    }
+}
+
+/// Compresses data of a single hook as it is being added.
+struct ChunkWriter {
+    /// Created once there is data to compress.
+    compress: Option<Compress>,
+    crc: flate2::Crc,
+    /// Amount of data added, including data after the chunk became too large.
+    length: u64,
+    version: u32,
+    error: Option<Error>,
+}
+
+impl ChunkWriter {
+    fn new() -> ChunkWriter {
+        ChunkWriter {
+            compress: None,
+            crc: flate2::Crc::new(),
+            length: 0,
+            version: 0,
+            error: None,
+        }
+    }
+
+    fn add_data(&mut self, out: &mut BlockWriter, data: &[u8], limits: &Limits) {
+        self.length += data.len() as u64;
+        if self.error.is_some() || self.length > limits.max_chunk_size {
+            return;
+        }
+        self.crc.update(data);
+        let compress = self.compress.get_or_insert_with(|| {
+            Compress::new(Compression::default(), false)
+        });
+        if let Err(e) = deflate(compress, out, data, FlushCompress::None) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Finishes compression, and sets `chunk` fields other than tag.
+    ///
+    /// Chunks that are too large are written with length 0, as the data that was
+    /// already written can't be removed.
+    fn finish(
+        self,
+        out: &mut BlockWriter,
+        chunk: &mut SerializedChunk,
+        limits: &Limits,
+    ) -> Result<(), Error> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let too_large = self.length > limits.max_chunk_size;
+        chunk.version = self.version;
+        match self.compress {
+            Some(mut compress) => {
+                deflate(&mut compress, out, &[], FlushCompress::Finish)?;
+                chunk.flags = COMPRESSION_DEFLATE | FLAG_CHECKSUM;
+                chunk.compressed = compress.total_out() as usize;
+            }
+            None => {
+                chunk.flags = COMPRESSION_NONE | FLAG_CHECKSUM;
+                chunk.compressed = 0;
+            }
+        }
+        if too_large {
+            chunk.length = 0;
+            chunk.checksum = save_file::checksum(&[]);
+        } else {
+            chunk.length = self.length as usize;
+            chunk.checksum = self.crc.sum();
+        }
+        Ok(())
+    }
+}
+
+fn deflate(
+    compress: &mut Compress,
+    out: &mut BlockWriter,
+    mut input: &[u8],
+    flush: FlushCompress,
+) -> Result<(), Error> {
+    let mut buffer = [0u8; 0x1000];
+    loop {
+        let in_before = compress.total_in();
+        let out_before = compress.total_out();
+        let status = compress.compress(input, &mut buffer, flush)
+            .map_err(io::Error::other)?;
+        let consumed = (compress.total_in() - in_before) as usize;
+        let produced = (compress.total_out() - out_before) as usize;
+        input = &input[consumed..];
+        out.write(&buffer[..produced])?;
+        let done = match flush {
+            FlushCompress::Finish => status == Status::StreamEnd,
+            _ => input.is_empty() && produced < buffer.len(),
+        };
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+struct SaveState<'a> {
+    out: BlockWriter<'a>,
+    /// Chunk of the hook being called.
+    chunk: Option<ChunkWriter>,
+    limits: Limits,
+}
+
 pub fn call_save_hooks<T: File>(mut file: T) -> Result<(), Error> {
     unsafe extern "C" fn add_save_data(data: *const u8, len: usize) {
+        let state = current_save();
+        if state.is_null() {
+            return;
+        }
         let slice = std::slice::from_raw_parts(data, len);
-        let mut current_hook = CURRENT_HOOK.get().unwrap().borrow_mut();
-        current_hook.extend_from_slice(slice);
+        let state = &mut *state;
+        if let Some(ref mut chunk) = state.chunk {
+            chunk.add_data(&mut state.out, slice, &state.limits);
+        }
     }
 
-    let mut chunks = Vec::new();
-    let mut data = Vec::new();
+    let limits = limits();
     let hooks = save_hooks();
-    let current_hook_cell = CURRENT_HOOK.get_or(|| RefCell::new(Vec::new()));
-    current_hook_cell.replace(Vec::new());
-    let chunk_start = file.seek(SeekFrom::End(0))?;
+    let chunk_start = match file.seek(SeekFrom::End(0)) {
+        Ok(o) => o,
+        Err(e) => {
+            file.warn(&format!("Save failed: {}", e));
+            return Err(e.into());
+        }
+    };
     trace!("Writing save extension chunk starting from offset {:x}", chunk_start);
     // Format: (First 2 fields are part of SC:R extension header)
     // u32 magic
     // u32 rest_len
-    // u32 version (0)
+    // u32 version (1)
     // u64 extension_count
     // Extension chunks[extension_count] {
     //     u64 name_len
     //     char name[name_len] (Not null-terminated)
     //     u64 length
     //     u64 compressed_length
+    //     u32 chunk_version (Not in version 0)
+    //     u32 flags (Not in version 0, save_file::COMPRESSION_* etc.)
+    //     u32 checksum (If flags has FLAG_CHECKSUM; CRC32 of the uncompressed data)
     // }
-    // u8 chunk_data [compressed_length][extension_count] (Deflated)
-    let mut buffer = Vec::with_capacity(0x2000);
-    buffer.write_u32::<LE>(SAVE_MAGIC)?;
-    buffer.write_u32::<LE>(0)?;
-    buffer.write_u32::<LE>(SAVE_VERSION)?;
-    for hook in hooks.iter() {
-        if let Some(save) = hook.save {
-            unsafe {
-                save(add_save_data);
+    // u8 chunk_data [compressed_length][extension_count] (Deflated unless flags say otherwise)
+    // u32 chunk_start
+    // If the data after rest_len is larger than limits.block_size, it is split into
+    // multiple blocks, of which rest are SAVE_CONTINUATION_MAGIC blocks.
+    //
+    // Every hook gets a chunk, so that the header size is known before calling the hooks;
+    // hooks that don't save anything have length 0. The header is written once the
+    // data has been written.
+    let mut chunks = hooks.iter()
+        .map(|hook| SerializedChunk {
+            tag: hook.tag.clone(),
+            length: 0,
+            compressed: 0,
+            version: 0,
+            flags: COMPRESSION_DEFLATE | FLAG_CHECKSUM,
+            checksum: 0,
+        })
+        .collect::<Vec<_>>();
+    let mut state = SaveState {
+        out: BlockWriter {
+            file: &mut file,
+            start: chunk_start,
+            pos: 0,
+            block_size: u64::from(limits.block_size.max(1)),
+            max_total_size: limits.max_total_size,
+        },
+        chunk: None,
+        limits,
+    };
+    let state_ptr: *mut SaveState<'static> = unsafe { std::mem::transmute(&mut state) };
+    let current_save = CURRENT_SAVE.get_or(|| Cell::new(CurrentSave(null_mut())));
+    let result = (|| {
+        let state = unsafe { &mut *state_ptr };
+        state.out.write(&SAVE_VERSION.to_le_bytes())?;
+        state.out.write(&(chunks.len() as u64).to_le_bytes())?;
+        let headers_pos = state.out.pos;
+        let headers_size = chunks.iter().map(|x| x.header_size()).sum();
+        state.out.write(&vec![0; headers_size])?;
+        for (hook, chunk) in hooks.iter().zip(chunks.iter_mut()) {
+            state.chunk = Some(ChunkWriter::new());
+            if let Some(save) = hook.save {
+                let previous = current_save.replace(CurrentSave(state_ptr));
+                unsafe {
+                    save(add_save_data);
+                }
+                current_save.set(previous);
             }
-        }
-        let previous = current_hook_cell.replace(Vec::new());
-        if previous.len() != 0 {
-            if previous.len() > 0x0400_0000 {
-                file.warn(&format!(
+            let writer = state.chunk.take().unwrap();
+            if writer.length > state.limits.max_chunk_size {
+                state.out.file.warn(&format!(
                     "Save failed: extension {} produced too much data ({} bytes)",
-                    hook.tag, previous.len(),
+                    hook.tag, writer.length,
                 ));
-            } else {
-                chunks.push(SerializedChunk {
-                    tag: hook.tag.clone(),
-                    length: previous.len(),
-                    compressed: 0,
-                });
-                data.push(previous);
             }
+            writer.finish(&mut state.out, chunk, &state.limits)?;
+            trace!("Write save extension {} {:x}/{:x}", chunk.tag, chunk.length, chunk.compressed);
+        }
+        // Quick hack for 1.16.1 saves. Store samase chunk offset
+        // as last u32 of the file. (For SC:R it is stored among all other extended chunks)
+        state.out.write(&(chunk_start as u32).to_le_bytes())?;
+        let mut headers = Vec::with_capacity(headers_size);
+        for chunk in &chunks {
+            chunk.write_header(&mut headers)?;
         }
+        state.out.patch(headers_pos, &headers)?;
+        state.out.finish()
+    })();
+    if let Err(ref e) = result {
+        state.out.file.warn(&format!("Save failed: {}", e));
+        state.out.invalidate();
     }
-    buffer.write_u64::<LE>(chunks.len() as u64)?;
-    let chunks_size = chunks.iter()
-        .map(|x| (8usize * 3).wrapping_add(x.tag.len()))
-        .sum();
-    let chunks_start = buffer.len();
-    buffer.resize_with(chunks_start + chunks_size, || 0);
-    for (block, chunk) in data.iter().zip(chunks.iter_mut()) {
-        let compressed_size = {
-            let mut writer = flate2::write::DeflateEncoder::new(
-                &mut buffer,
-                flate2::Compression::default(),
-            );
-            writer.write_all(&block)?;
-            writer.try_finish()?;
-            writer.total_out() as usize
-        };
-        chunk.compressed = compressed_size;
-        trace!("Write save extension {} {:x}/{:x}", chunk.tag, chunk.length, chunk.compressed);
-    }
-
-    // Quick hack for 1.16.1 saves. Store samase chunk offset
-    // as last u32 of the file. (For SC:R it is stored among all other extended chunks)
-    buffer.write_u32::<LE>(chunk_start as u32)?;
-    // Fix header offsets
-    let chunk_size = buffer.len() - 8;
-    assert!(chunk_size < 0x1000000);
-    (&mut buffer[4..]).write_u32::<LE>(chunk_size as u32)?;
-    let mut out = &mut buffer[chunks_start..][..chunks_size];
-    for chunk in &chunks {
-        let tag = chunk.tag.as_bytes();
-        out.write_u64::<LE>(tag.len() as u64)?;
-        (&mut out[..tag.len()]).copy_from_slice(tag);
-        out = &mut out[tag.len()..];
-        out.write_u64::<LE>(chunk.length as u64)?;
-        out.write_u64::<LE>(chunk.compressed as u64)?;
-    }
-    file.write_all(&buffer).map_err(|x| x.into())
+    result
 }
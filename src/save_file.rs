@@ -2,11 +2,25 @@
 
 use std::io::{self, BufRead, Read, Write, Seek, SeekFrom};
 
-use byteorder::{ByteOrder, LittleEndian};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt, LE};
 use quick_error::quick_error;
 
 pub const SAVE_MAGIC: u32 = 0x53736d53;
-pub const SAVE_VERSION: u32 = 0;
+/// Magic of extension blocks continuing data of the previous block, used when the data
+/// is larger than `Limits::block_size`.
+pub const SAVE_CONTINUATION_MAGIC: u32 = 0x436d7353;
+/// Format version that is written. Version 0 saves, which have no chunk versions or
+/// flags, can still be read.
+pub const SAVE_VERSION: u32 = 1;
+
+/// Bits of chunk flags specifying how the chunk data is compressed.
+pub const COMPRESSION_MASK: u32 = 0xff;
+pub const COMPRESSION_DEFLATE: u32 = 0;
+pub const COMPRESSION_NONE: u32 = 1;
+/// The chunk header has a CRC32 of the uncompressed data after the flags.
+pub const FLAG_CHECKSUM: u32 = 0x100;
+/// Flags that this version knows; chunks with other flags can't be read.
+pub const KNOWN_FLAGS: u32 = COMPRESSION_MASK | FLAG_CHECKSUM;
 
 quick_error! {
     #[derive(Debug)]
@@ -18,6 +32,14 @@ quick_error! {
         BadSave {
             display("Invalid save")
         }
+        /// The chunk couldn't be decompressed, or its checksum didn't match.
+        CorruptChunk(tag: String) {
+            display("Save extension {} is corrupted", tag)
+        }
+        /// `what` is either "extension data" or the tag of a chunk.
+        LimitExceeded(what: String, size: u64, limit: u64) {
+            display("Save {} is too large ({} bytes, limit is {})", what, size, limit)
+        }
     }
 }
 
@@ -25,11 +47,75 @@ pub trait File: Read + Seek + Write {
     fn warn(&mut self, msg: &str);
 }
 
+/// Size limits used when reading and writing saves.
+///
+/// The limits are mainly to prevent allocating huge buffers based on a corrupted save.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Largest size of all extension data, including headers.
+    pub max_total_size: u64,
+    /// Largest uncompressed size of a single chunk.
+    pub max_chunk_size: u64,
+    /// Size of the extension blocks that the data is split to when writing.
+    pub block_size: u32,
+}
+
+impl Limits {
+    pub const DEFAULT: Limits = Limits {
+        max_total_size: 0x4000_0000,
+        max_chunk_size: 0x1000_0000,
+        // Saves smaller than this are readable by versions that didn't support
+        // continuation blocks.
+        block_size: 0x0100_0000,
+    };
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits::DEFAULT
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct SerializedChunk {
     pub tag: String,
     pub length: usize,
     pub compressed: usize,
+    pub version: u32,
+    pub flags: u32,
+    /// Valid if `flags` has `FLAG_CHECKSUM`.
+    pub checksum: u32,
+}
+
+impl SerializedChunk {
+    /// Size of the header that `write_header` writes.
+    #[cfg(feature = "implementer_helpers")]
+    pub fn header_size(&self) -> usize {
+        let checksum_size = if self.flags & FLAG_CHECKSUM != 0 { 4 } else { 0 };
+        8 * 3 + 4 * 2 + checksum_size + self.tag.len()
+    }
+
+    /// Writes the chunk header in `SAVE_VERSION` format.
+    pub fn write_header<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let tag = self.tag.as_bytes();
+        out.write_u64::<LE>(tag.len() as u64)?;
+        out.write_all(tag)?;
+        out.write_u64::<LE>(self.length as u64)?;
+        out.write_u64::<LE>(self.compressed as u64)?;
+        out.write_u32::<LE>(self.version)?;
+        out.write_u32::<LE>(self.flags)?;
+        if self.flags & FLAG_CHECKSUM != 0 {
+            out.write_u32::<LE>(self.checksum)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checksum used for chunks with `FLAG_CHECKSUM`.
+pub(crate) fn checksum(data: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    crc.sum()
 }
 
 pub struct IterExtensions {
@@ -42,6 +128,10 @@ pub struct IterExtensions {
 pub struct Chunk {
     pub tag: String,
     pub data: Vec<u8>,
+    /// Version of the chunk's data, chosen by the plugin that saved it.
+    /// Always 0 in version 0 saves.
+    pub version: u32,
+    pub flags: u32,
 }
 
 impl Iterator for IterExtensions {
@@ -55,16 +145,34 @@ impl Iterator for IterExtensions {
             let pos = self.pos;
             self.pos += 1;
             let chunk = &self.chunks[pos];
+            let slice = &self.buffer[self.buffer_pos..][..chunk.compressed];
+            // Advance already, so that a corrupted chunk doesn't prevent reading the
+            // following ones.
+            self.buffer_pos += chunk.compressed;
             let mut buf = vec![0; chunk.length];
-            {
-                let slice = &self.buffer[self.buffer_pos..][..chunk.compressed];
-                let mut reader = flate2::read::DeflateDecoder::new(slice);
-                reader.read_exact(&mut buf)?;
+            let ok = match chunk.flags & COMPRESSION_MASK {
+                COMPRESSION_DEFLATE => {
+                    let mut reader = flate2::read::DeflateDecoder::new(slice);
+                    reader.read_exact(&mut buf).is_ok()
+                }
+                _ => {
+                    if slice.len() == buf.len() {
+                        buf.copy_from_slice(slice);
+                        true
+                    } else {
+                        false
+                    }
+                }
+            };
+            let ok = ok && (chunk.flags & FLAG_CHECKSUM == 0 || checksum(&buf) == chunk.checksum);
+            if !ok {
+                return Err(Error::CorruptChunk(chunk.tag.clone()));
             }
-            self.buffer_pos += chunk.compressed;
             Ok(Chunk {
                 tag: chunk.tag.clone(),
                 data: buf,
+                version: chunk.version,
+                flags: chunk.flags,
             })
         };
         Some(next())
@@ -72,10 +180,17 @@ impl Iterator for IterExtensions {
 }
 
 pub fn iter_extensions<T: File>(file: &mut T) -> Result<IterExtensions, Error> {
+    iter_extensions_with_limits(file, &Limits::default())
+}
+
+pub fn iter_extensions_with_limits<T: File>(
+    file: &mut T,
+    limits: &Limits,
+) -> Result<IterExtensions, Error> {
     file.seek(SeekFrom::Start(0))?;
 
-    let buffer = read_extended_data(file).ok_or(Error::BadSave)?;
-    iter_extensions_from_data(buffer)
+    let buffer = read_extended_data(file, limits)?;
+    iter_extensions_from_data(buffer, limits)
 }
 
 struct ReadBytes<'a>(&'a [u8]);
@@ -104,10 +219,10 @@ impl<'a> ReadBytes<'a> {
     }
 }
 
-fn iter_extensions_from_data(buffer: Vec<u8>) -> Result<IterExtensions, Error> {
+fn iter_extensions_from_data(buffer: Vec<u8>, limits: &Limits) -> Result<IterExtensions, Error> {
     let mut read = ReadBytes(&buffer[..]);
-    let version = read.read_u32()?;
-    if version != SAVE_VERSION {
+    let format_version = read.read_u32()?;
+    if format_version > SAVE_VERSION {
         return Err(Error::BadSave);
     }
     let chunk_count = read.read_u64()? as usize;
@@ -120,9 +235,27 @@ fn iter_extensions_from_data(buffer: Vec<u8>) -> Result<IterExtensions, Error> {
             None => return Err(Error::BadSave),
         };
         read.0 = &read.0[name_len..];
-        let length = read.read_u64()? as usize;
-        let compressed = read.read_u64()? as usize;
-        if length > 0x0400_0000 {
+        let length = read.read_u64()?;
+        let compressed = read.read_u64()?;
+        let (version, flags) = match format_version {
+            0 => (0, COMPRESSION_DEFLATE),
+            _ => (read.read_u32()?, read.read_u32()?),
+        };
+        let checksum = match flags & FLAG_CHECKSUM != 0 {
+            true => read.read_u32()?,
+            false => 0,
+        };
+        if length > limits.max_chunk_size {
+            return Err(Error::LimitExceeded(name.into(), length, limits.max_chunk_size));
+        }
+        // Compressed data is in the buffer, so it can't be larger than usize,
+        // which gets checked below.
+        let length = length as usize;
+        let compressed = usize::try_from(compressed).map_err(|_| Error::BadSave)?;
+        let compression = flags & COMPRESSION_MASK;
+        if flags & !KNOWN_FLAGS != 0 ||
+            (compression != COMPRESSION_DEFLATE && compression != COMPRESSION_NONE)
+        {
             return Err(Error::BadSave);
         }
         compressed_sum = compressed_sum.checked_add(compressed)
@@ -131,6 +264,9 @@ fn iter_extensions_from_data(buffer: Vec<u8>) -> Result<IterExtensions, Error> {
             tag: name.into(),
             length,
             compressed,
+            version,
+            flags,
+            checksum,
         });
     }
     // Won't be exactly same since there's also 1161-compatibility u32
@@ -146,69 +282,119 @@ fn iter_extensions_from_data(buffer: Vec<u8>) -> Result<IterExtensions, Error> {
     });
 }
 
+/// Reads a block with `SAVE_MAGIC` at the current position, and any continuation blocks
+/// after it, stopping at `end` if given.
+///
+/// Returns the joined data and offset after the last block.
+fn read_block_chain<T: File>(
+    file: &mut T,
+    end: Option<u64>,
+    limits: &Limits,
+) -> Result<(Vec<u8>, u64), Error> {
+    let mut buffer = Vec::new();
+    let mut pos = file.stream_position()?;
+    let mut first = true;
+    while end.map(|end| pos < end).unwrap_or(true) {
+        let mut ext_size = [0u8; 8];
+        if let Err(e) = file.read_exact(&mut ext_size) {
+            if !first && e.kind() == io::ErrorKind::UnexpectedEof {
+                break;
+            }
+            return Err(e.into());
+        }
+        let magic = LittleEndian::read_u32(&ext_size);
+        let size = LittleEndian::read_u32(&ext_size[4..]);
+        let expected_magic = if first { SAVE_MAGIC } else { SAVE_CONTINUATION_MAGIC };
+        if magic != expected_magic {
+            if first {
+                return Err(Error::BadSave);
+            }
+            break;
+        }
+        let total = buffer.len() as u64 + u64::from(size);
+        if total > limits.max_total_size {
+            let what = "extension data".into();
+            return Err(Error::LimitExceeded(what, total, limits.max_total_size));
+        }
+        let start = buffer.len();
+        file.take(size as u64).read_to_end(&mut buffer)?;
+        if buffer.len() - start != size as usize {
+            return Err(Error::BadSave);
+        }
+        pos += 8 + u64::from(size);
+        first = false;
+    }
+    Ok((buffer, pos))
+}
+
 // Finds extended data with SAVE_MAGIC and reads it
 // If version < 4 (1.16.1), tries to find multiple of them and joins them together.
-fn read_extended_data<T: File>(file: &mut T) -> Option<Vec<u8>> {
-    let scr_ext_offset = read_scr_extension_offset(file)?;
+fn read_extended_data<T: File>(file: &mut T, limits: &Limits) -> Result<Vec<u8>, Error> {
+    let scr_ext_offset = read_scr_extension_offset(file).ok_or(Error::BadSave)?;
     if let Some(ext_offset) = scr_ext_offset {
-        file.seek(SeekFrom::Start(ext_offset.into())).ok()?;
+        file.seek(SeekFrom::Start(ext_offset.into()))?;
         loop {
             let mut ext_size = [0u8; 8];
-            file.read_exact(&mut ext_size).ok()?;
+            file.read_exact(&mut ext_size)?;
             let extension = LittleEndian::read_u32(&ext_size);
             let size = LittleEndian::read_u32(&ext_size[4..]);
             if extension == SAVE_MAGIC {
-                if size > 0x1000000 {
-                    return None;
-                }
-                let mut buffer = Vec::with_capacity(size as usize);
-                file.take(size as u64).read_to_end(&mut buffer).ok()?;
-                return Some(buffer);
+                file.seek(SeekFrom::Current(-8))?;
+                return read_block_chain(file, None, limits).map(|x| x.0);
             } else {
-                file.seek(SeekFrom::Current(size.into())).ok()?;
+                file.seek(SeekFrom::Current(size.into()))?;
             }
         }
     } else {
-        // Join multiple save blocks together
+        // Join multiple save blocks together (Each plugin using samase_shim writes its own)
         // Pretty hacky way to do it, parses single blocks to get point
         // where header `chunks` and `data` get split and then
         // joins { VERSION, chunk_count, chunks_0, chunks_1, ..., data_0, data_1, ... }
         // but the format makes it work since there are no offsets in header chunks.
+        // The chunk headers are rewritten in current format, as the blocks may have been
+        // written by plugins using different format versions.
+        // Each block may be followed by continuation blocks, the last of which ends with
+        // the offset of the first block.
         let mut header_buffer: Vec<u8> = Vec::new();
         header_buffer.resize(0xcusize, 0u8);
         let mut chunk_count = 0;
         let mut data_buffer = Vec::new();
-        let mut current_offset = file.seek(SeekFrom::End(-4)).ok()?;
+        let mut current_offset = file.seek(SeekFrom::End(-4))?;
         loop {
             let mut buf = [0u8; 4];
-            file.read_exact(&mut buf).ok()?;
+            file.read_exact(&mut buf)?;
             let offset = LittleEndian::read_u32(&buf);
             if offset >= current_offset as u32 || offset < 0x100 {
                 break;
             }
-            file.seek(SeekFrom::Start(offset as u64)).ok()?;
-            let mut buf = [0u8; 8];
-            file.read_exact(&mut buf).ok()?;
-            let magic = LittleEndian::read_u32(&buf);
-            let size = LittleEndian::read_u32(&buf[4..]);
-            let expected_size = (current_offset as u32).checked_sub(offset)?.checked_sub(4)?;
-            if magic != SAVE_MAGIC || size != expected_size {
+            file.seek(SeekFrom::Start(offset as u64))?;
+            let (buf, end) = match read_block_chain(file, Some(current_offset + 4), limits) {
+                Ok(o) => o,
+                Err(Error::BadSave) => break,
+                Err(e) => return Err(e),
+            };
+            if end != current_offset + 4 {
                 break;
             }
-            let mut buf = Vec::with_capacity(size as usize);
-            buf.resize(size as usize, 0u8);
-            file.read_exact(&mut buf).ok()?;
-            let ext = iter_extensions_from_data(buf).ok()?;
+            let ext = iter_extensions_from_data(buf, limits)?;
             let data_start = ext.buffer_pos;
-            let data_end = ext.buffer.len().checked_sub(4)?;
-            header_buffer.extend_from_slice(&ext.buffer[0xc..data_start]);
+            let data_end = ext.buffer.len().checked_sub(4).ok_or(Error::BadSave)?;
+            for chunk in &ext.chunks {
+                chunk.write_header(&mut header_buffer)?;
+            }
             data_buffer.extend_from_slice(&ext.buffer[data_start..data_end]);
             chunk_count += ext.chunks.len();
-            current_offset = file.seek(SeekFrom::Start(u64::from(offset - 4))).ok()?;
+            let total = (header_buffer.len() + data_buffer.len()) as u64;
+            if total > limits.max_total_size {
+                let what = "extension data".into();
+                return Err(Error::LimitExceeded(what, total, limits.max_total_size));
+            }
+            current_offset = file.seek(SeekFrom::Start(u64::from(offset - 4)))?;
         }
         header_buffer.extend_from_slice(&data_buffer);
+        LittleEndian::write_u32(&mut header_buffer, SAVE_VERSION);
         LittleEndian::write_u32(&mut header_buffer[4..], chunk_count as u32);
-        Some(header_buffer)
+        Ok(header_buffer)
     }
 }
 
@@ -0,0 +1,250 @@
+//! Boxed closures as `extern "C"` hook functions.
+//!
+//! `PluginApi` hooks don't take a context pointer, so a hook can't find any state
+//! other than statics. Each function here takes a closure, stores it in a free slot,
+//! and returns a `Trampoline` whose `func()` is an `extern "C"` function calling that
+//! closure, distinct from all other live trampolines.
+//!
+//! ```ignore
+//! let config = Arc::new(config);
+//! let hook = trampoline::unit_hook(move |unit, orig| unsafe {
+//!     if config.enabled {
+//!         orig(unit);
+//!     }
+//! })?;
+//! api.hook_step_order(hook.func())?;
+//! ```
+//!
+//! There are `SLOTS` trampolines for each signature. Panics in the closures are reported
+//! like in `panic_safe`.
+
+use std::ffi::c_void;
+use std::fmt;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::panic_safe::{
+    catch, CommandOrig, CreateBulletOrig, CreateUnitOrig, DialogOrig, LayoutDrawTextOrig,
+    PlaySoundOrig, UnitOrig,
+};
+use crate::DebugUiDraw;
+
+/// Amount of trampolines that can be alive at once for each signature.
+pub const SLOTS: usize = 256;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TrampolineError {
+    /// All `SLOTS` trampolines of the signature are in use.
+    Full(&'static str),
+}
+
+impl fmt::Display for TrampolineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrampolineError::Full(name) => {
+                write!(f, "All {} `{}` trampolines are in use", SLOTS, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrampolineError {}
+
+/// Handle to a trampoline function.
+///
+/// Dropping the handle keeps the trampoline and closure alive, as hooks usually can't be
+/// removed from the host; use `free` to release the slot.
+pub struct Trampoline<T: Copy> {
+    func: T,
+    index: usize,
+    free: unsafe fn(usize),
+}
+
+impl<T: Copy> Trampoline<T> {
+    pub fn func(&self) -> T {
+        self.func
+    }
+
+    /// Drops the closure and allows the slot to be reused.
+    ///
+    /// # Safety
+    ///
+    /// The function must not be called anymore, and must not be running.
+    pub unsafe fn free(self) {
+        (self.free)(self.index)
+    }
+}
+
+/// Expands to `[$f::<0>, $f::<1>, ... $f::<SLOTS - 1>]`.
+macro_rules! fn_table {
+    ($f:ident) => {
+        fn_table!(@rows $f [
+            0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+        ])
+    };
+    (@rows $f:ident [$($row:literal)*]) => {
+        [$(fn_table!(@row $f $row),)*]
+    };
+    (@row $f:ident $row:literal) => {
+        [
+            $f::<{ $row * 16 }>, $f::<{ $row * 16 + 1 }>,
+            $f::<{ $row * 16 + 2 }>, $f::<{ $row * 16 + 3 }>,
+            $f::<{ $row * 16 + 4 }>, $f::<{ $row * 16 + 5 }>,
+            $f::<{ $row * 16 + 6 }>, $f::<{ $row * 16 + 7 }>,
+            $f::<{ $row * 16 + 8 }>, $f::<{ $row * 16 + 9 }>,
+            $f::<{ $row * 16 + 10 }>, $f::<{ $row * 16 + 11 }>,
+            $f::<{ $row * 16 + 12 }>, $f::<{ $row * 16 + 13 }>,
+            $f::<{ $row * 16 + 14 }>, $f::<{ $row * 16 + 15 }>,
+        ]
+    };
+}
+
+macro_rules! trampolines {
+    ($(
+        $(#[$attr:meta])*
+        $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;
+    )*) => {
+        $(
+            $(#[$attr])*
+            pub fn $name<F>(func: F)
+                -> Result<Trampoline<unsafe extern "C" fn($($ty),*) $(-> $ret)?>, TrampolineError>
+            where F: Fn($($ty),*) $(-> $ret)? + Send + Sync + 'static
+            {
+                mod slots {
+                    use super::*;
+
+                    pub type Closure = Box<dyn Fn($($ty),*) $(-> $ret)? + Send + Sync>;
+                    pub type Func = unsafe extern "C" fn($($ty),*) $(-> $ret)?;
+
+                    pub static SLOTS: [AtomicPtr<Closure>; super::SLOTS] =
+                        [const { AtomicPtr::new(null_mut()) }; super::SLOTS];
+
+                    unsafe extern "C" fn shim<const I: usize>($($arg: $ty),*) $(-> $ret)? {
+                        catch(stringify!($name), || {
+                            let closure = SLOTS[I].load(Ordering::Acquire);
+                            if closure.is_null() {
+                                panic!("Freed trampoline was called");
+                            }
+                            (*closure)($($arg),*)
+                        })
+                    }
+
+                    static FUNCS: [[Func; 16]; super::SLOTS / 16] = fn_table!(shim);
+
+                    pub fn func(index: usize) -> Func {
+                        FUNCS[index / 16][index % 16]
+                    }
+
+                    pub unsafe fn free(index: usize) {
+                        let closure = SLOTS[index].swap(null_mut(), Ordering::AcqRel);
+                        if !closure.is_null() {
+                            drop(Box::from_raw(closure));
+                        }
+                    }
+                }
+
+                let closure: slots::Closure = Box::new(func);
+                let closure = Box::into_raw(Box::new(closure));
+                for (index, slot) in slots::SLOTS.iter().enumerate() {
+                    let result = slot.compare_exchange(
+                        null_mut(),
+                        closure,
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    );
+                    if result.is_ok() {
+                        return Ok(Trampoline {
+                            func: slots::func(index),
+                            index,
+                            free: slots::free,
+                        });
+                    }
+                }
+                unsafe {
+                    drop(Box::from_raw(closure));
+                }
+                Err(TrampolineError::Full(stringify!($name)))
+            }
+        )*
+    };
+}
+
+trampolines! {
+    /// `hook_step_objects`, `hook_on_first_file_access`, `hook_renderer`,
+    /// `hook_game_loop_start`, `extend_save` init
+    no_args();
+    /// `hook_aiscript_opcode`, `set_tooltip_draw_func`
+    ptr_arg(ptr: *mut c_void);
+    /// `hook_step_order`, `hook_step_order_hidden`, `hook_step_secondary_order`,
+    /// `hook_game_screen_rclick`, `hook_draw_image`, `hook_ai_focus_disabled`,
+    /// `hook_ai_focus_air`
+    unit_hook(unit: *mut c_void, orig: UnitOrig);
+    /// `hook_process_commands`, `hook_process_lobby_commands`
+    command_hook(data: *const c_void, len: u32, replayed: u32, orig: CommandOrig);
+    send_command_hook(
+        data: *mut c_void,
+        len: u32,
+        orig: unsafe extern "C" fn(*mut c_void, u32)
+    );
+    save_hook(add_data: unsafe extern "C" fn(*const u8, usize));
+    load_hook(data: *const u8, len: usize) -> u32;
+    ingame_command_hook(
+        data: *const u8,
+        len: u32,
+        player: u32,
+        unique_player: u32,
+        orig: unsafe extern "C" fn(*const u8, u32)
+    );
+    command_length(data: *const u8, max_len: u32) -> u32;
+    iscript_opcode_hook(
+        pos: *mut c_void,
+        iscript: *mut c_void,
+        image: *mut c_void,
+        dry_run: u32,
+        speed_out: *mut u32
+    );
+    file_read_hook(path: *const u8, size_out: *mut u32) -> *mut u8;
+    /// `hook_run_dialog`, `hook_spawn_dialog`
+    dialog_hook(dialog: *mut c_void, unk: usize, event_handler: *mut c_void, orig: DialogOrig)
+        -> u32;
+    create_bullet_hook(
+        bullet_id: u32,
+        x: i32,
+        y: i32,
+        player: u32,
+        direction: u32,
+        parent: *mut c_void,
+        orig: CreateBulletOrig
+    ) -> *mut c_void;
+    create_unit_hook(
+        unit_id: u32,
+        x: i32,
+        y: i32,
+        player: u32,
+        skin: *const u8,
+        orig: CreateUnitOrig
+    ) -> *mut c_void;
+    init_units_hook(orig: unsafe extern "C" fn());
+    layout_draw_text_hook(
+        a1: u32,
+        a2: u32,
+        text: *const u8,
+        a4: *mut u32,
+        a5: u32,
+        a6: *mut u32,
+        a7: u32,
+        a8: u32,
+        orig: LayoutDrawTextOrig
+    ) -> *const u8;
+    draw_graphic_layers_hook(layer: u32, orig: unsafe extern "C" fn(u32));
+    ai_step_region_hook(player: u32, region: u32, orig: unsafe extern "C" fn(u32, u32));
+    play_sound_hook(
+        sound: u32,
+        volume: f32,
+        unit: *mut c_void,
+        x: *mut i32,
+        y: *mut i32,
+        orig: PlaySoundOrig
+    ) -> u32;
+    debug_ui_draw(draw: *const DebugUiDraw, ctx: *mut c_void);
+}
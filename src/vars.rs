@@ -0,0 +1,399 @@
+//! Typed access to `VarId` variables.
+//!
+//! `Vars` reads and writes single variables. `VarSet` loads a fixed list of variables
+//! once, after which `refresh` reads all of them and `flush` writes the changed ones,
+//! each with a single host call.
+//!
+//! ```ignore
+//! let mut set = VarSet::load(&api, &[VarId::Game, VarId::RngSeed])?;
+//! set.refresh()?;
+//! let game = set.get(VarId::Game).and_then(|x| x.as_ptr());
+//! set.set(VarId::RngSeed, VarValue::U32(0))?;
+//! set.flush()?;
+//! ```
+
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::fmt;
+
+use crate::api::{ApiError, SamaseApi};
+use crate::VarId;
+
+/// `load_vars` result.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VarAccess {
+    /// Host doesn't know about the variable (Newer than the host)
+    NotFound,
+    /// Host knows the variable, but couldn't find it in this executable.
+    NotSupported,
+    ReadOnly,
+    ReadWrite,
+}
+
+impl VarAccess {
+    pub fn from_raw(value: u8) -> VarAccess {
+        match value {
+            1 => VarAccess::NotSupported,
+            2 => VarAccess::ReadOnly,
+            3 => VarAccess::ReadWrite,
+            _ => VarAccess::NotFound,
+        }
+    }
+
+    pub fn to_raw(self) -> u8 {
+        match self {
+            VarAccess::NotFound => 0,
+            VarAccess::NotSupported => 1,
+            VarAccess::ReadOnly => 2,
+            VarAccess::ReadWrite => 3,
+        }
+    }
+
+    pub fn can_read(self) -> bool {
+        matches!(self, VarAccess::ReadOnly | VarAccess::ReadWrite)
+    }
+
+    pub fn can_write(self) -> bool {
+        self == VarAccess::ReadWrite
+    }
+}
+
+impl fmt::Display for VarAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            VarAccess::NotFound => "not found",
+            VarAccess::NotSupported => "not supported",
+            VarAccess::ReadOnly => "read only",
+            VarAccess::ReadWrite => "read / write",
+        };
+        f.write_str(text)
+    }
+}
+
+/// What the `usize` that hosts use for a variable contains.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VarKind {
+    /// Pointer stored in the variable, e.g. head of a linked list.
+    Pointer,
+    /// Address of the structure / array itself, writing changes which object is used.
+    Address,
+    U8,
+    U32,
+    /// Only provided by SC:R hosts, and the format isn't known; the value is passed
+    /// through as is.
+    Raw,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VarValue {
+    Pointer(*mut c_void),
+    Address(*mut c_void),
+    U8(u8),
+    U32(u32),
+    Raw(usize),
+}
+
+impl VarValue {
+    pub fn from_raw(kind: VarKind, value: usize) -> VarValue {
+        match kind {
+            VarKind::Pointer => VarValue::Pointer(value as *mut c_void),
+            VarKind::Address => VarValue::Address(value as *mut c_void),
+            VarKind::U8 => VarValue::U8(value as u8),
+            VarKind::U32 => VarValue::U32(value as u32),
+            VarKind::Raw => VarValue::Raw(value),
+        }
+    }
+
+    pub fn to_raw(self) -> usize {
+        match self {
+            VarValue::Pointer(x) | VarValue::Address(x) => x as usize,
+            VarValue::U8(x) => x as usize,
+            VarValue::U32(x) => x as usize,
+            VarValue::Raw(x) => x,
+        }
+    }
+
+    pub fn kind(self) -> VarKind {
+        match self {
+            VarValue::Pointer(..) => VarKind::Pointer,
+            VarValue::Address(..) => VarKind::Address,
+            VarValue::U8(..) => VarKind::U8,
+            VarValue::U32(..) => VarKind::U32,
+            VarValue::Raw(..) => VarKind::Raw,
+        }
+    }
+
+    /// Returns the pointer for both `Pointer` and `Address`.
+    pub fn as_ptr(self) -> Option<*mut c_void> {
+        match self {
+            VarValue::Pointer(x) | VarValue::Address(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_u8(self) -> Option<u8> {
+        match self {
+            VarValue::U8(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(self) -> Option<u32> {
+        match self {
+            VarValue::U32(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl VarId {
+    /// Kinds follow how samase_shim (1.16.1) reads the variables: scalars are read with
+    /// their size, structures and arrays as their address, and everything else is a
+    /// pointer stored in the variable. `UnitsVector` is documented in `VarId`, variables
+    /// that only SC:R hosts provide are otherwise `Raw`.
+    pub fn kind(self) -> VarKind {
+        use crate::VarId::*;
+        match self {
+            RngSeed | RngEnable | ScMainState | CommandUser | UniqueCommandUser |
+                IsReplay | LocalPlayerId | LocalUniquePlayerId | ScreenX | ScreenY |
+                IsPaused | DatRequirementError | IsPlacingBuilding |
+                UnitShouldRevealArea => VarKind::U32,
+            IsMultiplayer | DrawCursorMarker | IsTargeting => VarKind::U8,
+            Game | AiRegions | PlayerAi | Units | UnitsVector | FirstGuardAi |
+                ActiveAiTowns | Selections | ClientSelection | Players | SpriteHlines |
+                SpriteHlinesEnd | GameData | ReplayData | ReplayHeader | FirstPlayerUnit |
+                ResourceAreas | FirstDialog | MainPalette | SelectionCircleImages |
+                HpBarImages | HpBarState | PlacementImages | PlacementRects |
+                AiScriptListHead => VarKind::Address,
+            Zoom | TooltipDrawFunc | GraphicLayers | CmdIconsDdsGrp | CmdBtnsDdsGrp |
+                StatusScreenMode | Allocator | DrawCommands | VertexBuffer | Renderer |
+                UseRgbColors | RgbColors | GameScreenWidthBwpx | GameScreenHeightBwpx |
+                StepGameFrames | StatportTalkingPortraitActive | ImagesVector |
+                SpritesVector => VarKind::Raw,
+            _ => VarKind::Pointer,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VarError {
+    Api(ApiError),
+    NotReadable(VarId),
+    NotWritable(VarId),
+    /// Variable wasn't passed to `VarSet::load`.
+    NotInSet(VarId),
+    /// Written value has different `VarKind` than the variable.
+    WrongKind(VarId),
+}
+
+impl From<ApiError> for VarError {
+    fn from(e: ApiError) -> VarError {
+        VarError::Api(e)
+    }
+}
+
+impl fmt::Display for VarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VarError::Api(ref e) => write!(f, "{}", e),
+            VarError::NotReadable(id) => write!(f, "Variable {:?} cannot be read", id),
+            VarError::NotWritable(id) => write!(f, "Variable {:?} cannot be written", id),
+            VarError::NotInSet(id) => write!(f, "Variable {:?} is not in the set", id),
+            VarError::WrongKind(id) => {
+                write!(f, "Value of wrong kind for variable {:?} ({:?})", id, id.kind())
+            }
+        }
+    }
+}
+
+/// Single variable access. Each call is a host call; use `VarSet` for variables that
+/// are accessed often.
+pub struct Vars<'a> {
+    api: &'a SamaseApi,
+}
+
+impl<'a> Vars<'a> {
+    pub fn new(api: &'a SamaseApi) -> Vars<'a> {
+        Vars {
+            api,
+        }
+    }
+
+    /// Returns `NotFound` for every variable if the host doesn't have `load_vars`.
+    ///
+    /// # Safety
+    ///
+    /// Same as `SamaseApi::load_vars`.
+    pub unsafe fn access(&self, id: VarId) -> VarAccess {
+        let mut out = [0u8];
+        match self.api.load_vars(&[id as u16], &mut out) {
+            Ok(()) => VarAccess::from_raw(out[0]),
+            Err(_) => VarAccess::NotFound,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Same as `SamaseApi::read_vars`.
+    pub unsafe fn read(&self, id: VarId) -> Result<VarValue, VarError> {
+        if !self.access(id).can_read() {
+            return Err(VarError::NotReadable(id));
+        }
+        let mut out = [0usize];
+        self.api.read_vars(&[id as u16], &mut out)?;
+        Ok(VarValue::from_raw(id.kind(), out[0]))
+    }
+
+    /// # Safety
+    ///
+    /// Same as `SamaseApi::write_vars`; pointers written must be valid for the game.
+    pub unsafe fn write(&self, id: VarId, value: VarValue) -> Result<(), VarError> {
+        if value.kind() != id.kind() {
+            return Err(VarError::WrongKind(id));
+        }
+        if !self.access(id).can_write() {
+            return Err(VarError::NotWritable(id));
+        }
+        self.api.write_vars(&[id as u16], &[value.to_raw()])?;
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// Same as `VarSet::load`.
+    pub unsafe fn set(&self, ids: &[VarId]) -> Result<VarSet<'a>, ApiError> {
+        VarSet::load(self.api, ids)
+    }
+}
+
+/// List of variables whose access is loaded once.
+///
+/// Values are cached; `refresh` reads every readable variable and `flush` writes
+/// every variable changed with `set`.
+pub struct VarSet<'a> {
+    api: &'a SamaseApi,
+    ids: Vec<VarId>,
+    access: Vec<VarAccess>,
+    /// Readable ids, passed to `read_vars`
+    read_ids: Vec<u16>,
+    /// Index in `ids` for each of `read_ids`
+    read_index: Vec<usize>,
+    read_buf: Vec<usize>,
+    values: Vec<usize>,
+    dirty: Vec<bool>,
+}
+
+impl<'a> VarSet<'a> {
+    /// # Safety
+    ///
+    /// Same as `SamaseApi::load_vars`.
+    pub unsafe fn load(api: &'a SamaseApi, ids: &[VarId]) -> Result<VarSet<'a>, ApiError> {
+        let raw_ids: Vec<u16> = ids.iter().map(|&x| x as u16).collect();
+        let mut raw_access = alloc::vec![0u8; ids.len()];
+        api.load_vars(&raw_ids, &mut raw_access)?;
+        let access: Vec<VarAccess> =
+            raw_access.iter().map(|&x| VarAccess::from_raw(x)).collect();
+        let read_index: Vec<usize> = access.iter()
+            .enumerate()
+            .filter(|x| x.1.can_read())
+            .map(|x| x.0)
+            .collect();
+        let read_ids = read_index.iter().map(|&i| raw_ids[i]).collect();
+        Ok(VarSet {
+            api,
+            ids: ids.into(),
+            access,
+            read_ids,
+            read_buf: alloc::vec![0; read_index.len()],
+            read_index,
+            values: alloc::vec![0; ids.len()],
+            dirty: alloc::vec![false; ids.len()],
+        })
+    }
+
+    pub fn ids(&self) -> &[VarId] {
+        &self.ids
+    }
+
+    fn index(&self, id: VarId) -> Option<usize> {
+        self.ids.iter().position(|&x| x == id)
+    }
+
+    /// Returns `NotFound` for variables that aren't in the set.
+    pub fn access(&self, id: VarId) -> VarAccess {
+        match self.index(id) {
+            Some(i) => self.access[i],
+            None => VarAccess::NotFound,
+        }
+    }
+
+    /// Reads all readable variables. Discards values changed with `set` that haven't
+    /// been flushed.
+    ///
+    /// # Safety
+    ///
+    /// Same as `SamaseApi::read_vars`.
+    pub unsafe fn refresh(&mut self) -> Result<(), ApiError> {
+        if self.read_ids.is_empty() {
+            return Ok(());
+        }
+        self.api.read_vars(&self.read_ids, &mut self.read_buf)?;
+        for (&index, &value) in self.read_index.iter().zip(self.read_buf.iter()) {
+            self.values[index] = value;
+            self.dirty[index] = false;
+        }
+        Ok(())
+    }
+
+    /// Returns the value from last `refresh` or `set`.
+    ///
+    /// `None` if the variable isn't in the set or cannot be read.
+    pub fn get(&self, id: VarId) -> Option<VarValue> {
+        let index = self.index(id)?;
+        if !self.access[index].can_read() && !self.dirty[index] {
+            return None;
+        }
+        Some(VarValue::from_raw(id.kind(), self.values[index]))
+    }
+
+    /// Changes cached value; it is written to the host on `flush`.
+    ///
+    /// In debug builds setting a variable that isn't writable is an error; release
+    /// builds leave it for the host to ignore.
+    pub fn set(&mut self, id: VarId, value: VarValue) -> Result<(), VarError> {
+        let index = self.index(id).ok_or(VarError::NotInSet(id))?;
+        if value.kind() != id.kind() {
+            return Err(VarError::WrongKind(id));
+        }
+        if cfg!(debug_assertions) && !self.access[index].can_write() {
+            return Err(VarError::NotWritable(id));
+        }
+        self.values[index] = value.to_raw();
+        self.dirty[index] = true;
+        Ok(())
+    }
+
+    /// Writes every variable changed since last `flush` / `refresh`.
+    ///
+    /// # Safety
+    ///
+    /// Same as `SamaseApi::write_vars`; pointers written must be valid for the game.
+    pub unsafe fn flush(&mut self) -> Result<(), ApiError> {
+        let mut ids = Vec::new();
+        let mut values = Vec::new();
+        for (i, &dirty) in self.dirty.iter().enumerate() {
+            if dirty {
+                ids.push(self.ids[i] as u16);
+                values.push(self.values[i]);
+            }
+        }
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.api.write_vars(&ids, &values)?;
+        for dirty in self.dirty.iter_mut() {
+            *dirty = false;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+//! Compares `abi::describe` against the golden file.
+//!
+//! The only accepted changes are new fields / enum variants at the end, and those require
+//! `VERSION` to be bumped. New fields also have to be marked `#[since(VERSION)]`. Run with
+//! `UPDATE_ABI_GOLDEN=1` to write an accepted change to the golden file.
+
+extern crate samase_plugin;
+
+use std::mem::size_of;
+use std::path::PathBuf;
+
+use samase_plugin::abi::{self, ApiField, API_FIELDS};
+use samase_plugin::{PluginApi, VERSION};
+
+fn golden_path() -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push(format!("tests/abi_golden_{}bit.txt", usize::BITS));
+    path
+}
+
+struct Description<'a> {
+    version: u16,
+    /// Lines other than `version`, grouped by their first word.
+    groups: Vec<(&'a str, Vec<&'a str>)>,
+}
+
+fn parse(text: &str) -> Description<'_> {
+    let mut version = None;
+    let mut groups: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in text.lines().filter(|x| !x.is_empty()) {
+        let kind = line.split(' ').next().unwrap();
+        if kind == "version" {
+            version = Some(line[8..].parse().unwrap());
+            continue;
+        }
+        match groups.iter_mut().find(|x| x.0 == kind) {
+            Some(group) => group.1.push(line),
+            None => groups.push((kind, vec![line])),
+        }
+    }
+    Description {
+        version: version.expect("No version line"),
+        groups,
+    }
+}
+
+/// Returns Err describing the first change that isn't an append.
+fn check_append_only(old: &Description, new: &Description) -> Result<bool, String> {
+    let mut appended = false;
+    for &(kind, ref old_lines) in &old.groups {
+        let new_lines = match new.groups.iter().find(|x| x.0 == kind) {
+            Some(s) => &s.1,
+            None => return Err(format!("All `{}` lines were removed", kind)),
+        };
+        if kind == "size" {
+            // Grows with appended fields, checked through field offsets.
+            continue;
+        }
+        for (i, old_line) in old_lines.iter().enumerate() {
+            match new_lines.get(i) {
+                Some(new_line) if new_line == old_line => (),
+                Some(new_line) => {
+                    return Err(format!("Changed `{}` to `{}`", old_line, new_line));
+                }
+                None => return Err(format!("Removed `{}`", old_line)),
+            }
+        }
+        if new_lines.len() > old_lines.len() {
+            appended = true;
+        }
+    }
+    for &(kind, _) in &new.groups {
+        if !old.groups.iter().any(|x| x.0 == kind) {
+            appended = true;
+        }
+    }
+    Ok(appended)
+}
+
+/// Returns Err if a field that isn't in `old` doesn't have `since <new.version>`.
+fn check_appended_fields(old: &Description, new: &Description) -> Result<(), String> {
+    let old_count = old.groups.iter().find(|x| x.0 == "field").map(|x| x.1.len()).unwrap_or(0);
+    let new_fields = match new.groups.iter().find(|x| x.0 == "field") {
+        Some(s) => &s.1[old_count.min(s.1.len())..],
+        None => return Ok(()),
+    };
+    let expected = format!(" since {}", new.version);
+    match new_fields.iter().find(|x| !x.ends_with(&expected)) {
+        Some(line) => Err(format!("`{}` was appended without `{}`", line, expected.trim())),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn abi_matches_golden() {
+    let current = abi::describe();
+    let path = golden_path();
+    let update = std::env::var_os("UPDATE_ABI_GOLDEN").is_some();
+    let golden = match std::fs::read_to_string(&path) {
+        Ok(o) => o,
+        Err(e) => {
+            if update {
+                std::fs::write(&path, &current).unwrap();
+                return;
+            }
+            panic!("Couldn't read {}: {}", path.display(), e);
+        }
+    };
+    if golden == current {
+        return;
+    }
+    let old = parse(&golden);
+    let new = parse(&current);
+    let appended = match check_append_only(&old, &new) {
+        Ok(o) => o,
+        Err(e) => panic!("Incompatible ABI change: {}", e),
+    };
+    if appended && new.version <= old.version {
+        panic!("ABI was extended without bumping VERSION (still {})", new.version);
+    }
+    if let Err(e) = check_appended_fields(&old, &new) {
+        panic!("{}", e);
+    }
+    assert!(new.version >= old.version, "VERSION was decreased");
+    if update {
+        std::fs::write(&path, &current).unwrap();
+    } else {
+        panic!(
+            "ABI changed compatibly; rerun with UPDATE_ABI_GOLDEN=1 to update {}",
+            path.display(),
+        );
+    }
+}
+
+#[test]
+fn fields_are_appended() {
+    let mut prev_end = 0;
+    for field in API_FIELDS {
+        assert!(field.offset >= prev_end, "{} overlaps previous field", field.name);
+        prev_end = field.offset + field.size;
+    }
+    assert_eq!(prev_end, size_of::<PluginApi>());
+}
+
+#[test]
+fn field_versions() {
+    let mut prev = 0;
+    for field in API_FIELDS {
+        assert!(field.since >= prev, "{} is older than the field before it", field.name);
+        assert!(field.since <= VERSION, "{} is newer than VERSION", field.name);
+        prev = field.since;
+    }
+}
+
+#[test]
+fn type_descriptions() {
+    let expected = "unsafe extern \"C\" fn(*const u8, usize) -> Option<fn()>";
+    assert_eq!(abi::type_description(expected), expected);
+    let spaced = "unsafe extern \"C\" fn ( * const u8 , usize ) -> Option < fn ( ) >";
+    assert_eq!(abi::type_description(spaced), expected);
+}
+
+#[test]
+fn append_check() {
+    let old = parse("version 1\nfield 0 8 a x\nfunc 0 A\n");
+    let appended = parse("version 2\nfield 0 8 a x\nfield 8 8 b x\nfunc 0 A\nfunc 1 B\n");
+    let inserted = parse("version 2\nfield 0 8 a x\nfunc 0 B\nfunc 1 A\n");
+    let retyped = parse("version 2\nfield 0 8 a y\nfunc 0 A\n");
+    assert_eq!(check_append_only(&old, &old), Ok(false));
+    assert_eq!(check_append_only(&old, &appended), Ok(true));
+    assert!(check_append_only(&old, &inserted).is_err());
+    assert!(check_append_only(&old, &retyped).is_err());
+
+    let versioned = parse("version 2\nfield 0 8 a x\nfield 8 8 b x since 2\nfunc 0 A\n");
+    let stale = parse("version 3\nfield 0 8 a x\nfield 8 8 b x since 2\nfunc 0 A\n");
+    assert_eq!(check_appended_fields(&old, &versioned), Ok(()));
+    assert_eq!(check_appended_fields(&old, &old), Ok(()));
+    assert!(check_appended_fields(&old, &appended).is_err());
+    assert!(check_appended_fields(&old, &stale).is_err());
+}
+
+#[test]
+fn field_exists_in() {
+    let field = ApiField {
+        name: "new_field",
+        offset: 0x100,
+        size: 8,
+        ty: "usize",
+        since: VERSION,
+    };
+    assert!(field.exists_in(VERSION));
+    assert!(field.exists_in(VERSION + 1));
+    assert!(!field.exists_in(VERSION - 1));
+    let old = ApiField { since: 0, ..field };
+    assert!(old.exists_in(0));
+}
@@ -0,0 +1,145 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+use samase_plugin::commands::{self, CommandChain, IngameHookOptions};
+
+static SENT: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+static LOBBY: Mutex<Vec<(Vec<u8>, u32)>> = Mutex::new(Vec::new());
+static PLAYERS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Types of `PluginApi::hook_send_command` / `hook_process_lobby_commands` hooks.
+type SendCommandHook =
+    unsafe extern "C" fn(*mut c_void, u32, unsafe extern "C" fn(*mut c_void, u32));
+type LobbyCommandHook =
+    unsafe extern "C" fn(*const c_void, u32, u32, unsafe extern "C" fn(*const c_void, u32, u32));
+
+/// Replaces the second byte with 0x99.
+unsafe extern "C" fn rewrite(
+    data: *const u8,
+    len: u32,
+    player: u32,
+    _: u32,
+    orig: unsafe extern "C" fn(*const u8, u32),
+) {
+    PLAYERS.lock().unwrap().push(player);
+    let mut cmd = std::slice::from_raw_parts(data, len as usize).to_vec();
+    cmd[1] = 0x99;
+    orig(cmd.as_ptr(), cmd.len() as u32);
+}
+
+/// Doesn't let commands with a nonzero second byte through.
+unsafe extern "C" fn veto(
+    data: *const u8,
+    len: u32,
+    _: u32,
+    _: u32,
+    orig: unsafe extern "C" fn(*const u8, u32),
+) {
+    if *data.add(1) == 0 {
+        orig(data, len);
+    }
+}
+
+unsafe extern "C" fn send(data: *mut c_void, len: u32) {
+    let data = std::slice::from_raw_parts(data as *const u8, len as usize);
+    SENT.lock().unwrap().push(data.to_vec());
+}
+
+unsafe extern "C" fn lobby_orig(data: *const c_void, len: u32, replayed: u32) {
+    let data = std::slice::from_raw_parts(data as *const u8, len as usize);
+    LOBBY.lock().unwrap().push((data.to_vec(), replayed));
+}
+
+fn send_command(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut data = data.to_vec();
+    unsafe {
+        commands::send_hook(data.as_mut_ptr() as *mut c_void, data.len() as u32, 2, 7, send);
+    }
+    std::mem::take(&mut *SENT.lock().unwrap())
+}
+
+fn lobby_command(data: &[u8]) -> Vec<(Vec<u8>, u32)> {
+    unsafe {
+        commands::lobby_hook(data.as_ptr() as *const c_void, data.len() as u32, 1, 4, 8, lobby_orig);
+    }
+    std::mem::take(&mut *LOBBY.lock().unwrap())
+}
+
+#[test]
+fn send_and_lobby_chains() {
+    let mut lengths = vec![0u32; 0x100];
+    lengths[0x20] = 2;
+    lengths[0x21] = 2;
+    lengths[0x40] = 3;
+    commands::set_default_command_lengths(&lengths);
+
+    // Without hooks the buffers are passed on unsplit
+    assert_eq!(send_command(&[0x21, 0x05, 0x21, 0x06]), vec![vec![0x21, 0x05, 0x21, 0x06]]);
+    assert_eq!(lobby_command(&[0x21, 0x05, 0x50, 0x01]), vec![(vec![0x21, 0x05, 0x50, 0x01], 1)]);
+
+    commands::add_send_hook(0x20, veto);
+    let options = IngameHookOptions {
+        priority: 1,
+        ..Default::default()
+    };
+    commands::add_command_hook_with(CommandChain::Send, 0x20, rewrite, &options).unwrap();
+    // Rewritten before the veto sees it
+    assert_eq!(send_command(&[0x20, 0x00]), Vec::<Vec<u8>>::new());
+    assert_eq!(*PLAYERS.lock().unwrap(), vec![2]);
+    PLAYERS.lock().unwrap().clear();
+    // Other ids aren't affected
+    assert_eq!(send_command(&[0x21, 0x05]), vec![vec![0x21, 0x05]]);
+    assert_eq!(send_command(&[]), vec![vec![]]);
+    // Each command of the buffer goes through the chain separately
+    assert_eq!(
+        send_command(&[0x21, 0x05, 0x20, 0x00, 0x21, 0x06]),
+        vec![vec![0x21, 0x05], vec![0x21, 0x06]],
+    );
+    assert_eq!(send_command(&[0x21, 0x05, 0x50, 0x01]), vec![vec![0x21, 0x05], vec![0x50, 0x01]]);
+
+    let lobby = commands::add_lobby_hook(0x40, rewrite);
+    assert_eq!(
+        lobby_command(&[0x40, 0x01, 0x02, 0x21, 0x03]),
+        vec![(vec![0x40, 0x99, 0x02], 1), (vec![0x21, 0x03], 1)],
+    );
+    // Send hooks aren't called for lobby commands
+    assert_eq!(lobby_command(&[0x20, 0x01]), vec![(vec![0x20, 0x01], 1)]);
+    // Invalid length passes the rest as is
+    assert_eq!(lobby_command(&[0x21, 0x00, 0x50, 0x01]), vec![
+        (vec![0x21, 0x00], 1),
+        (vec![0x50, 0x01], 1),
+    ]);
+    assert_eq!(*PLAYERS.lock().unwrap(), vec![2, 4]);
+
+    PLAYERS.lock().unwrap().clear();
+
+    // Through the adapters that the host registers with hook_send_command and
+    // hook_process_lobby_commands.
+    let send_adapter: SendCommandHook = commands::send_command_hook;
+    let lobby_adapter: LobbyCommandHook = commands::lobby_commands_hook;
+    commands::set_send_player(|| (5, 11));
+    commands::set_lobby_player(|| (6, 12));
+    let mut data = vec![0x21u8, 0x01, 0x20, 0x00];
+    unsafe {
+        send_adapter(data.as_mut_ptr() as *mut c_void, data.len() as u32, send);
+    }
+    assert_eq!(std::mem::take(&mut *SENT.lock().unwrap()), vec![vec![0x21, 0x01]]);
+    let data = [0x21u8, 0x01, 0x40, 0x02, 0x03];
+    unsafe {
+        lobby_adapter(data.as_ptr() as *const c_void, data.len() as u32, 0, lobby_orig);
+    }
+    assert_eq!(
+        std::mem::take(&mut *LOBBY.lock().unwrap()),
+        vec![(vec![0x21, 0x01], 0), (vec![0x40, 0x99, 0x03], 0)],
+    );
+    assert_eq!(*PLAYERS.lock().unwrap(), vec![5, 6]);
+
+    assert!(lobby.remove());
+    assert_eq!(lobby_command(&[0x40, 0x01, 0x02]), vec![(vec![0x40, 0x01, 0x02], 1)]);
+    assert_eq!(
+        lobby_command(&[0x40, 0x01, 0x02, 0x21, 0x03]),
+        vec![(vec![0x40, 0x01, 0x02, 0x21, 0x03], 1)],
+    );
+}
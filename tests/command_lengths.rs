@@ -0,0 +1,76 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+use log::{Log, Metadata, Record};
+
+use samase_plugin::commands;
+
+static EXECUTED: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() == log::Level::Warn {
+            WARNINGS.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {
+    }
+}
+
+unsafe extern "C" fn add_to_replay_data(_: *const u8, _: usize) {
+}
+
+unsafe extern "C" fn orig(data: *const c_void, len: u32, _: u32) {
+    let cmd = std::slice::from_raw_parts(data as *const u8, len as usize);
+    EXECUTED.lock().unwrap().push(cmd.to_vec());
+}
+
+unsafe extern "C" fn length_3(_: *const u8, _: u32) -> u32 {
+    3
+}
+
+fn run(data: &[u8]) -> Vec<Vec<u8>> {
+    let globals = commands::IngameHookGlobals {
+        is_replay: 0,
+        unique_command_user: 0,
+        command_user: 0,
+        add_to_replay_data,
+    };
+    unsafe {
+        let ptr = data.as_ptr() as *const c_void;
+        commands::ingame_hook(ptr, data.len() as u32, 0, &globals, orig);
+    }
+    std::mem::take(&mut *EXECUTED.lock().unwrap())
+}
+
+#[test]
+fn late_length_changes() {
+    log::set_logger(&Logger).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+    commands::set_default_command_lengths(&[1; 0x100]);
+    assert_eq!(run(&[0x70, 0x71]), [[0x70], [0x71]]);
+    // Not processed yet, no warning
+    commands::add_length_override(0x72, length_3);
+    assert!(WARNINGS.lock().unwrap().is_empty());
+    // Already processed as 1-byte command
+    commands::add_length_override(0x70, length_3);
+    assert_eq!(WARNINGS.lock().unwrap().len(), 1);
+    // Setting the same function again isn't a change
+    commands::add_length_override(0x70, length_3);
+    assert_eq!(WARNINGS.lock().unwrap().len(), 1);
+    assert_eq!(run(&[0x70, 0x01, 0x02, 0x72, 0x03, 0x04]), [[0x70, 1, 2], [0x72, 3, 4]]);
+    // Same defaults again keep the overrides of ids that aren't in the defaults
+    commands::set_default_command_lengths(&[1; 0x10]);
+    assert_eq!(WARNINGS.lock().unwrap().len(), 1);
+    assert_eq!(run(&[0x70, 0x01, 0x02]), [[0x70, 1, 2]]);
+}
@@ -0,0 +1,132 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use samase_plugin::commands;
+use samase_plugin::commands::recorder::{self, Batch, Output, Recording};
+
+static FRAME: AtomicU32 = AtomicU32::new(0);
+
+fn frame() -> u32 {
+    FRAME.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Increments the second byte
+unsafe extern "C" fn increment(
+    data: *const u8,
+    len: u32,
+    _: u32,
+    _: u32,
+    orig: unsafe extern "C" fn(*const u8, u32),
+) {
+    let mut cmd = std::slice::from_raw_parts(data, len as usize).to_vec();
+    cmd[1] = cmd[1].wrapping_add(1);
+    orig(cmd.as_ptr(), len);
+}
+
+/// Player 1 can't use the command
+unsafe extern "C" fn filter(
+    data: *const u8,
+    len: u32,
+    player: u32,
+    _: u32,
+    orig: unsafe extern "C" fn(*const u8, u32),
+) {
+    if player != 1 {
+        orig(data, len);
+    }
+}
+
+unsafe extern "C" fn add_to_replay_data(_: *const u8, _: usize) {
+}
+
+unsafe extern "C" fn orig(_: *const c_void, _: u32, _: u32) {
+}
+
+fn run(data: &[u8], player: u32) {
+    let globals = commands::IngameHookGlobals {
+        is_replay: 0,
+        unique_command_user: player + 8,
+        command_user: player,
+        add_to_replay_data,
+    };
+    unsafe {
+        commands::ingame_hook(data.as_ptr() as *const c_void, data.len() as u32, 0, &globals, orig);
+    }
+}
+
+#[test]
+fn record_and_play() {
+    let mut lengths = vec![1u32; 0x100];
+    lengths[0x70] = 2;
+    commands::set_default_command_lengths(&lengths);
+    commands::add_ingame_hook(0x70, increment);
+    commands::add_ingame_hook(0x70, filter);
+
+    let buffer = Buffer::default();
+    FRAME.store(40, Ordering::Relaxed);
+    recorder::start_recording(buffer.clone(), frame).unwrap();
+    assert!(recorder::is_recording());
+    run(&[0x70, 0x05, 0x1a], 0);
+    FRAME.store(42, Ordering::Relaxed);
+    run(&[0x70, 0x07], 1);
+    recorder::stop_recording().unwrap();
+    assert!(!recorder::is_recording());
+    // Not recorded anymore
+    run(&[0x1a], 0);
+
+    let data = buffer.0.lock().unwrap().clone();
+    let recording = Recording::read(&mut &data[..]).unwrap();
+    let expected = Recording {
+        batches: vec![
+            Batch {
+                frame: 40,
+                is_replay: false,
+                replayed: false,
+                command_user: 0,
+                unique_command_user: 8,
+                data: vec![0x70, 0x05, 0x1a],
+                outputs: vec![
+                    Output::Forwarded { hook: 0, data: vec![0x70, 0x06] },
+                    Output::Forwarded { hook: 1, data: vec![0x70, 0x06] },
+                    Output::ToGame(vec![0x70, 0x06]),
+                    Output::ToGame(vec![0x1a]),
+                ],
+            },
+            Batch {
+                frame: 42,
+                is_replay: false,
+                replayed: false,
+                command_user: 1,
+                unique_command_user: 9,
+                data: vec![0x70, 0x07],
+                outputs: vec![Output::Forwarded { hook: 0, data: vec![0x70, 0x08] }],
+            },
+        ],
+    };
+    assert_eq!(recording, expected);
+
+    let mut written = Vec::new();
+    recording.write(&mut written).unwrap();
+    assert_eq!(written, data);
+    assert_eq!(recorder::play(&recording), recording);
+    assert!(!recorder::is_recording());
+    assert!(Recording::read(&mut &data[..data.len() - 1]).is_err());
+}
@@ -0,0 +1,89 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+use samase_plugin::commands::{self, validate};
+use samase_plugin::commands::validate::{
+    MalformedAction, MalformedCommand, MalformedPolicy, MalformedReason,
+};
+
+static EXECUTED: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+static CALLBACK: Mutex<Vec<(u32, Vec<u8>, MalformedReason)>> = Mutex::new(Vec::new());
+
+unsafe extern "C" fn add_to_replay_data(_: *const u8, _: usize) {
+}
+
+unsafe extern "C" fn orig(data: *const c_void, len: u32, _: u32) {
+    let cmd = std::slice::from_raw_parts(data as *const u8, len as usize);
+    EXECUTED.lock().unwrap().push(cmd.to_vec());
+}
+
+unsafe extern "C" fn zero_length(_: *const u8, _: u32) -> u32 {
+    0
+}
+
+fn nonzero(cmd: &[u8]) -> Result<(), &'static str> {
+    match cmd[1] {
+        0 => Err("zero"),
+        _ => Ok(()),
+    }
+}
+
+fn callback(cmd: &MalformedCommand) -> MalformedAction {
+    CALLBACK.lock().unwrap().push((cmd.player, cmd.data.to_vec(), cmd.reason));
+    MalformedAction::DropCommand
+}
+
+fn run(data: &[u8], player: u32) -> Vec<Vec<u8>> {
+    let globals = commands::IngameHookGlobals {
+        is_replay: 0,
+        unique_command_user: player,
+        command_user: player,
+        add_to_replay_data,
+    };
+    unsafe {
+        let ptr = data.as_ptr() as *const c_void;
+        commands::ingame_hook(ptr, data.len() as u32, 0, &globals, orig);
+    }
+    std::mem::take(&mut *EXECUTED.lock().unwrap())
+}
+
+#[test]
+fn malformed_commands() {
+    let mut lengths = vec![1u32; 0x100];
+    lengths[0x70] = 3;
+    lengths[0x13] = 3;
+    commands::set_default_command_lengths(&lengths);
+    commands::add_length_override(0x71, zero_length);
+    validate::add_validator(0x70, nonzero);
+    validate::add_validator(0x13, validate::decodable);
+
+    let data = [0x70, 0x00, 0x00, 0x1a, 0x70, 0x01, 0x01];
+    assert_eq!(run(&data, 2), [&[0x1a][..], &[0x70, 1, 1]]);
+    let stats = validate::stats();
+    assert_eq!(stats.malformed, 1);
+    assert_eq!(stats.dropped_bytes, 3);
+    assert_eq!(stats.per_player[2], 1);
+
+    validate::set_malformed_policy(MalformedPolicy::DropRest);
+    assert_eq!(run(&data, 3), [] as [&[u8]; 0]);
+    assert_eq!(run(&[0x1a, 0x13, 0x03, 0x00, 0x1a], 3), [[0x1a]]);
+    let stats = validate::stats();
+    assert_eq!(stats.malformed, 3);
+    assert_eq!(stats.dropped_bytes, 3 + 7 + 4);
+    assert_eq!(stats.per_player[3], 2);
+
+    // Zero length can't be skipped, regardless of policy
+    validate::set_malformed_policy(MalformedPolicy::Callback(callback));
+    assert_eq!(run(&[0x1a, 0x70, 0x00, 0x00, 0x1b, 0x71, 0x1a], 20), [[0x1a], [0x1b]]);
+    assert_eq!(*CALLBACK.lock().unwrap(), [
+        (20, vec![0x70, 0x00, 0x00], MalformedReason::Invalid("zero")),
+        (20, vec![0x71, 0x1a], MalformedReason::Length(0)),
+    ]);
+    let stats = validate::stats();
+    assert_eq!(stats.malformed, 5);
+    assert_eq!(stats.dropped_bytes, 3 + 7 + 4 + 3 + 2);
+    // Player 20 has no counter
+    assert_eq!(stats.per_player.iter().sum::<u64>(), 3);
+}
@@ -1,7 +1,11 @@
 extern crate samase_plugin;
 
 use std::ffi::c_void;
-use samase_plugin::commands;
+use samase_plugin::commands::{self, CommandLengths};
+use samase_plugin::commands::decode::{
+    self, Command, DecodeError, HotkeyAction, SelectMode, UnitRef,
+};
+use samase_plugin::commands::encode::{self, CommandBuffer, EncodeError};
 
 #[test]
 fn cmds() {
@@ -74,3 +78,304 @@ unsafe extern "C" fn hook3(
     let data = vec![1, *data.offset(1) - 3];
     orig(data.as_ptr(), 2);
 }
+
+#[test]
+fn decode_commands() {
+    let mut lengths = known_length_table();
+    lengths[0x40] = 4;
+    let lengths = CommandLengths::with_defaults(&lengths);
+    let data = [
+        0x09, 0x02, 0x01, 0x00, 0x02, 0x08,
+        0x1f, 0x07, 0x00,
+        0x14, 0x10, 0x00, 0x20, 0x00, 0x05, 0x00, 0xe4, 0x00, 0x01,
+        0x61, 0x10, 0x00, 0x20, 0x00, 0x05, 0x00, 0x01, 0x00, 0xe4, 0x00, 0x0a, 0x00,
+        0x13, 0x01, 0x04,
+        0x06, 0x00, 0x00, 0x00, 0x00, b'a', b'b', 0x00,
+        0x40, 0x01, 0x02, 0x03,
+        0x0c, 0x1e, 0x05, 0x00, 0x06, 0x00, 0x6a, 0x00,
+    ];
+    let commands = decode::iter_with(&lengths, &data)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(commands.len(), 8);
+    match commands[0] {
+        Command::Select { mode, units } => {
+            assert_eq!(mode, SelectMode::Select);
+            assert!(!units.is_extended());
+            let units = units.iter().collect::<Vec<_>>();
+            assert_eq!(units, vec![UnitRef::Legacy(1), UnitRef::Legacy(0x802)]);
+        }
+        ref x => panic!("Expected select, got {:?}", x),
+    }
+    assert_eq!(commands[1], Command::Train { unit_id: 7 });
+    assert_eq!(commands[2], Command::RightClick {
+        x: 0x10,
+        y: 0x20,
+        target: UnitRef::Legacy(5),
+        fow_unit_id: 0xe4,
+        queued: true,
+    });
+    assert_eq!(commands[3], Command::TargetedOrder {
+        x: 0x10,
+        y: 0x20,
+        target: UnitRef::Extended(0x10005),
+        fow_unit_id: 0xe4,
+        order: 0xa,
+        queued: false,
+    });
+    assert_eq!(commands[4], Command::Hotkey { action: HotkeyAction::Select, group: 4 });
+    assert_eq!(commands[5], Command::SaveGame { unk: 0, filename: b"ab" });
+    assert_eq!(commands[6], Command::Other { id: 0x40, data: &[0x40, 1, 2, 3] });
+    assert_eq!(commands[7], Command::Build { order: 0x1e, x: 5, y: 6, unit_id: 0x6a });
+    for (command, &id) in commands.iter().zip(&[0x09, 0x1f, 0x14, 0x61, 0x13, 0x06, 0x40, 0x0c]) {
+        assert_eq!(command.id(), id);
+    }
+}
+
+#[test]
+fn decode_errors() {
+    let lengths = known_lengths();
+    let decode = |data: &'static [u8]| decode::decode_with(&lengths, data).map(|x| x.0);
+    assert_eq!(decode(&[]), Err(DecodeError::Empty));
+    assert_eq!(
+        decode(&[0x09, 0x03, 0x01, 0x00, 0x02, 0x00]),
+        Err(DecodeError::Truncated { id: 0x09, needed: 8, have: 6 }),
+    );
+    assert_eq!(
+        decode(&[0x63]),
+        Err(DecodeError::Truncated { id: 0x63, needed: 2, have: 1 }),
+    );
+    assert_eq!(
+        decode(&[0x09, 0x00]),
+        Err(DecodeError::InvalidField { id: 0x09, field: "unit count", value: 0 }),
+    );
+    assert_eq!(
+        decode(&[0x07, 0x00, 0x00, 0x00, 0x00, b'a']),
+        Err(DecodeError::Unterminated { id: 0x07 }),
+    );
+    assert_eq!(
+        decode(&[0x13, 0x03, 0x01]),
+        Err(DecodeError::InvalidField { id: 0x13, field: "hotkey action", value: 3 }),
+    );
+    assert_eq!(
+        decode(&[0x15, 0x00, 0x00]),
+        Err(DecodeError::Truncated { id: 0x15, needed: 11, have: 3 }),
+    );
+    assert_eq!(decode(&[0x40, 0x00]), Err(DecodeError::UnknownLength { id: 0x40 }));
+    // Length table disagreeing with the layout
+    let mut wrong = known_length_table();
+    wrong[0x1f] = 5;
+    wrong[0x0c] = 5;
+    wrong[0x1a] = 0;
+    let wrong = CommandLengths::with_defaults(&wrong);
+    assert_eq!(
+        decode::decode_with(&wrong, &[0x1f, 0x07, 0x00, 0x00, 0x00]),
+        Err(DecodeError::LengthMismatch { id: 0x1f, layout: 3, table: 5 }),
+    );
+    assert_eq!(
+        decode::decode_with(&wrong, &[0x0c, 0x1e, 0x05, 0x00, 0x06]),
+        Err(DecodeError::LengthMismatch { id: 0x0c, layout: 8, table: 5 }),
+    );
+    assert_eq!(
+        decode::decode_with(&wrong, &[0x1a, 0x00]),
+        Err(DecodeError::UnknownLength { id: 0x1a }),
+    );
+    // The iterator stops at the first error
+    let data = [0x1a, 0x01, 0x1f, 0x00];
+    let results = decode::iter_with(&lengths, &data).collect::<Vec<_>>();
+    assert_eq!(results, vec![
+        Ok(Command::Stop { queued: true }),
+        Err(DecodeError::Truncated { id: 0x1f, needed: 3, have: 2 }),
+    ]);
+}
+
+/// Lengths of every command that has a known layout, other entries unset.
+fn known_length_table() -> Vec<u32> {
+    let zeroes = [0u8; 0x100];
+    let mut lengths = vec![u32::MAX; 0x100];
+    for (id, out) in lengths.iter_mut().enumerate() {
+        let mut cmd = zeroes;
+        cmd[0] = id as u8;
+        if let Ok(Some(len)) = decode::known_command_len(&cmd) {
+            *out = len as u32;
+        }
+    }
+    lengths
+}
+
+fn known_lengths() -> CommandLengths {
+    CommandLengths::with_defaults(&known_length_table())
+}
+
+#[test]
+fn encode_round_trip() {
+    let lengths = known_lengths();
+    let commands = [
+        Command::KeepAlive,
+        Command::SaveGame { unk: 0x1234, filename: b"save.rep" },
+        Command::LoadGame { unk: 0, filename: b"x" },
+        Command::Build { order: 0x1e, x: 5, y: 6, unit_id: 0x6a },
+        Command::Vision { players: 0x3 },
+        Command::Alliance { players: 0x12345 },
+        Command::Hotkey { action: HotkeyAction::Add, group: 9 },
+        Command::RightClick {
+            x: 100,
+            y: 200,
+            target: UnitRef::Legacy(0x801),
+            fow_unit_id: 0xe4,
+            queued: true,
+        },
+        Command::RightClick {
+            x: 100,
+            y: 200,
+            target: UnitRef::Extended(0x20001),
+            fow_unit_id: 0xe4,
+            queued: false,
+        },
+        Command::TargetedOrder {
+            x: 1,
+            y: 2,
+            target: UnitRef::Legacy(0),
+            fow_unit_id: 0xe4,
+            order: 0xe,
+            queued: false,
+        },
+        Command::TargetedOrder {
+            x: 1,
+            y: 2,
+            target: UnitRef::Extended(7),
+            fow_unit_id: 0xe4,
+            order: 0xe,
+            queued: true,
+        },
+        Command::Stop { queued: true },
+        Command::Train { unit_id: 0x25 },
+        Command::CancelTrain { slot: 0xfe },
+        Command::Unload { target: UnitRef::Legacy(3) },
+        Command::Unload { target: UnitRef::Extended(3) },
+        Command::LiftOff { x: 30, y: 40 },
+        Command::Research { tech: 0x13 },
+        Command::Upgrade { upgrade: 0x2 },
+        Command::BuildingMorph { unit_id: 0x84 },
+        Command::LeaveGame { reason: 1 },
+        Command::MinimapPing { x: 300, y: 400 },
+        Command::Chat { player: 2, message: b"gg" },
+        Command::MergeDarkArchon,
+    ];
+    let mut buf = CommandBuffer::with_lengths(&lengths);
+    for command in &commands {
+        buf.command(command).unwrap();
+        let single = encode::encode_with(&lengths, command).unwrap();
+        assert_eq!(decode::decode_with(&lengths, &single), Ok((*command, single.len())));
+    }
+    buf.select(SelectMode::ShiftSelect, &[UnitRef::Legacy(1), UnitRef::Legacy(2)]).unwrap();
+    buf.select(SelectMode::ShiftDeselect, &[UnitRef::Extended(0x10001)]).unwrap();
+    let decoded = decode::iter_with(&lengths, buf.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(&decoded[..commands.len()], &commands[..]);
+    match decoded[commands.len()] {
+        Command::Select { mode: SelectMode::ShiftSelect, units } => {
+            assert_eq!(units.iter().collect::<Vec<_>>(), [UnitRef::Legacy(1), UnitRef::Legacy(2)]);
+        }
+        ref x => panic!("Expected select, got {:?}", x),
+    }
+    match decoded[commands.len() + 1] {
+        Command::Select { mode: SelectMode::ShiftDeselect, units } => {
+            assert_eq!(units.iter().collect::<Vec<_>>(), [UnitRef::Extended(0x10001)]);
+        }
+        ref x => panic!("Expected select, got {:?}", x),
+    }
+    assert_eq!(decoded.len(), commands.len() + 2);
+    assert_eq!(buf.as_slice()[buf.as_slice().len() - 6], 0x65);
+}
+
+#[test]
+fn encode_errors() {
+    let lengths = known_lengths();
+    let mut buf = CommandBuffer::with_lengths(&lengths);
+    buf.command(&Command::Stim).unwrap();
+    assert_eq!(
+        buf.select(SelectMode::Select, &[]).err(),
+        Some(EncodeError::UnitCount(0)),
+    );
+    assert_eq!(
+        buf.select(SelectMode::Select, &[UnitRef::Legacy(1), UnitRef::Extended(2)]).err(),
+        Some(EncodeError::MixedUnitRefs),
+    );
+    assert_eq!(
+        buf.command(&Command::SaveGame { unk: 0, filename: b"a\0b" }).err(),
+        Some(EncodeError::InvalidFilename),
+    );
+    assert_eq!(
+        buf.command(&Command::Chat { player: 0, message: &[b'a'; 80] }).err(),
+        Some(EncodeError::ChatTooLong(80)),
+    );
+    assert_eq!(
+        buf.command(&Command::Other { id: 0x37, data: &[0x38, 0, 0, 0, 0, 0, 0] }).err(),
+        Some(EncodeError::OtherIdMismatch { id: 0x37 }),
+    );
+    buf.command(&Command::Other { id: 0x37, data: &[0x37, 0, 0, 0, 0, 0, 0] }).unwrap();
+    // Host's table disagreeing with the layout
+    let mut wrong = vec![0u32; 0x100];
+    wrong[0x1f] = 5;
+    let wrong = CommandLengths::with_defaults(&wrong);
+    assert_eq!(
+        encode::encode_with(&wrong, &Command::Train { unit_id: 1 }),
+        Err(EncodeError::LengthMismatch { id: 0x1f, encoded: 3, expected: 5 }),
+    );
+    // No length table entry
+    let unset = CommandLengths::with_defaults(&[]);
+    assert_eq!(
+        CommandBuffer::with_lengths(&unset).command(&Command::Stim).err(),
+        Some(EncodeError::UnknownLength { id: 0x36 }),
+    );
+    // Failed commands weren't added
+    assert_eq!(buf.as_slice(), &[0x36, 0x37, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn default_length_tables() {
+    use samase_plugin::commands::lengths::{self, LengthDifference, Target, NO_LENGTH};
+
+    for &target in &[Target::V1161, Target::Scr] {
+        let table = lengths::command_lengths(target);
+        // Agrees with every command that `decode` knows the layout of
+        for id in 0..=0xffu8 {
+            let mut cmd = [0u8; 0x100];
+            cmd[0] = id;
+            cmd[1] = 2;
+            cmd[5] = b'a';
+            if let Ok(Some(len)) = decode::known_command_len(&cmd) {
+                if target == Target::V1161 && (0x60..=0x62).contains(&id) {
+                    assert!(!table.is_set(id), "{:x}", id);
+                } else {
+                    assert_eq!(table.command_len(&cmd), len, "{:x}", id);
+                }
+            }
+        }
+        assert!(lengths::diff(target, target.lengths()).is_empty());
+    }
+
+    let table = lengths::command_lengths(Target::Scr);
+    let data = [0x63, 0x01, 0x44, 0x33, 0x22, 0x11, 0x3d, 0x50, 0x1a, 0x00, 0x37, 1, 2, 3, 4, 5, 6];
+    let commands = decode::iter_with(&table, &data).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(commands.len(), 4);
+    assert_eq!(commands[1], Command::Other { id: 0x3d, data: &[0x3d, 0x50] });
+    assert!(!lengths::command_lengths(Target::V1161).is_set(0x61));
+
+    let mut host = lengths::V1161_LENGTHS.to_vec();
+    host[0x14] = 9;
+    host[0x16] = 4;
+    host[0x5c] = NO_LENGTH;
+    // Overridden anyway
+    host[0x09] = 0;
+    host[0x37] = 1;
+    assert_eq!(lengths::diff(Target::V1161, &host), vec![
+        LengthDifference { id: 0x14, expected: Some(10), host: Some(9) },
+        LengthDifference { id: 0x16, expected: None, host: Some(4) },
+        LengthDifference { id: 0x5c, expected: Some(82), host: None },
+    ]);
+    // Only entries that the host table has are compared
+    assert!(lengths::diff(Target::Scr, &lengths::V1161_LENGTHS).is_empty());
+}
@@ -0,0 +1,81 @@
+extern crate samase_plugin;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use samase_plugin::mock_host::{self, MockHost};
+use samase_plugin::{entry, SamaseApi, MAX_FUNC_ID, VERSION};
+
+static INIT_CALLS: AtomicUsize = AtomicUsize::new(0);
+static INIT_PANICS: AtomicBool = AtomicBool::new(false);
+
+unsafe fn init(api: &'static SamaseApi) {
+    INIT_CALLS.fetch_add(1, Ordering::Relaxed);
+    if INIT_PANICS.load(Ordering::Relaxed) {
+        panic!("Init failed with version {}", api.version());
+    }
+    api.print_text("Initialized").unwrap();
+}
+
+samase_plugin::entry! {
+    name: "test_plugin",
+    min_version: VERSION,
+    min_max_func_id: 10,
+    init: init,
+}
+
+// Each test uses its own thread-local MockHost, but share INIT_CALLS, so everything
+// is in a single test.
+#[test]
+fn entry() {
+    unsafe {
+        let host = MockHost::with_version(VERSION - 1);
+        samase_plugin_init(host.api());
+        assert_eq!(INIT_CALLS.load(Ordering::Relaxed), 0);
+        let expected = format!(
+            "test_plugin requires samase API version {}, this samase has version {}.",
+            VERSION,
+            VERSION - 1,
+        );
+        let warnings = host.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with(&expected), "{:?}", warnings);
+        drop(host);
+
+        let host = MockHost::new();
+        assert!(entry::check_api(host.api(), "test", VERSION + 1, 0).is_none());
+        assert!(entry::check_api(host.api(), "test", VERSION, MAX_FUNC_ID + 1).is_none());
+        let warnings = host.warnings();
+        assert_eq!(warnings.len(), 2);
+        let expected = format!("test requires samase API version {}", VERSION + 1);
+        assert!(warnings[0].starts_with(&expected));
+        assert!(warnings[1].starts_with("test requires samase to support"));
+        drop(host);
+
+        let host = MockHost::new();
+        samase_plugin_init(host.api());
+        assert_eq!(INIT_CALLS.load(Ordering::Relaxed), 1);
+        assert_eq!(host.printed_text(), vec!["Initialized".to_string()]);
+        drop(host);
+    }
+
+    // crash_with_message can't return, so the thread is left parked.
+    INIT_PANICS.store(true, Ordering::Relaxed);
+    std::thread::spawn(|| unsafe {
+        let host = MockHost::new();
+        host.park_on_crash();
+        samase_plugin_init(host.api());
+    });
+    let expected = format!(
+        "test_plugin panicked during initialization: Init failed with version {}",
+        VERSION,
+    );
+    for _ in 0..500 {
+        if mock_host::crash_messages().contains(&expected) {
+            assert_eq!(INIT_CALLS.load(Ordering::Relaxed), 2);
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("No crash message: {:?}", mock_host::crash_messages());
+}
@@ -0,0 +1,70 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use samase_plugin::funcs::{self, FUNC_SIGNATURES};
+use samase_plugin::mock_host::MockHost;
+use samase_plugin::{FuncId, SamaseApi, MAX_FUNC_ID};
+
+#[test]
+fn signature_table() {
+    assert_eq!(FUNC_SIGNATURES.len(), MAX_FUNC_ID as usize);
+    for (i, sig) in FUNC_SIGNATURES.iter().enumerate() {
+        assert_eq!(sig.id as usize, i, "{}", sig.name);
+    }
+    let damage = funcs::signature(FuncId::DamageUnit).unwrap();
+    assert_eq!(damage.name, "damage_unit");
+    assert_eq!(damage.args.len(), 5);
+    assert_eq!(damage.args[1], ("damage", "i32"));
+    assert_eq!(damage.ret, "()");
+    assert_eq!(funcs::signature(FuncId::UnitMaxEnergy).unwrap().ret, "u32");
+}
+
+static DAMAGE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe extern "C" fn damage_orig(
+    target: usize,
+    damage: usize,
+    _attacker: usize,
+    _player: usize,
+    _show: usize,
+) -> usize {
+    assert_eq!(target, 0x40);
+    DAMAGE.store(damage, Ordering::Relaxed);
+    0
+}
+
+unsafe extern "C" fn max_energy(unit: usize) -> usize {
+    unit * 2
+}
+
+unsafe fn as_func(func: *const ()) -> unsafe extern "C" fn() {
+    mem::transmute::<*const (), unsafe extern "C" fn()>(func)
+}
+
+#[test]
+fn typed_hooks() {
+    let host = MockHost::new();
+    host.set_func(FuncId::DamageUnit, unsafe { as_func(damage_orig as *const ()) });
+    host.set_func(FuncId::UnitMaxEnergy, unsafe { as_func(max_energy as *const ()) });
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        funcs::hook_damage_unit(&api, |target, damage, attacker, player, show, orig| {
+            orig(target, damage / 2, attacker, player, show)
+        }).unwrap();
+        let hooks = host.func_hooks(FuncId::DamageUnit);
+        assert_eq!(hooks.len(), 1);
+        let hook: unsafe extern "C" fn(
+            usize, usize, usize, usize, usize,
+            unsafe extern "C" fn(usize, usize, usize, usize, usize) -> usize,
+        ) -> usize = mem::transmute(hooks[0]);
+        hook(0x40, 100, 0, 1, 0, damage_orig);
+        assert_eq!(DAMAGE.load(Ordering::Relaxed), 50);
+
+        let unit = 0x100 as *mut c_void;
+        assert_eq!(funcs::call_unit_max_energy(&api, unit), Some(0x200));
+        assert_eq!(funcs::call_kill_unit(&api, unit), None);
+    }
+}
@@ -0,0 +1,46 @@
+extern crate samase_plugin;
+
+use std::process::Command;
+
+use samase_plugin::abi::API_FIELDS;
+
+fn header() -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_gen_header")).output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn plugin_api_fields_in_order() {
+    let header = header();
+    let start = header.find("struct SamasePluginApi {").unwrap();
+    let body = &header[start..];
+    let body = &body[..body.find("};").unwrap()];
+    let names: Vec<&str> = body.lines()
+        .map(|x| x.trim())
+        .filter(|x| !x.starts_with("//") && x.ends_with(';'))
+        .map(|x| x.trim_end_matches(';').rsplit(' ').next().unwrap())
+        .collect();
+    let expected: Vec<&str> = API_FIELDS.iter().map(|x| x.name).collect();
+    assert_eq!(names, expected);
+}
+
+#[test]
+fn enums_and_typedefs() {
+    let header = header();
+    let version = format!("#define SAMASE_PLUGIN_VERSION {}", samase_plugin::VERSION);
+    assert!(header.contains(&version));
+    assert!(header.contains("    SamaseFuncId_DamageUnit = 5,\n"));
+    assert!(header.contains("    // this = target, damage, attacker_unit, attacker_player, show_attacker\n"));
+    assert!(header.contains("    SamaseVarId_Game = 0,\n"));
+    assert!(header.contains("    SamaseComplexLineParamType_PlayerId = 8,\n"));
+    assert!(header.contains(
+        "typedef uint8_t* (*SamasePluginApi_read_file_ret)(const uint8_t*, uintptr_t*);\n"
+    ));
+    assert!(header.contains(
+        "typedef uint32_t (*SamasePluginApi_hook_ingame_command)\
+        (uint32_t, SamaseIngameCommandHook, SamaseCommandLength);\n"
+    ));
+    // Comments inside the type are moved before the field
+    assert!(header.contains("    // Func ID (enum)\n"));
+}
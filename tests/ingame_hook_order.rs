@@ -0,0 +1,82 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+use samase_plugin::commands::{self, IngameHookError, IngameHookOptions};
+
+static CALLS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+macro_rules! hooks {
+    ($($name:ident),*) => {
+        $(
+            unsafe extern "C" fn $name(
+                data: *const u8,
+                len: u32,
+                _: u32,
+                _: u32,
+                orig: unsafe extern "C" fn(*const u8, u32),
+            ) {
+                CALLS.lock().unwrap().push(stringify!($name));
+                orig(data, len);
+            }
+        )*
+    };
+}
+
+hooks!(a, b, c, d, e);
+
+unsafe extern "C" fn add_to_replay_data(_: *const u8, _: usize) {
+}
+
+unsafe extern "C" fn orig(_: *const c_void, _: u32, _: u32) {
+    CALLS.lock().unwrap().push("orig");
+}
+
+fn run_command(id: u8) -> Vec<&'static str> {
+    let globals = commands::IngameHookGlobals {
+        is_replay: 0,
+        unique_command_user: 0,
+        command_user: 0,
+        add_to_replay_data,
+    };
+    let data = [id];
+    unsafe {
+        commands::ingame_hook(data.as_ptr() as *const c_void, 1, 0, &globals, orig);
+    }
+    std::mem::take(&mut *CALLS.lock().unwrap())
+}
+
+#[test]
+fn ordering_and_removal() {
+    commands::set_default_command_lengths(&[1; 0x100]);
+    let named = |name, priority, before, after| IngameHookOptions {
+        name: Some(name),
+        priority,
+        before,
+        after,
+    };
+    let c = commands::add_ingame_hook(0x40, c);
+    commands::add_ingame_hook_with(0x40, a, &named("a", 0, &[], &["b"])).unwrap();
+    let b = commands::add_ingame_hook_with(0x40, b, &named("b", 0, &[], &[])).unwrap();
+    // Higher priority goes first, but `d` has to be after `a`
+    commands::add_ingame_hook_with(0x40, d, &named("d", 10, &[], &["a"])).unwrap();
+    commands::add_ingame_hook_with(0x41, e, &named("e", 0, &["a"], &[])).unwrap();
+    assert_eq!(run_command(0x40), ["c", "b", "a", "d", "orig"]);
+    assert_eq!(run_command(0x41), ["e", "orig"]);
+
+    assert_eq!(
+        commands::add_ingame_hook_with(0x40, e, &named("a", 0, &[], &[])),
+        Err(IngameHookError::DuplicateName("a".into())),
+    );
+    assert_eq!(
+        commands::add_ingame_hook_with(0x40, e, &named("cyclic", 0, &["b"], &["d"])),
+        Err(IngameHookError::Cycle("cyclic".into())),
+    );
+    assert_eq!(run_command(0x40), ["c", "b", "a", "d", "orig"]);
+
+    assert!(b.remove());
+    assert!(!commands::remove_ingame_hook(b));
+    assert!(c.remove());
+    assert_eq!(run_command(0x40), ["a", "d", "orig"]);
+}
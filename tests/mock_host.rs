@@ -0,0 +1,180 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use samase_plugin::capabilities::{Capabilities, Missing, Requirements};
+use samase_plugin::mock_host::MockHost;
+use samase_plugin::{FuncId, SamaseApi, VarId};
+
+#[test]
+fn files_and_text() {
+    let host = MockHost::new();
+    host.add_file("arr\\units.dat", vec![1, 2, 3]);
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        assert_eq!(&*api.read_file("arr\\units.dat").unwrap(), &[1, 2, 3]);
+        assert!(api.read_file("arr\\weapons.dat").is_none());
+        api.print_text("Hello").unwrap();
+        api.warn_unsupported_feature("Something").unwrap();
+    }
+    assert_eq!(host.printed_text(), vec!["Hello".to_string()]);
+    assert_eq!(host.warnings(), vec!["Something".to_string()]);
+}
+
+#[test]
+fn vars() {
+    let host = MockHost::new();
+    host.set_var(VarId::Game, 0x1234, false);
+    host.set_var(VarId::RngSeed, 5, true);
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        assert_eq!(api.game(), Some(0x1234 as *mut c_void));
+        assert!(api.first_active_unit().is_none());
+        let vars = [VarId::Game as u16, VarId::RngSeed as u16, VarId::Units as u16];
+        let mut access = [0u8; 3];
+        api.load_vars(&vars, &mut access).unwrap();
+        assert_eq!(access, [2, 3, 0]);
+        api.write_vars(&vars, &[1, 2, 3]).unwrap();
+        let mut values = [0usize; 3];
+        api.read_vars(&vars, &mut values).unwrap();
+        assert_eq!(values, [0x1234, 2, 0]);
+    }
+    assert_eq!(host.var(VarId::RngSeed), Some(2));
+}
+
+static STEP_ORDER_CALLS: AtomicUsize = AtomicUsize::new(0);
+static ORIG_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe extern "C" fn step_order(unit: *mut c_void, orig: unsafe extern "C" fn(*mut c_void)) {
+    assert_eq!(unit as usize, 0x50);
+    STEP_ORDER_CALLS.fetch_add(1, Ordering::Relaxed);
+    orig(unit);
+}
+
+unsafe extern "C" fn step_order_orig(unit: *mut c_void) {
+    assert_eq!(unit as usize, 0x50);
+    ORIG_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[test]
+fn unit_hooks() {
+    let host = MockHost::new();
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        api.hook_step_order(step_order).unwrap();
+        api.hook_step_order(step_order).unwrap();
+        assert_eq!(host.hooks("hook_step_order").len(), 2);
+        host.fire_unit_hooks("hook_step_order", 0x50 as *mut c_void, step_order_orig);
+    }
+    assert_eq!(STEP_ORDER_CALLS.load(Ordering::Relaxed), 2);
+    assert_eq!(ORIG_CALLS.load(Ordering::Relaxed), 1);
+}
+
+unsafe extern "C" fn add_one(
+    data: *const u8,
+    len: u32,
+    player: u32,
+    _: u32,
+    orig: unsafe extern "C" fn(*const u8, u32),
+) {
+    assert_eq!(player, 3);
+    let data = std::slice::from_raw_parts(data, len as usize);
+    let new = [data[0], data[1] + 1];
+    orig(new.as_ptr(), 2);
+}
+
+#[test]
+fn ingame_commands() {
+    let host = MockHost::new();
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        api.hook_ingame_command(0x80, add_one, None).unwrap();
+        api.hook_ingame_command(0x80, add_one, None).unwrap();
+        host.fire_ingame_command(&[0x80, 1], 3, 3);
+        host.fire_ingame_command(&[0x81, 1], 3, 3);
+    }
+    assert_eq!(host.processed_commands(), vec![vec![0x80, 3], vec![0x81, 1]]);
+}
+
+static SAVED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe extern "C" fn save(add_data: unsafe extern "C" fn(*const u8, usize)) {
+    let data = [1u8, 2, 3];
+    add_data(data.as_ptr(), data.len());
+}
+
+unsafe extern "C" fn load(data: *const u8, len: usize) -> u32 {
+    let data = std::slice::from_raw_parts(data, len);
+    SAVED.store(data.len(), Ordering::Relaxed);
+    (data == [1, 2, 3]) as u32
+}
+
+unsafe extern "C" fn init() {
+}
+
+#[test]
+fn save_extensions() {
+    let host = MockHost::new();
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        api.extend_save(c"test".as_ptr() as *const u8, Some(save), Some(load), init).unwrap();
+        let saved = host.save_extensions();
+        assert_eq!(saved, vec![("test".to_string(), vec![1, 2, 3])]);
+        assert!(host.load_extension("test", &saved[0].1));
+        assert!(!host.load_extension("test", &[5]));
+    }
+    assert_eq!(SAVED.load(Ordering::Relaxed), 1);
+}
+
+unsafe extern "C" fn dummy_func() {
+}
+
+#[test]
+fn old_version() {
+    let host = MockHost::with_version(20);
+    host.set_var(VarId::IsReplay, 1, false);
+    host.set_func(FuncId::DamageUnit, dummy_func);
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        // Fields that predate recorded versions are assumed to exist in every host
+        assert!(api.has_field("max_func_id"));
+        assert!(api.has_field("units"));
+        assert!(api.has_field("free_memory"));
+        assert!(!api.has_field("not_a_field"));
+        assert!(api.is_replay().is_some());
+        assert!(api.get_func(FuncId::DamageUnit).is_some());
+        assert!(api.hook_func(FuncId::DamageUnit, 0).is_ok());
+
+        let caps = Capabilities::probe(&api);
+        let req = Requirements::new()
+            .field("max_func_id")
+            .field("is_replay")
+            .field("not_a_field")
+            .func(FuncId::DamageUnit);
+        assert_eq!(caps.missing(&req), vec![
+            Missing::Field { name: "not_a_field", old_host: false },
+        ]);
+    }
+}
+
+#[test]
+fn capabilities() {
+    let host = MockHost::new();
+    host.set_var(VarId::RngSeed, 1, true);
+    host.set_func(FuncId::KillUnit, dummy_func);
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        let caps = Capabilities::probe(&api);
+        assert!(caps.has_func(FuncId::KillUnit));
+        assert!(!caps.has_func(FuncId::DamageUnit));
+        assert!(caps.can_write_var(VarId::RngSeed));
+        assert!(!caps.can_read_var(VarId::Game));
+        assert!(caps.has_field("mutate_dat"));
+        let req = Requirements::new()
+            .func(FuncId::KillUnit)
+            .var_writable(VarId::RngSeed);
+        assert!(caps.check(&req).is_ok());
+        assert!(caps.to_string().contains("KillUnit: ok"));
+    }
+}
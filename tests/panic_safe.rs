@@ -0,0 +1,58 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use samase_plugin::mock_host::{self, MockHost};
+use samase_plugin::{panic_safe, SamaseApi};
+
+static ORIG_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe extern "C" fn orig(_unit: *mut c_void) {
+    ORIG_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn wait_for_crash(expected: &str) {
+    for _ in 0..500 {
+        if mock_host::crash_messages().iter().any(|x| x == expected) {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("No crash message: {:?}", mock_host::crash_messages());
+}
+
+#[test]
+fn no_panic() {
+    let host = MockHost::new();
+    let hook = panic_safe::step_order(|unit, orig| unsafe {
+        orig(unit);
+    });
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        api.hook_step_order(hook).unwrap();
+        host.fire_unit_hooks("hook_step_order", 0x50 as *mut c_void, orig);
+    }
+    assert_eq!(ORIG_CALLS.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn panic_is_reported() {
+    // crash_with_message can't return, so the thread is left parked.
+    std::thread::spawn(|| unsafe {
+        let host = MockHost::new();
+        host.park_on_crash();
+        let api: &'static SamaseApi = Box::leak(Box::new(SamaseApi::new(host.api())));
+        panic_safe::set_api(api);
+        let hook = panic_safe::load(|_data, len| {
+            if len == 0 {
+                panic!("Empty save data");
+            }
+            1
+        });
+        assert_eq!(hook([1u8].as_ptr(), 1), 1);
+        hook([].as_ptr(), 0);
+    });
+    wait_for_crash("Panic in load hook: Empty save data");
+}
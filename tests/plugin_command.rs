@@ -0,0 +1,180 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+use samase_plugin::commands::{self, plugin_command, replay};
+use samase_plugin::commands::plugin_command::{
+    PluginCommandError, Registry, MAX_COMMAND_LEN, PLUGIN_COMMAND_ID,
+};
+
+static RECEIVED: Mutex<Vec<(&'static str, Vec<u8>, u32)>> = Mutex::new(Vec::new());
+static ORIG_CALLS: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+/// Registries of two plugins; `FIRST` passes unknown tags to `SECOND`.
+static FIRST: Registry = Registry::new();
+static SECOND: Registry = Registry::new();
+static CHAINED: Mutex<Vec<(&'static str, Vec<u8>)>> = Mutex::new(Vec::new());
+
+unsafe fn receive(tag: &'static str, data: *const u8, len: u32, player: u32) {
+    let payload = std::slice::from_raw_parts(data, len as usize).to_vec();
+    RECEIVED.lock().unwrap().push((tag, payload, player));
+}
+
+unsafe extern "C" fn hook_a(
+    data: *const u8,
+    len: u32,
+    player: u32,
+    _: u32,
+    _: unsafe extern "C" fn(*const u8, u32),
+) {
+    receive("a", data, len, player);
+}
+
+unsafe extern "C" fn hook_b(
+    data: *const u8,
+    len: u32,
+    player: u32,
+    _: u32,
+    orig: unsafe extern "C" fn(*const u8, u32),
+) {
+    receive("b", data, len, player);
+    // Not forwarded to the game
+    orig(data, len);
+}
+
+/// Claim notice that registries pass on after handling a command with `tag`.
+fn claim(tag: &str) -> Vec<u8> {
+    let mut out = vec![PLUGIN_COMMAND_ID, 0, tag.len() as u8, 0];
+    out.extend_from_slice(tag.as_bytes());
+    out
+}
+
+unsafe extern "C" fn add_to_replay_data(_: *const u8, _: usize) {
+}
+
+unsafe extern "C" fn orig(data: *const c_void, len: u32, _: u32) {
+    let data = std::slice::from_raw_parts(data as *const u8, len as usize);
+    ORIG_CALLS.lock().unwrap().push(data.to_vec());
+}
+
+#[test]
+fn plugin_commands() {
+    let mut lengths = vec![0u32; 0x100];
+    lengths[0x1f] = 3;
+    commands::set_default_command_lengths(&lengths);
+    plugin_command::enable();
+    plugin_command::register("plugin_a", hook_a).unwrap();
+    plugin_command::register("plugin_b", hook_b).unwrap();
+    assert_eq!(
+        plugin_command::register("plugin_a", hook_b),
+        Err(PluginCommandError::TagInUse("plugin_a".into())),
+    );
+    assert_eq!(plugin_command::register("", hook_b), Err(PluginCommandError::EmptyTag));
+    assert_eq!(
+        plugin_command::encode(&"x".repeat(256), &[]),
+        Err(PluginCommandError::TagTooLong(256)),
+    );
+    // Longest command that can still be recorded to replays
+    let longest = plugin_command::encode("plugin_a", &[0; MAX_COMMAND_LEN - 12]).unwrap();
+    assert_eq!(longest.len(), replay::MAX_WRAPPED_LEN);
+    assert!(replay::wrap(&longest).is_some());
+    assert_eq!(
+        plugin_command::encode("plugin_a", &[0; MAX_COMMAND_LEN - 11]),
+        Err(PluginCommandError::PayloadTooLong(MAX_COMMAND_LEN - 11)),
+    );
+
+    let a = plugin_command::encode("plugin_a", &[1, 2, 3]).unwrap();
+    assert_eq!(a[0], PLUGIN_COMMAND_ID);
+    assert_eq!(plugin_command::decode(&a), Some((&b"plugin_a"[..], &[1u8, 2, 3][..])));
+    assert_eq!(plugin_command::decode(&a[..a.len() - 1]), None);
+    let b = plugin_command::encode("plugin_b", &[]).unwrap();
+    let unknown = plugin_command::encode("plugin_c", &[4]).unwrap();
+    let mut data = Vec::new();
+    data.extend_from_slice(&a);
+    data.extend_from_slice(&[0x1f, 0x07, 0x00]);
+    data.extend_from_slice(&unknown);
+    data.extend_from_slice(&b);
+    let globals = commands::IngameHookGlobals {
+        is_replay: 0,
+        unique_command_user: 0,
+        command_user: 3,
+        add_to_replay_data,
+    };
+    unsafe {
+        commands::ingame_hook(data.as_ptr() as *const c_void, data.len() as u32, 0, &globals, orig);
+    }
+    assert_eq!(*RECEIVED.lock().unwrap(), vec![("a", vec![1, 2, 3], 3), ("b", vec![], 3)]);
+    // Unknown tags are passed on for other plugins, handled ones as claim notices
+    assert_eq!(*ORIG_CALLS.lock().unwrap(), vec![
+        claim("plugin_a"),
+        vec![0x1f, 0x07, 0x00],
+        unknown,
+        claim("plugin_b"),
+    ]);
+    assert!(plugin_command::duplicate_tags().is_empty());
+}
+
+unsafe extern "C" fn chained_a(
+    data: *const u8,
+    len: u32,
+    _: u32,
+    _: u32,
+    _: unsafe extern "C" fn(*const u8, u32),
+) {
+    CHAINED.lock().unwrap().push(("a", std::slice::from_raw_parts(data, len as usize).to_vec()));
+}
+
+unsafe extern "C" fn chained_b(
+    data: *const u8,
+    len: u32,
+    _: u32,
+    _: u32,
+    _: unsafe extern "C" fn(*const u8, u32),
+) {
+    CHAINED.lock().unwrap().push(("b", std::slice::from_raw_parts(data, len as usize).to_vec()));
+}
+
+unsafe extern "C" fn to_second(data: *const u8, len: u32) {
+    let cmd = std::slice::from_raw_parts(data, len as usize);
+    SECOND.dispatch(cmd, 0, 0, to_game);
+}
+
+unsafe extern "C" fn to_game(data: *const u8, len: u32) {
+    let cmd = std::slice::from_raw_parts(data, len as usize).to_vec();
+    CHAINED.lock().unwrap().push(("game", cmd));
+}
+
+#[test]
+fn chained_registries() {
+    FIRST.register("tag_a", chained_a).unwrap();
+    SECOND.register("tag_b", chained_b).unwrap();
+    // Registries don't see each other's tags when registering
+    SECOND.register("tag_a", chained_b).unwrap();
+    SECOND.register("tag_c", chained_b).unwrap();
+    let a = plugin_command::encode("tag_a", &[1]).unwrap();
+    let b = plugin_command::encode("tag_b", &[2]).unwrap();
+    let c = plugin_command::encode("tag_c", &[3]).unwrap();
+    let d = plugin_command::encode("tag_d", &[4]).unwrap();
+    for cmd in [&a, &b, &c, &d] {
+        unsafe {
+            FIRST.dispatch(cmd, 0, 0, to_second);
+        }
+    }
+    // `tag_a` only reaches the first registry, which the second one notices
+    assert_eq!(*CHAINED.lock().unwrap(), vec![
+        ("a", vec![1]),
+        ("game", claim("tag_a")),
+        ("b", vec![2]),
+        ("game", claim("tag_b")),
+        ("b", vec![3]),
+        ("game", claim("tag_c")),
+        ("game", d),
+    ]);
+    assert!(FIRST.duplicate_tags().is_empty());
+    assert_eq!(SECOND.duplicate_tags(), vec!["tag_a".to_string()]);
+    // Noticed only once
+    unsafe {
+        FIRST.dispatch(&a, 0, 0, to_second);
+    }
+    assert_eq!(SECOND.duplicate_tags(), vec!["tag_a".to_string()]);
+}
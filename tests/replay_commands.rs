@@ -0,0 +1,77 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+use samase_plugin::commands::{self, replay};
+use samase_plugin::commands::replay::ReplayClass;
+
+static EXECUTED: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+static RECORDED: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+unsafe extern "C" fn add_to_replay_data(data: *const u8, len: usize) {
+    let cmd = std::slice::from_raw_parts(data, len);
+    RECORDED.lock().unwrap().push(cmd.to_vec());
+}
+
+unsafe extern "C" fn orig(data: *const c_void, len: u32, _: u32) {
+    let cmd = std::slice::from_raw_parts(data as *const u8, len as usize);
+    EXECUTED.lock().unwrap().push(cmd.to_vec());
+}
+
+/// Returns (executed, recorded) commands
+fn run(data: &[u8], is_replay: bool, replayed: bool) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+    let globals = commands::IngameHookGlobals {
+        is_replay: is_replay as u32,
+        unique_command_user: 0,
+        command_user: 0,
+        add_to_replay_data,
+    };
+    unsafe {
+        let ptr = data.as_ptr() as *const c_void;
+        commands::ingame_hook(ptr, data.len() as u32, replayed as u32, &globals, orig);
+    }
+    let executed = std::mem::take(&mut *EXECUTED.lock().unwrap());
+    let recorded = std::mem::take(&mut *RECORDED.lock().unwrap());
+    (executed, recorded)
+}
+
+#[test]
+fn replay_classes() {
+    let mut lengths = vec![1u32; 0x100];
+    lengths[0x70] = 3;
+    commands::set_default_command_lengths(&lengths);
+    assert_eq!(replay::replay_class(0x10), ReplayClass::Skipped);
+    assert_eq!(replay::replay_class(0x1f), ReplayClass::Recorded);
+    replay::set_replay_class(0x71, ReplayClass::ReplayOnly);
+    replay::set_replay_class(0x72, ReplayClass::Skipped);
+
+    let data = [0x10, 0x1a, 0x70, 0x01, 0x02, 0x71, 0x72];
+    let (executed, recorded) = run(&data, false, false);
+    assert_eq!(executed, [&[0x10][..], &[0x1a], &[0x70, 1, 2], &[0x72]]);
+    assert_eq!(recorded, [&[0x1a][..], &[0x70, 1, 2], &[0x71]]);
+    // Live commands while watching a replay
+    let (executed, recorded) = run(&data, true, false);
+    assert_eq!(executed, [&[0x10][..], &[0x72]]);
+    assert!(recorded.is_empty());
+    // Commands from the replay
+    let (executed, recorded) = run(&[0x1a, 0x70, 0x01, 0x02, 0x71], true, true);
+    assert_eq!(executed, [&[0x1a][..], &[0x70, 1, 2], &[0x71]]);
+    assert!(recorded.is_empty());
+    // Wrapped commands are unwrapped on playback
+    let wrapped = replay::wrap(&[0x70, 0x01, 0x02]).unwrap();
+    let (executed, _) = run(&wrapped, true, true);
+    assert_eq!(executed, [&[0x70, 1, 2]]);
+}
+
+#[test]
+fn wrapped_commands() {
+    let cmd = [0x70, 0x01, 0x02];
+    let wrapped = replay::wrap(&cmd).unwrap();
+    assert_eq!(wrapped, [0x09, 0x04, 0xff, 0xff, 0x03, 0x00, 0x70, 0x01, 0x02, 0x00]);
+    assert_eq!(replay::unwrap(&wrapped), Some(&cmd[..]));
+    assert_eq!(replay::unwrap(&[0x09, 0x01, 0x05, 0x00]), None);
+    let longest = vec![0x70; replay::MAX_WRAPPED_LEN];
+    assert_eq!(replay::wrap(&longest).unwrap().len(), 0xff * 2 + 2);
+    assert_eq!(replay::wrap(&[0x70; replay::MAX_WRAPPED_LEN + 1]), None);
+}
@@ -0,0 +1,254 @@
+extern crate byteorder;
+extern crate flate2;
+extern crate samase_plugin;
+
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::slice;
+use std::sync::Mutex;
+
+use byteorder::{WriteBytesExt, LE};
+
+use samase_plugin::save::{self, ChunkHeader, CorruptChunkAction};
+use samase_plugin::save_file::{
+    self, COMPRESSION_DEFLATE, COMPRESSION_MASK, COMPRESSION_NONE, FLAG_CHECKSUM, SAVE_MAGIC,
+};
+
+type Loaded = (&'static str, Vec<u8>, Option<ChunkHeader>);
+/// Tag, data, version, flags
+type ReadChunk = (String, Vec<u8>, u32, u32);
+
+static LOADED: Mutex<Vec<Loaded>> = Mutex::new(Vec::new());
+static LOADED_CHECKED: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+#[test]
+fn chunk_versions() {
+    save::add_hook("versioned".into(), Some(save_versioned), Some(load_versioned), nop_init);
+    save::add_hook("plain".into(), Some(save_plain), Some(load_plain), nop_init);
+    let data = fs::read("tests/save.snx").unwrap();
+    let mut save_file = TestFile(Cursor::new(data), Vec::new());
+    save::call_save_hooks(&mut save_file).unwrap();
+    save::call_load_hooks(&mut save_file).unwrap();
+    assert!(save_file.1.is_empty());
+    assert_eq!(save::loading_chunk(), None);
+    let deflate = ChunkHeader {
+        version: 0,
+        flags: COMPRESSION_DEFLATE | FLAG_CHECKSUM,
+    };
+    assert_eq!(*LOADED.lock().unwrap(), vec![
+        ("versioned", vec![1, 2, 3], Some(ChunkHeader { version: 3, ..deflate })),
+        ("plain", vec![4; 100], Some(deflate)),
+    ]);
+}
+
+struct RawChunk<'a> {
+    tag: &'a str,
+    data: &'a [u8],
+    version: u32,
+    flags: u32,
+    /// Written if `flags` has `FLAG_CHECKSUM`
+    checksum: u32,
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+impl<'a> RawChunk<'a> {
+    fn new(tag: &'a str, data: &'a [u8], version: u32, flags: u32) -> RawChunk<'a> {
+        RawChunk { tag, data, version, flags, checksum: crc32(data) }
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer =
+        flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    writer.write_all(data).unwrap();
+    writer.finish().unwrap()
+}
+
+/// Appends a save extension of `format_version` to `tests/save.snx`.
+fn save_with_extension(format_version: u32, chunks: &[RawChunk]) -> Vec<u8> {
+    let mut out = fs::read("tests/save.snx").unwrap();
+    let chunk_start = out.len();
+    let mut ext = Vec::new();
+    ext.write_u32::<LE>(format_version).unwrap();
+    ext.write_u64::<LE>(chunks.len() as u64).unwrap();
+    let mut data = Vec::new();
+    for chunk in chunks {
+        let stored = if chunk.flags & COMPRESSION_MASK == COMPRESSION_NONE {
+            chunk.data.to_vec()
+        } else {
+            deflate(chunk.data)
+        };
+        ext.write_u64::<LE>(chunk.tag.len() as u64).unwrap();
+        ext.extend_from_slice(chunk.tag.as_bytes());
+        ext.write_u64::<LE>(chunk.data.len() as u64).unwrap();
+        ext.write_u64::<LE>(stored.len() as u64).unwrap();
+        if format_version != 0 {
+            ext.write_u32::<LE>(chunk.version).unwrap();
+            ext.write_u32::<LE>(chunk.flags).unwrap();
+            if chunk.flags & FLAG_CHECKSUM != 0 {
+                ext.write_u32::<LE>(chunk.checksum).unwrap();
+            }
+        }
+        data.extend_from_slice(&stored);
+    }
+    ext.extend_from_slice(&data);
+    ext.write_u32::<LE>(chunk_start as u32).unwrap();
+    out.write_u32::<LE>(SAVE_MAGIC).unwrap();
+    out.write_u32::<LE>(ext.len() as u32).unwrap();
+    out.extend_from_slice(&ext);
+    out
+}
+
+fn read_chunks(data: Vec<u8>) -> Result<Vec<ReadChunk>, save_file::Error> {
+    let mut file = TestFile(Cursor::new(data), Vec::new());
+    save_file::iter_extensions(&mut &mut file)?
+        .map(|x| x.map(|x| (x.tag, x.data, x.version, x.flags)))
+        .collect()
+}
+
+#[test]
+fn format_versions() {
+    let chunks = [
+        RawChunk::new("a", &[1, 2, 3], 5, COMPRESSION_DEFLATE),
+        RawChunk::new("bb", &[9; 40], 0, COMPRESSION_NONE),
+    ];
+    let result = read_chunks(save_with_extension(1, &chunks)).unwrap();
+    assert_eq!(result, vec![
+        ("a".into(), vec![1, 2, 3], 5, COMPRESSION_DEFLATE),
+        ("bb".into(), vec![9; 40], 0, COMPRESSION_NONE),
+    ]);
+
+    // Version 0 has no chunk versions or flags
+    let result = read_chunks(save_with_extension(0, &chunks[..1])).unwrap();
+    assert_eq!(result, vec![("a".into(), vec![1, 2, 3], 0, COMPRESSION_DEFLATE)]);
+
+    let unknown_flags = [RawChunk::new("a", &[1], 0, 0x8000_0000)];
+    assert!(read_chunks(save_with_extension(1, &unknown_flags)).is_err());
+    let unknown_compression = [RawChunk::new("a", &[1], 0, 7)];
+    assert!(read_chunks(save_with_extension(1, &unknown_compression)).is_err());
+    assert!(read_chunks(save_with_extension(2, &[])).is_err());
+}
+
+fn corrupted_save() -> Vec<u8> {
+    let chunks = [
+        RawChunk::new("checked_a", &[1, 2, 3], 0, COMPRESSION_NONE | FLAG_CHECKSUM),
+        RawChunk::new("checked_b", &[4, 5, 6], 0, COMPRESSION_DEFLATE | FLAG_CHECKSUM),
+        RawChunk::new("checked_c", &[7, 8, 9], 0, COMPRESSION_NONE | FLAG_CHECKSUM),
+    ];
+    let mut data = save_with_extension(1, &chunks);
+    // Corrupt the stored data of checked_a, which is right after the headers
+    let data_start = data.len() - 4 - 3 - deflate(&[4, 5, 6]).len() - 3;
+    data[data_start] ^= 0x40;
+    data
+}
+
+
+#[test]
+fn checksums() {
+    let result = read_chunks(save_with_extension(1, &[
+        RawChunk::new("a", &[1, 2, 3], 0, COMPRESSION_DEFLATE | FLAG_CHECKSUM),
+    ]));
+    assert_eq!(result.unwrap()[0].1, vec![1, 2, 3]);
+
+    let mut file = TestFile(Cursor::new(corrupted_save()), Vec::new());
+    let chunks = save_file::iter_extensions(&mut &mut file).unwrap().collect::<Vec<_>>();
+    match chunks[0] {
+        Err(save_file::Error::CorruptChunk(ref tag)) => assert_eq!(tag, "checked_a"),
+        ref x => panic!("Unexpected result {:?}", x.as_ref().map(|x| &x.tag)),
+    }
+    // Following chunks are still readable
+    assert_eq!(chunks[1].as_ref().unwrap().data, vec![4, 5, 6]);
+    assert_eq!(chunks[2].as_ref().unwrap().data, vec![7, 8, 9]);
+
+    save::add_hook("checked_a".into(), None, Some(load_checked), nop_init);
+    save::add_hook("checked_c".into(), None, Some(load_checked), nop_init);
+    let mut file = TestFile(Cursor::new(corrupted_save()), Vec::new());
+    let mut corrupted = Vec::new();
+    save::call_load_hooks_with(&mut file, |tag| {
+        corrupted.push(tag.to_string());
+        CorruptChunkAction::Skip
+    }).unwrap();
+    assert_eq!(corrupted, vec!["checked_a"]);
+    assert_eq!(file.1.len(), 1);
+    assert!(file.1[0].contains("checked_a"));
+    assert_eq!(*LOADED_CHECKED.lock().unwrap(), vec![vec![7, 8, 9]]);
+
+    let mut file = TestFile(Cursor::new(corrupted_save()), Vec::new());
+    match save::call_load_hooks(&mut file) {
+        Err(save::Error::SaveFile(save_file::Error::CorruptChunk(tag))) => {
+            assert_eq!(tag, "checked_a");
+        }
+        x => panic!("Unexpected result {:?}", x),
+    }
+    assert_eq!(file.1.len(), 1);
+    assert_eq!(LOADED_CHECKED.lock().unwrap().len(), 1);
+}
+
+unsafe extern "C" fn save_versioned(add_data: unsafe extern "C" fn(*const u8, usize)) {
+    let data = [1, 2, 3];
+    add_data(data.as_ptr(), data.len());
+    save::set_chunk_version(3);
+}
+
+unsafe extern "C" fn save_plain(add_data: unsafe extern "C" fn(*const u8, usize)) {
+    let data = [4; 100];
+    add_data(data.as_ptr(), data.len());
+}
+
+unsafe fn load(tag: &'static str, data: *const u8, length: usize) -> u32 {
+    let data = slice::from_raw_parts(data, length).to_vec();
+    LOADED.lock().unwrap().push((tag, data, save::loading_chunk()));
+    1
+}
+
+unsafe extern "C" fn load_versioned(data: *const u8, length: usize) -> u32 {
+    load("versioned", data, length)
+}
+
+unsafe extern "C" fn load_plain(data: *const u8, length: usize) -> u32 {
+    load("plain", data, length)
+}
+
+unsafe extern "C" fn load_checked(data: *const u8, length: usize) -> u32 {
+    LOADED_CHECKED.lock().unwrap().push(slice::from_raw_parts(data, length).to_vec());
+    1
+}
+
+unsafe extern "C" fn nop_init() {
+}
+
+/// File and warnings written to it
+pub struct TestFile(Cursor<Vec<u8>>, Vec<String>);
+
+impl io::Read for TestFile {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.0.read(out)
+    }
+}
+
+impl io::Write for TestFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl io::Seek for TestFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl save::File for &mut TestFile {
+    fn warn(&mut self, msg: &str) {
+        self.1.push(msg.into());
+    }
+}
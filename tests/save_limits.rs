@@ -0,0 +1,207 @@
+extern crate byteorder;
+extern crate samase_plugin;
+
+use std::fs;
+use std::io::{self, Cursor};
+use std::slice;
+use std::sync::Mutex;
+
+use byteorder::{ByteOrder, LE};
+
+use samase_plugin::save::{self, Limits};
+use samase_plugin::save_file::{self, SAVE_CONTINUATION_MAGIC, SAVE_MAGIC};
+
+static SAVE_DATA: Mutex<Vec<(&'static str, Vec<u8>)>> = Mutex::new(Vec::new());
+static LOADED: Mutex<Vec<(&'static str, Vec<u8>)>> = Mutex::new(Vec::new());
+
+/// Data that doesn't compress much
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x1234_5678u32;
+    (0..len).map(|_| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        (state >> 16) as u8
+    }).collect()
+}
+
+fn set_save_data(a: Vec<u8>, b: Vec<u8>) {
+    *SAVE_DATA.lock().unwrap() = vec![("a", a), ("b", b)];
+}
+
+/// Returns magics of the extension blocks written after `orig_len`.
+fn block_magics(data: &[u8], orig_len: usize) -> Vec<u32> {
+    let mut pos = orig_len;
+    let mut result = Vec::new();
+    while pos < data.len() {
+        result.push(LE::read_u32(&data[pos..]));
+        pos += 8 + LE::read_u32(&data[pos + 4..]) as usize;
+    }
+    assert_eq!(pos, data.len());
+    result
+}
+
+fn save_and_load(path: &str) -> (Vec<u32>, Vec<(&'static str, Vec<u8>)>) {
+    let data = fs::read(path).unwrap();
+    let orig_len = data.len();
+    let mut save_file = TestFile(Cursor::new(data), Vec::new());
+    save::call_save_hooks(&mut save_file).unwrap();
+    save::call_load_hooks(&mut save_file).unwrap();
+    let magics = block_magics(save_file.0.get_ref(), orig_len);
+    (magics, std::mem::take(&mut *LOADED.lock().unwrap()))
+}
+
+#[test]
+fn save_limits() {
+    save::add_hook("a".into(), Some(save_a), Some(load_a), nop_init);
+    save::add_hook("b".into(), Some(save_b), Some(load_b), nop_init);
+    let a = noise(1000);
+    let b = noise(300);
+    set_save_data(a.clone(), b.clone());
+
+    let (magics, loaded) = save_and_load("tests/save.snx");
+    assert_eq!(magics, vec![SAVE_MAGIC]);
+    assert_eq!(loaded, vec![("a", a.clone()), ("b", b.clone())]);
+
+    save::set_limits(Limits {
+        block_size: 0x100,
+        ..Limits::default()
+    });
+    for path in &["tests/save.snx", "tests/idk.snx"] {
+        let (magics, loaded) = save_and_load(path);
+        assert!(magics.len() > 4);
+        assert_eq!(magics[0], SAVE_MAGIC);
+        assert!(magics[1..].iter().all(|&x| x == SAVE_CONTINUATION_MAGIC));
+        assert_eq!(loaded, vec![("a", a.clone()), ("b", b.clone())]);
+    }
+
+    // Too large chunks are skipped with a warning
+    save::set_limits(Limits {
+        max_chunk_size: 500,
+        ..Limits::default()
+    });
+    let mut save_file = TestFile(Cursor::new(fs::read("tests/save.snx").unwrap()), Vec::new());
+    save::call_save_hooks(&mut save_file).unwrap();
+    assert_eq!(save_file.1.len(), 1);
+    assert!(save_file.1[0].contains("extension a"));
+    save::set_limits(Limits::default());
+    save::call_load_hooks(&mut save_file).unwrap();
+    assert_eq!(*LOADED.lock().unwrap(), vec![("b", b.clone())]);
+    LOADED.lock().unwrap().clear();
+
+    // Loading with smaller limits than what was saved with
+    let mut save_file = TestFile(Cursor::new(fs::read("tests/save.snx").unwrap()), Vec::new());
+    save::call_save_hooks(&mut save_file).unwrap();
+    save::set_limits(Limits {
+        max_chunk_size: 500,
+        ..Limits::default()
+    });
+    match save::call_load_hooks(&mut save_file) {
+        Err(save::Error::SaveFile(save_file::Error::LimitExceeded(what, 1000, 500))) => {
+            assert_eq!(what, "a");
+        }
+        x => panic!("Unexpected result {:?}", x),
+    }
+    save::set_limits(Limits {
+        max_total_size: 1000,
+        ..Limits::default()
+    });
+    match save::call_load_hooks(&mut save_file) {
+        Err(save::Error::SaveFile(save_file::Error::LimitExceeded(..))) => (),
+        x => panic!("Unexpected result {:?}", x),
+    }
+
+    // Too large total is an error, and the data written so far is not an extension
+    let data = fs::read("tests/save.snx").unwrap();
+    let orig_len = data.len();
+    let mut save_file = TestFile(Cursor::new(data), Vec::new());
+    match save::call_save_hooks(&mut save_file) {
+        Err(save::Error::SaveFile(save_file::Error::LimitExceeded(..))) => (),
+        x => panic!("Unexpected result {:?}", x),
+    }
+    assert_eq!(block_magics(save_file.0.get_ref(), orig_len), vec![0]);
+    assert_eq!(save_file.1.len(), 1);
+    save::set_limits(Limits::default());
+    assert!(save::call_load_hooks(&mut save_file).is_err());
+    assert!(LOADED.lock().unwrap().is_empty());
+
+    // Data added in small pieces over block boundaries
+    save::set_limits(Limits {
+        block_size: 0x40,
+        ..Limits::default()
+    });
+    *SAVE_DATA.lock().unwrap() = vec![("pieces", noise(3000))];
+    save::add_hook("pieces".into(), Some(save_pieces), Some(load_pieces), nop_init);
+    let (magics, loaded) = save_and_load("tests/save.snx");
+    assert!(magics.len() > 10);
+    assert_eq!(loaded, vec![("pieces", noise(3000))]);
+}
+
+unsafe fn save(tag: &str, add_data: unsafe extern "C" fn(*const u8, usize)) {
+    let data = SAVE_DATA.lock().unwrap();
+    if let Some(data) = data.iter().find(|x| x.0 == tag) {
+        add_data(data.1.as_ptr(), data.1.len());
+    }
+}
+
+unsafe extern "C" fn save_a(add_data: unsafe extern "C" fn(*const u8, usize)) {
+    save("a", add_data);
+}
+
+unsafe extern "C" fn save_b(add_data: unsafe extern "C" fn(*const u8, usize)) {
+    save("b", add_data);
+}
+
+unsafe extern "C" fn save_pieces(add_data: unsafe extern "C" fn(*const u8, usize)) {
+    let data = SAVE_DATA.lock().unwrap();
+    for piece in data[0].1.chunks(7) {
+        add_data(piece.as_ptr(), piece.len());
+    }
+}
+
+unsafe extern "C" fn load_pieces(data: *const u8, length: usize) -> u32 {
+    LOADED.lock().unwrap().push(("pieces", slice::from_raw_parts(data, length).to_vec()));
+    1
+}
+
+unsafe extern "C" fn load_a(data: *const u8, length: usize) -> u32 {
+    LOADED.lock().unwrap().push(("a", slice::from_raw_parts(data, length).to_vec()));
+    1
+}
+
+unsafe extern "C" fn load_b(data: *const u8, length: usize) -> u32 {
+    LOADED.lock().unwrap().push(("b", slice::from_raw_parts(data, length).to_vec()));
+    1
+}
+
+unsafe extern "C" fn nop_init() {
+}
+
+/// File and warnings written to it
+pub struct TestFile(Cursor<Vec<u8>>, Vec<String>);
+
+impl io::Read for TestFile {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.0.read(out)
+    }
+}
+
+impl io::Write for TestFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl io::Seek for TestFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl save::File for &mut TestFile {
+    fn warn(&mut self, msg: &str) {
+        self.1.push(msg.into());
+    }
+}
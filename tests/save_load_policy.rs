@@ -0,0 +1,157 @@
+extern crate samase_plugin;
+
+use std::fs;
+use std::io::{self, Cursor};
+use std::slice;
+use std::sync::Mutex;
+
+use samase_plugin::save::{self, LoadFailure, LoadPolicy, Requirement};
+
+static LOADED: Mutex<Vec<(&'static str, Vec<u8>)>> = Mutex::new(Vec::new());
+static INITS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+fn saved_file() -> TestFile {
+    let mut file = TestFile(Cursor::new(fs::read("tests/save.snx").unwrap()), Vec::new());
+    save::call_save_hooks(&mut file).unwrap();
+    file
+}
+
+fn take<T>(vec: &Mutex<Vec<T>>) -> Vec<T> {
+    std::mem::take(&mut *vec.lock().unwrap())
+}
+
+fn report(failure: &LoadFailure) -> Option<String> {
+    Some(format!("{:?} {}", failure.tag, failure.fatal))
+}
+
+#[test]
+fn load_policy() {
+    save::add_hook("good".into(), Some(save_good), Some(load_good), init_good);
+    save::add_hook("bad".into(), Some(save_bad), Some(load_bad), init_bad);
+    save::add_hook("bad".into(), None, Some(load_bad_second), init_bad_second);
+    save::add_hook("last".into(), Some(save_last), Some(load_last), init_last);
+
+    // Required by default
+    let mut file = saved_file();
+    match save::call_load_hooks(&mut file) {
+        Err(save::Error::HookFail(tag)) => assert_eq!(tag, "bad"),
+        x => panic!("Unexpected result {:?}", x),
+    }
+    assert_eq!(file.1.len(), 1);
+    assert!(file.1[0].contains("bad"));
+    assert_eq!(take(&LOADED), vec![("good", vec![1]), ("bad", vec![2])]);
+    assert!(take(&INITS).is_empty());
+
+    // Optional tag gets reset and the rest is loaded
+    save::set_requirement("bad", Requirement::Optional);
+    let mut file = saved_file();
+    save::call_load_hooks(&mut file).unwrap();
+    assert_eq!(file.1.len(), 1);
+    assert_eq!(take(&LOADED), vec![("good", vec![1]), ("bad", vec![2]), ("last", vec![3])]);
+    assert_eq!(take(&INITS), vec!["bad", "bad_second"]);
+
+    // Custom reports, required tag overriding the default
+    save::set_load_policy(LoadPolicy {
+        default: Requirement::Optional,
+        tags: vec![("bad".into(), Requirement::Required)],
+        report,
+    });
+    assert_eq!(save::load_policy().requirement("good"), Requirement::Optional);
+    let mut file = saved_file();
+    assert!(save::call_load_hooks(&mut file).is_err());
+    assert_eq!(file.1, vec!["Some(\"bad\") true"]);
+    take(&LOADED);
+
+    save::set_load_policy(LoadPolicy {
+        report: |_| None,
+        ..LoadPolicy::default()
+    });
+    save::set_requirement("bad", Requirement::Optional);
+    let mut file = saved_file();
+    save::call_load_hooks(&mut file).unwrap();
+    assert!(file.1.is_empty());
+    assert_eq!(take(&INITS), vec!["bad", "bad_second"]);
+}
+
+unsafe extern "C" fn save_good(add_data: unsafe extern "C" fn(*const u8, usize)) {
+    add_data([1].as_ptr(), 1);
+}
+
+unsafe extern "C" fn save_bad(add_data: unsafe extern "C" fn(*const u8, usize)) {
+    add_data([2].as_ptr(), 1);
+}
+
+unsafe extern "C" fn save_last(add_data: unsafe extern "C" fn(*const u8, usize)) {
+    add_data([3].as_ptr(), 1);
+}
+
+unsafe fn load(tag: &'static str, data: *const u8, length: usize) {
+    LOADED.lock().unwrap().push((tag, slice::from_raw_parts(data, length).to_vec()));
+}
+
+unsafe extern "C" fn load_good(data: *const u8, length: usize) -> u32 {
+    load("good", data, length);
+    1
+}
+
+unsafe extern "C" fn load_bad(data: *const u8, length: usize) -> u32 {
+    load("bad", data, length);
+    0
+}
+
+unsafe extern "C" fn load_bad_second(data: *const u8, length: usize) -> u32 {
+    load("bad_second", data, length);
+    1
+}
+
+unsafe extern "C" fn load_last(data: *const u8, length: usize) -> u32 {
+    load("last", data, length);
+    1
+}
+
+unsafe extern "C" fn init_good() {
+    INITS.lock().unwrap().push("good");
+}
+
+unsafe extern "C" fn init_bad() {
+    INITS.lock().unwrap().push("bad");
+}
+
+unsafe extern "C" fn init_bad_second() {
+    INITS.lock().unwrap().push("bad_second");
+}
+
+unsafe extern "C" fn init_last() {
+    INITS.lock().unwrap().push("last");
+}
+
+/// File and warnings written to it
+pub struct TestFile(Cursor<Vec<u8>>, Vec<String>);
+
+impl io::Read for TestFile {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.0.read(out)
+    }
+}
+
+impl io::Write for TestFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl io::Seek for TestFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl save::File for &mut TestFile {
+    fn warn(&mut self, msg: &str) {
+        self.1.push(msg.into());
+    }
+}
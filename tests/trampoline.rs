@@ -0,0 +1,71 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use samase_plugin::mock_host::MockHost;
+use samase_plugin::trampoline::{self, TrampolineError, SLOTS};
+use samase_plugin::SamaseApi;
+
+static ORIG_UNIT: AtomicUsize = AtomicUsize::new(0);
+
+unsafe extern "C" fn orig(unit: *mut c_void) {
+    ORIG_UNIT.store(unit as usize, Ordering::Relaxed);
+}
+
+#[test]
+fn captured_state() {
+    let host = MockHost::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let first = {
+        let calls = calls.clone();
+        trampoline::unit_hook(move |unit, orig| unsafe {
+            calls.fetch_add(1, Ordering::Relaxed);
+            orig((unit as usize + 1) as *mut c_void);
+        }).unwrap()
+    };
+    let second = {
+        let calls = calls.clone();
+        trampoline::unit_hook(move |unit, orig| unsafe {
+            calls.fetch_add(10, Ordering::Relaxed);
+            orig(unit);
+        }).unwrap()
+    };
+    assert_ne!(first.func() as usize, second.func() as usize);
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        api.hook_step_order(first.func()).unwrap();
+        api.hook_step_order(second.func()).unwrap();
+        host.fire_unit_hooks("hook_step_order", 0x50 as *mut c_void, orig);
+        first.free();
+        second.free();
+    }
+    assert_eq!(calls.load(Ordering::Relaxed), 11);
+    assert_eq!(ORIG_UNIT.load(Ordering::Relaxed), 0x51);
+}
+
+#[test]
+fn all_slots() {
+    // Other tests don't use `load_hook`, so all slots are free.
+    let trampolines: Vec<_> = (0..SLOTS)
+        .map(|i| trampoline::load_hook(move |_, len| (i + len) as u32).unwrap())
+        .collect();
+    let result = trampoline::load_hook(|_, _| 0);
+    assert_eq!(result.err(), Some(TrampolineError::Full("load_hook")));
+    for (i, tramp) in trampolines.iter().enumerate() {
+        assert_eq!(unsafe { tramp.func()(std::ptr::null(), 1000) }, i as u32 + 1000);
+    }
+    let mut trampolines = trampolines.into_iter();
+    unsafe {
+        trampolines.next().unwrap().free();
+    }
+    let new = trampoline::load_hook(|_, _| 7).unwrap();
+    assert_eq!(unsafe { new.func()(std::ptr::null(), 0) }, 7);
+    unsafe {
+        new.free();
+        for tramp in trampolines {
+            tramp.free();
+        }
+    }
+}
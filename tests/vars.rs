@@ -0,0 +1,64 @@
+extern crate samase_plugin;
+
+use std::ffi::c_void;
+
+use samase_plugin::mock_host::MockHost;
+use samase_plugin::vars::{VarAccess, VarError, VarKind, VarSet, VarValue, Vars};
+use samase_plugin::{SamaseApi, VarId};
+
+#[test]
+fn var_kinds() {
+    // Read as their size by samase_shim
+    assert_eq!(VarId::IsReplay.kind(), VarKind::U32);
+    assert_eq!(VarId::IsMultiplayer.kind(), VarKind::U8);
+    assert_eq!(VarId::ScMainState.kind(), VarKind::U32);
+    // Address of the variable vs. pointer stored in it
+    assert_eq!(VarId::ReplayData.kind(), VarKind::Address);
+    assert_eq!(VarId::MapTileFlags.kind(), VarKind::Pointer);
+    // SC:R only
+    assert_eq!(VarId::Zoom.kind(), VarKind::Raw);
+    assert_eq!(VarValue::from_raw(VarKind::Raw, usize::MAX).to_raw(), usize::MAX);
+}
+
+#[test]
+fn var_set() {
+    let host = MockHost::new();
+    host.set_var(VarId::Game, 0x1234, false);
+    host.set_var(VarId::RngSeed, 5, true);
+    host.set_var(VarId::IsReplay, 1, false);
+    unsafe {
+        let api = SamaseApi::new(host.api());
+        let vars = Vars::new(&api);
+        assert_eq!(vars.access(VarId::Units), VarAccess::NotFound);
+        assert_eq!(vars.read(VarId::IsReplay), Ok(VarValue::U32(1)));
+        assert_eq!(vars.read(VarId::Units), Err(VarError::NotReadable(VarId::Units)));
+        assert_eq!(
+            vars.write(VarId::Game, VarValue::Address(0x10 as *mut c_void)),
+            Err(VarError::NotWritable(VarId::Game)),
+        );
+
+        let ids = [VarId::Game, VarId::RngSeed, VarId::Units];
+        let mut set = VarSet::load(&api, &ids).unwrap();
+        assert_eq!(set.access(VarId::RngSeed), VarAccess::ReadWrite);
+        assert_eq!(set.get(VarId::Game), Some(VarValue::Address(std::ptr::null_mut())));
+        set.refresh().unwrap();
+        assert_eq!(set.get(VarId::Game), Some(VarValue::Address(0x1234 as *mut c_void)));
+        assert_eq!(set.get(VarId::RngSeed), Some(VarValue::U32(5)));
+        assert_eq!(set.get(VarId::Units), None);
+        assert_eq!(set.get(VarId::IsReplay), None);
+        assert_eq!(
+            set.set(VarId::RngSeed, VarValue::U8(1)),
+            Err(VarError::WrongKind(VarId::RngSeed)),
+        );
+        set.set(VarId::RngSeed, VarValue::U32(9)).unwrap();
+        if cfg!(debug_assertions) {
+            assert_eq!(
+                set.set(VarId::Game, VarValue::Address(0x10 as *mut c_void)),
+                Err(VarError::NotWritable(VarId::Game)),
+            );
+        }
+        set.flush().unwrap();
+    }
+    assert_eq!(host.var(VarId::RngSeed), Some(9));
+    assert_eq!(host.var(VarId::Game), Some(0x1234));
+}